@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Benchmarks rendering synthetic chats of a few sizes.
+//!
+//! Run with `cargo bench --bench render`. Each chat repeats one exchange
+//! with headings, fenced code, tags, references, edits, and context, so
+//! the time per exchange can be compared across sizes.
+
+use cp2md::parser::{
+    ChatExport, ContextItem, EditKind, Message, Request, ResponseElement, Severity,
+};
+use cp2md::renderer::{RenderOptions, render_chat};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+/// Chat sizes, by number of exchanges.
+const SIZES: &[(&str, usize)] = &[("small", 5), ("medium", 50), ("large", 500)];
+
+const QUESTION: &str = "# Parsing flags\n\
+    How do I parse `--limit <N>` with lexopt? Here is what I have:\n\
+    \n\
+    ```rust\n\
+    # not a heading\n\
+    fn main() {\n    let mut parser = lexopt::Parser::from_env();\n}\n\
+    ```\n\
+    \n\
+    It fails with <Error> when the value is missing.\n";
+
+const ANSWER: &str = "## Reading a value\n\
+    \n\
+    Call `parser.value()` after matching the flag, then parse it:\n\
+    \n\
+    ```rust\n\
+    Long(\"limit\") => limit = Some(parser.value()?.parse()?),\n\
+    ```\n\
+    \n\
+    ### Why it failed\n\
+    \n\
+    A missing value returns an error, which `<main>` then printed. \
+    Wrap it with context so the message names the flag, and keep the \
+    default when the flag is not given at all.\n";
+
+fn request(index: usize) -> Request {
+    let path = format!("/work/project/src/module_{index}.rs");
+    Request {
+        timestamp: 1_735_000_000_000 + i64::try_from(index).unwrap() * 60_000,
+        model_id: Some("copilot/gpt-4o".to_owned()),
+        agent_name: None,
+        context: vec![
+            ContextItem::File {
+                name: format!("module_{index}.rs"),
+                path: path.clone(),
+            },
+            ContextItem::Selection {
+                name: "main.rs".to_owned(),
+                path: "/work/project/src/main.rs".to_owned(),
+                start_line: 1,
+                end_line: 3,
+                text: Some("fn main() {\n    run();\n}".to_owned()),
+            },
+        ],
+        message: Message {
+            text: QUESTION.to_owned(),
+        },
+        response: vec![
+            ResponseElement::Text(ANSWER.to_owned()),
+            ResponseElement::InlineReference {
+                name: None,
+                path: path.clone(),
+            },
+            ResponseElement::Text(" has the parser.\n\n".to_owned()),
+            ResponseElement::ToolInvocation {
+                past_tense: Some(format!("Read module_{index}.rs")),
+            },
+            ResponseElement::TextEditGroup {
+                path,
+                edits: vec!["let limit = parser.value()?.parse()?;".to_owned()],
+                kind: EditKind::Modify,
+            },
+            ResponseElement::Annotation {
+                severity: Severity::Warning,
+                message: "Similar code found with 1 license type".to_owned(),
+            },
+        ],
+        hidden: false,
+        usage: None,
+    }
+}
+
+fn chat(exchanges: usize) -> ChatExport {
+    ChatExport {
+        responder_username: "GitHub Copilot".to_owned(),
+        requester_username: Some("octocat".to_owned()),
+        custom_title: Some("Parsing flags".to_owned()),
+        default_model: None,
+        requests: (0..exchanges).map(request).collect(),
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let options = [
+        ("default", RenderOptions::default()),
+        ("all", RenderOptions::all_metadata()),
+    ];
+    let mut group = c.benchmark_group("render_chat");
+    for &(size, exchanges) in SIZES {
+        let chat = chat(exchanges);
+        group.throughput(Throughput::Elements(exchanges as u64));
+        for (name, opts) in &options {
+            group.bench_with_input(BenchmarkId::new(*name, size), &chat, |b, chat| {
+                b.iter(|| render_chat(black_box(chat), opts));
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
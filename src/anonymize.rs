@@ -0,0 +1,614 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Anonymization of parsed chat exports.
+//!
+//! This module rewrites a [`ChatExport`] so it can be shared without leaking
+//! the user's identity or filesystem layout. It replaces:
+//!
+//! - The requester username with `User`
+//! - Every distinct file or folder path with a stable placeholder
+//!   (`/project/file-1.rs`, `/project/folder-1`)
+//! - The detected home directory with `~` and the OS username with `user`
+//!
+//! The mapping is built once per chat, so the same real path always maps to
+//! the same placeholder and cross-references in the rendered document still
+//! line up. The returned [`AnonymizeMap`] records the placeholders so the
+//! output can be de-anonymized privately later.
+//!
+//! # Example
+//!
+//! ```
+//! use cp2md::anonymize::anonymize;
+//! use cp2md::parser::parse_chat;
+//!
+//! let chat = parse_chat(r#"{
+//!     "requesterUsername": "octocat",
+//!     "responderUsername": "GitHub Copilot",
+//!     "requests": [{
+//!         "message": { "text": "octocat here, please fix /home/octocat/app/main.rs" },
+//!         "response": []
+//!     }]
+//! }"#).unwrap();
+//!
+//! let (anonymized, map) = anonymize(&chat);
+//! assert_eq!(
+//!     anonymized.requests[0].message.text,
+//!     "User here, please fix ~/app/main.rs"
+//! );
+//! assert_eq!(map.requester.as_deref(), Some("octocat"));
+//! ```
+
+use crate::parser::{ChatExport, ContextItem, ResponseElement};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Replacement for the requester username.
+const ANONYMOUS_REQUESTER: &str = "User";
+
+/// Replacement for the OS username inside paths.
+const ANONYMOUS_OS_USER: &str = "user";
+
+/// Directory that placeholder paths are rooted in.
+const PLACEHOLDER_ROOT: &str = "/project";
+
+/// The substitutions made while anonymizing a chat.
+///
+/// Serializes to JSON for use as a private de-anonymization sidecar.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AnonymizeMap {
+    /// The real requester username that was replaced with `User`.
+    pub requester: Option<String>,
+
+    /// The detected home directory that was replaced with `~`.
+    pub home_dir: Option<String>,
+
+    /// The detected OS username that was replaced with `user`.
+    pub os_user: Option<String>,
+
+    /// Placeholder paths mapped to the real paths they replace.
+    pub paths: BTreeMap<String, String>,
+}
+
+/// Returns an anonymized copy of `chat` along with the substitutions made.
+///
+/// Paths are assigned placeholders in order of first appearance, so
+/// anonymizing the same chat twice produces identical output.
+#[must_use]
+pub fn anonymize(chat: &ChatExport) -> (ChatExport, AnonymizeMap) {
+    let anonymizer = Anonymizer::for_chat(chat);
+    let mut out = chat.clone();
+
+    if out.requester_username.is_some() {
+        out.requester_username = Some(ANONYMOUS_REQUESTER.to_owned());
+    }
+    if let Some(title) = out.custom_title.as_mut() {
+        *title = anonymizer.scrub_text(title);
+    }
+
+    for request in &mut out.requests {
+        request.message.text = anonymizer.scrub_text(&request.message.text);
+
+        for item in &mut request.context {
+            if let ContextItem::Selection {
+                text: Some(text), ..
+            }
+            | ContextItem::PastedText { text }
+            | ContextItem::TerminalOutput { text } = item
+            {
+                *text = anonymizer.scrub_text(text);
+            }
+            match item {
+                ContextItem::File { name, path }
+                | ContextItem::Selection { name, path, .. }
+                | ContextItem::Folder { name, path } => {
+                    if let Some(placeholder) = anonymizer.placeholder(path) {
+                        *name = placeholder_name(name, placeholder);
+                        placeholder.clone_into(path);
+                    }
+                }
+                ContextItem::Instructions { .. }
+                | ContextItem::PastedText { .. }
+                | ContextItem::TerminalOutput { .. } => {}
+            }
+        }
+
+        for elem in &mut request.response {
+            match elem {
+                ResponseElement::Text(text) => *text = anonymizer.scrub_text(text),
+                ResponseElement::InlineReference { name, path } => {
+                    if let Some(placeholder) = anonymizer.placeholder(path) {
+                        if let Some(name) = name.as_mut()
+                            && Path::new(path)
+                                .file_name()
+                                .is_some_and(|f| f == name.as_str())
+                        {
+                            *name = placeholder_name(name, placeholder);
+                        }
+                        placeholder.clone_into(path);
+                    }
+                }
+                ResponseElement::CodeBlockUri { path }
+                | ResponseElement::TextEditGroup { path, .. } => {
+                    if let Some(placeholder) = anonymizer.placeholder(path) {
+                        placeholder.clone_into(path);
+                    }
+                }
+                ResponseElement::ToolInvocation { past_tense } => {
+                    if let Some(msg) = past_tense.as_mut() {
+                        *msg = anonymizer.scrub_text(msg);
+                    }
+                }
+                ResponseElement::Annotation { message, .. } => {
+                    *message = anonymizer.scrub_text(message);
+                }
+                // Raw JSON can hold any path or name, so it is not kept
+                ResponseElement::Other { raw } => *raw = None,
+            }
+        }
+    }
+
+    (out, anonymizer.into_map())
+}
+
+/// Returns the display name for a placeholder path.
+///
+/// Folder names conventionally end in `/`, which is preserved.
+fn placeholder_name(original: &str, placeholder: &str) -> String {
+    let file_name = placeholder.rsplit('/').next().unwrap_or(placeholder);
+    if original.ends_with('/') {
+        format!("{file_name}/")
+    } else {
+        file_name.to_owned()
+    }
+}
+
+/// The per-chat substitution tables.
+struct Anonymizer {
+    requester: Option<String>,
+    home: Option<(String, String)>,
+    placeholders: HashMap<String, String>,
+    /// Real paths sorted longest first, so text replacement never rewrites
+    /// a prefix of a longer known path.
+    by_length: Vec<String>,
+}
+
+impl Anonymizer {
+    /// Builds the substitution tables for every path that appears in `chat`.
+    fn for_chat(chat: &ChatExport) -> Self {
+        let mut placeholders = HashMap::new();
+        let mut files = 0;
+        let mut folders = 0;
+        let mut home = None;
+
+        let mut assign = |path: &str, is_folder: bool| {
+            if path.is_empty() || placeholders.contains_key(path) {
+                return;
+            }
+            if home.is_none() {
+                home = detect_home(path);
+            }
+            let placeholder = if is_folder {
+                folders += 1;
+                format!("{PLACEHOLDER_ROOT}/folder-{folders}")
+            } else {
+                files += 1;
+                let ext = Path::new(path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|ext| format!(".{ext}"))
+                    .unwrap_or_default();
+                format!("{PLACEHOLDER_ROOT}/file-{files}{ext}")
+            };
+            placeholders.insert(path.to_owned(), placeholder);
+        };
+
+        for request in &chat.requests {
+            for item in &request.context {
+                match item {
+                    ContextItem::File { path, .. } | ContextItem::Selection { path, .. } => {
+                        assign(path, false);
+                    }
+                    ContextItem::Folder { path, .. } => assign(path, true),
+                    ContextItem::Instructions { .. }
+                    | ContextItem::PastedText { .. }
+                    | ContextItem::TerminalOutput { .. } => {}
+                }
+            }
+            for elem in &request.response {
+                match elem {
+                    ResponseElement::InlineReference { path, .. }
+                    | ResponseElement::CodeBlockUri { path }
+                    | ResponseElement::TextEditGroup { path, .. } => assign(path, false),
+                    _ => {}
+                }
+            }
+        }
+
+        // Fall back to paths mentioned only in prose
+        if home.is_none() {
+            home = chat
+                .requests
+                .iter()
+                .flat_map(|r| {
+                    r.message
+                        .text
+                        .split(|c: char| c.is_whitespace() || "`'\"(),".contains(c))
+                })
+                .find_map(detect_home);
+        }
+
+        let mut by_length: Vec<String> = placeholders.keys().cloned().collect();
+        by_length.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+        Self {
+            requester: chat.requester_username.clone().filter(|u| !u.is_empty()),
+            home,
+            placeholders,
+            by_length,
+        }
+    }
+
+    /// Returns the placeholder for a known real path.
+    fn placeholder(&self, path: &str) -> Option<&String> {
+        self.placeholders.get(path)
+    }
+
+    /// Replaces every known path, the home directory, and usernames in `text`.
+    fn scrub_text(&self, text: &str) -> String {
+        let mut text = text.to_owned();
+
+        for real in &self.by_length {
+            if text.contains(real.as_str()) {
+                text = replace_path(&text, real, &self.placeholders[real]);
+            }
+        }
+
+        if let Some((home_dir, os_user)) = &self.home {
+            text = replace_path(&text, home_dir, "~");
+            text = text.replace(&format!("/{os_user}/"), &format!("/{ANONYMOUS_OS_USER}/"));
+        }
+
+        if let Some(requester) = &self.requester {
+            text = replace_word(&text, requester, ANONYMOUS_REQUESTER);
+        }
+
+        text
+    }
+
+    fn into_map(self) -> AnonymizeMap {
+        let (home_dir, os_user) = self.home.unzip();
+        AnonymizeMap {
+            requester: self.requester,
+            home_dir,
+            os_user,
+            paths: self
+                .placeholders
+                .into_iter()
+                .map(|(real, placeholder)| (placeholder, real))
+                .collect(),
+        }
+    }
+}
+
+/// Detects a home directory and OS username from an absolute path.
+///
+/// Recognizes `/home/<user>/…`, `/Users/<user>/…`, and the VS Code form of
+/// Windows paths, `/c:/Users/<user>/…`.
+fn detect_home(path: &str) -> Option<(String, String)> {
+    let rest_start = match path.as_bytes() {
+        [b'/', drive, b':', b'/', ..] if drive.is_ascii_alphabetic() => 3,
+        [drive, b':', b'/', ..] if drive.is_ascii_alphabetic() => 2,
+        _ => 0,
+    };
+    let rest = &path[rest_start..];
+
+    let prefix_len = ["/home/", "/Users/", "/users/"]
+        .iter()
+        .find(|prefix| rest.starts_with(*prefix))?
+        .len();
+    let user_start = rest_start + prefix_len;
+    let user_end = path[user_start..]
+        .find('/')
+        .map_or(path.len(), |i| user_start + i);
+    let user = &path[user_start..user_end];
+
+    (!user.is_empty()).then(|| (path[..user_end].to_owned(), user.to_owned()))
+}
+
+/// Replaces whole-word occurrences of `word` in `text`.
+///
+/// A match only counts when it is not surrounded by alphanumerics, `_`, or
+/// `-`, so a username like `al` leaves `also` untouched.
+fn replace_word(text: &str, word: &str, replacement: &str) -> String {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (start, _) in text.match_indices(word) {
+        let end = start + word.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        if before.is_some_and(is_word_char) || after.is_some_and(is_word_char) {
+            continue;
+        }
+        result.push_str(&text[last..start]);
+        result.push_str(replacement);
+        last = end;
+    }
+
+    result.push_str(&text[last..]);
+    result
+}
+
+/// Replaces occurrences of `path` in `text` that end at a path component.
+///
+/// A match only counts when it ends in `/` or is followed by `/`, the end of
+/// the text, or a character that cannot continue a file name, so `/home/al`
+/// leaves `/home/alice` untouched. A `.` ends the path only when no file
+/// name character follows it, as at the end of a sentence.
+fn replace_path(text: &str, path: &str, replacement: &str) -> String {
+    let is_name_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (start, _) in text.match_indices(path) {
+        let end = start + path.len();
+        let mut after = text[end..].chars();
+        let continues = !path.ends_with('/')
+            && match after.next() {
+                Some('.') => after.next().is_some_and(|c| is_name_char(c) || c == '.'),
+                Some(c) => is_name_char(c),
+                None => false,
+            };
+        if continues {
+            continue;
+        }
+        result.push_str(&text[last..start]);
+        result.push_str(replacement);
+        last = end;
+    }
+
+    result.push_str(&text[last..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{EditKind, Message, Request, Severity};
+
+    fn make_request(
+        message: &str,
+        context: Vec<ContextItem>,
+        response: Vec<ResponseElement>,
+    ) -> Request {
+        Request {
+            timestamp: 1_733_356_800_000,
+            model_id: None,
+            agent_name: None,
+            context,
+            message: Message {
+                text: message.into(),
+            },
+            response,
+            hidden: false,
+            usage: None,
+        }
+    }
+
+    fn make_chat(requests: Vec<Request>) -> ChatExport {
+        ChatExport {
+            responder_username: "GitHub Copilot".into(),
+            requester_username: Some("alice".into()),
+            custom_title: None,
+            default_model: None,
+            requests,
+        }
+    }
+
+    #[test]
+    fn maps_same_path_to_same_placeholder_everywhere() {
+        let path = "/home/alice/work/app/src/main.rs";
+        let chat = make_chat(vec![
+            make_request(
+                "Look at /home/alice/work/app/src/main.rs",
+                vec![ContextItem::File {
+                    name: "main.rs".into(),
+                    path: path.into(),
+                }],
+                vec![ResponseElement::InlineReference {
+                    name: Some("main.rs".into()),
+                    path: path.into(),
+                }],
+            ),
+            make_request(
+                "Again",
+                vec![],
+                vec![ResponseElement::TextEditGroup {
+                    path: path.into(),
+                    edits: vec!["fn main() {}".into()],
+                    kind: EditKind::Modify,
+                }],
+            ),
+        ]);
+
+        let (out, map) = anonymize(&chat);
+
+        assert_eq!(out.requests[0].message.text, "Look at /project/file-1.rs");
+        assert_eq!(
+            out.requests[0].context[0],
+            ContextItem::File {
+                name: "file-1.rs".into(),
+                path: "/project/file-1.rs".into(),
+            }
+        );
+        assert_eq!(
+            out.requests[0].response[0],
+            ResponseElement::InlineReference {
+                name: Some("file-1.rs".into()),
+                path: "/project/file-1.rs".into(),
+            }
+        );
+        match &out.requests[1].response[0] {
+            ResponseElement::TextEditGroup { path, .. } => assert_eq!(path, "/project/file-1.rs"),
+            other => panic!("Expected TextEditGroup, got {other:?}"),
+        }
+        assert_eq!(map.paths.len(), 1);
+        assert_eq!(map.paths["/project/file-1.rs"], path);
+    }
+
+    #[test]
+    fn numbers_distinct_paths_in_order_of_appearance() {
+        let chat = make_chat(vec![make_request(
+            "Hi",
+            vec![
+                ContextItem::File {
+                    name: "b.rs".into(),
+                    path: "/repo/b.rs".into(),
+                },
+                ContextItem::Folder {
+                    name: "src/".into(),
+                    path: "/repo/src".into(),
+                },
+                ContextItem::Selection {
+                    name: "Makefile".into(),
+                    path: "/repo/Makefile".into(),
+                    start_line: 1,
+                    end_line: 3,
+                    text: None,
+                },
+            ],
+            vec![],
+        )]);
+
+        let (out, _) = anonymize(&chat);
+
+        assert_eq!(
+            out.requests[0].context,
+            vec![
+                ContextItem::File {
+                    name: "file-1.rs".into(),
+                    path: "/project/file-1.rs".into(),
+                },
+                ContextItem::Folder {
+                    name: "folder-1/".into(),
+                    path: "/project/folder-1".into(),
+                },
+                ContextItem::Selection {
+                    name: "file-2".into(),
+                    path: "/project/file-2".into(),
+                    start_line: 1,
+                    end_line: 3,
+                    text: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn scrubs_home_directory_and_usernames_in_text() {
+        let chat = make_chat(vec![make_request(
+            "alice: check /Users/alice/notes.txt, also /tmp/alice/x",
+            vec![ContextItem::File {
+                name: "main.rs".into(),
+                path: "/Users/alice/src/main.rs".into(),
+            }],
+            vec![
+                ResponseElement::ToolInvocation {
+                    past_tense: Some("Read /Users/alice/.zshrc".into()),
+                },
+                ResponseElement::Annotation {
+                    severity: Severity::Warning,
+                    message: "Secret in /Users/alice/.env".into(),
+                },
+            ],
+        )]);
+
+        let (out, map) = anonymize(&chat);
+
+        assert_eq!(
+            out.requests[0].message.text,
+            "User: check ~/notes.txt, also /tmp/user/x"
+        );
+        assert_eq!(
+            out.requests[0].response[0],
+            ResponseElement::ToolInvocation {
+                past_tense: Some("Read ~/.zshrc".into()),
+            }
+        );
+        assert_eq!(
+            out.requests[0].response[1],
+            ResponseElement::Annotation {
+                severity: Severity::Warning,
+                message: "Secret in ~/.env".into(),
+            }
+        );
+        assert_eq!(out.requester_username.as_deref(), Some("User"));
+        assert_eq!(map.home_dir.as_deref(), Some("/Users/alice"));
+        assert_eq!(map.os_user.as_deref(), Some("alice"));
+        assert_eq!(map.requester.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn leaves_paths_that_only_share_a_prefix() {
+        let path = "/home/al/app/main.rs";
+        let mut chat = make_chat(vec![make_request(
+            "Compare /home/al/app/main.rs.bak and /home/alice/app/main.rs with /home/al/app/main.rs.",
+            vec![ContextItem::File {
+                name: "main.rs".into(),
+                path: path.into(),
+            }],
+            vec![],
+        )]);
+        chat.requester_username = Some("al".into());
+
+        let (out, _) = anonymize(&chat);
+
+        let placeholder = format!("{PLACEHOLDER_ROOT}/file-1.rs");
+        assert_eq!(
+            out.requests[0].message.text,
+            format!("Compare ~/app/main.rs.bak and /home/alice/app/main.rs with {placeholder}.")
+        );
+    }
+
+    #[test]
+    fn detects_home_directories() {
+        assert_eq!(
+            detect_home("/home/bob/x.rs"),
+            Some(("/home/bob".into(), "bob".into()))
+        );
+        assert_eq!(
+            detect_home("/c:/Users/Bob/x.rs"),
+            Some(("/c:/Users/Bob".into(), "Bob".into()))
+        );
+        assert_eq!(detect_home("/srv/app/x.rs"), None);
+        assert_eq!(detect_home("/home/"), None);
+    }
+
+    #[test]
+    fn replaces_only_whole_words() {
+        assert_eq!(
+            replace_word("al also al-x al.", "al", "User"),
+            "User also al-x User."
+        );
+    }
+
+    #[test]
+    fn leaves_symbol_references_alone() {
+        let chat = make_chat(vec![make_request(
+            "Hi",
+            vec![],
+            vec![ResponseElement::InlineReference {
+                name: Some("Deserialize".into()),
+                path: String::new(),
+            }],
+        )]);
+
+        let (out, map) = anonymize(&chat);
+
+        assert_eq!(out.requests[0].response, chat.requests[0].response);
+        assert!(map.paths.is_empty());
+    }
+}
@@ -0,0 +1,1940 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Command-line argument parsing.
+
+use super::{
+    Cli, CollisionPolicy, ColorChoice, ConflictingModesSnafu, Error, IncompleteRepoSnafu,
+    InvalidCodeIndentSnafu, InvalidCollisionPolicySnafu, InvalidColorSnafu,
+    InvalidContextKindSnafu, InvalidContextPositionSnafu, InvalidDeepHeadingsSnafu,
+    InvalidEncodingFallbackSnafu, InvalidExtensionSnafu, InvalidFrontMatterFormatSnafu,
+    InvalidHeadingOffsetSnafu, InvalidLineEndingSnafu, InvalidLogFormatSnafu,
+    InvalidMessageLengthSnafu, InvalidMetadataModeSnafu, InvalidOutputEncodingSnafu,
+    InvalidPathDisplaySnafu, InvalidPathStyleSnafu, InvalidRedactPatternSnafu,
+    InvalidSelectionLinesSnafu, InvalidTimestampStyleSnafu, InvalidTimestampUnitSnafu,
+    InvalidToolLengthSnafu, InvalidWrapWidthSnafu, LogFormat, OutputEncoding, OutputTarget,
+    ParseArgsSnafu,
+};
+use cp2md::parser::TimestampUnit;
+use cp2md::renderer;
+use lexopt::prelude::*;
+use snafu::{ensure, prelude::*};
+use std::path::{Path, PathBuf};
+
+/// Help lines for the options that choose which files are read.
+const INPUT_OPTIONS: &str =
+    "      --ext <SUFFIX>        Read files ending in .SUFFIX found in input directories
+                            (repeatable, default: json and jsonl)
+      --scan-vscode         Treat inputs as VS Code user data directories and read their chats";
+
+/// Help lines for the options that say how inputs are parsed.
+const PARSE_OPTIONS: &str =
+    "      --pretty-errors       Show the line of JSON an error points at, with a caret
+      --lenient-json        Accept inputs with trailing commas in arrays and objects
+      --strict              Refuse inputs that are not UTF-8 instead of replacing bad bytes
+      --timestamp-unit <UNIT>
+                            Read request timestamps as auto, millis, or seconds (default: auto)";
+
+/// Help lines for the options that say how progress is reported.
+const LOG_OPTIONS: &str =
+    "      --color <WHEN>        Color progress messages: auto, always, or never (default: auto)
+      --log-format <FORMAT> Write progress messages as human or json lines (default: human)";
+
+/// Help lines for the options every command takes.
+const COMMON_OPTIONS: &str = "  -h, --help                Print help
+  -V, --version             Print version";
+
+/// Help lines for the exit statuses.
+const EXIT_STATUS: &str = "\
+Exit status:
+  0  Success
+  1  Invalid arguments or option combinations
+  2  Inputs could not be found or read
+  3  Inputs could not be parsed
+  4  Outputs could not be written, or were skipped under --strict-exit";
+
+/// A command, named by the first argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subcommand {
+    /// Convert inputs to Markdown, the default when no command is named.
+    Convert,
+    /// Check that inputs parse.
+    Validate,
+    /// Count what each chat holds.
+    Stats,
+    /// Compare two chats exchange by exchange.
+    Diff,
+    /// List the chats in VS Code user data directories.
+    Discover,
+}
+
+impl Subcommand {
+    /// Every command, in the order the overview lists them.
+    const ALL: [Self; 5] = [
+        Self::Convert,
+        Self::Validate,
+        Self::Stats,
+        Self::Diff,
+        Self::Discover,
+    ];
+
+    /// The command's name on the command line.
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Convert => "convert",
+            Self::Validate => "validate",
+            Self::Stats => "stats",
+            Self::Diff => "diff",
+            Self::Discover => "discover",
+        }
+    }
+
+    /// What the command does, in the overview.
+    const fn about(self) -> &'static str {
+        match self {
+            Self::Convert => "Convert inputs to Markdown (the default)",
+            Self::Validate => "Check that inputs parse, without converting them",
+            Self::Stats => "Count the exchanges, edited files, and tools in each chat",
+            Self::Diff => "Compare two chats exchange by exchange",
+            Self::Discover => "List the chats stored in VS Code user data directories",
+        }
+    }
+
+    /// The options the command takes besides `--help` and `--version`, by
+    /// long name, or `None` for every option.
+    const fn options(self) -> Option<&'static [&'static str]> {
+        const READ: &[&str] = &[
+            "ext",
+            "scan-vscode",
+            "pretty-errors",
+            "lenient-json",
+            "strict",
+            "timestamp-unit",
+            "color",
+            "log-format",
+            "quiet",
+        ];
+        match self {
+            Self::Convert => None,
+            Self::Validate | Self::Stats => Some(READ),
+            // The inputs are named, not searched for
+            Self::Diff | Self::Discover => Some(READ.split_at(2).1),
+        }
+    }
+
+    /// Returns whether the command takes `arg`.
+    fn takes(self, arg: &lexopt::Arg<'_>) -> bool {
+        let Some(options) = self.options() else {
+            return true;
+        };
+        let name = match *arg {
+            Long(name) => name,
+            Short('h') => "help",
+            Short('V') => "version",
+            Short('q') => "quiet",
+            Short(_) => return false,
+            Value(_) => return true,
+        };
+        matches!(name, "help" | "version") || options.contains(&name)
+    }
+
+    /// Returns the command's `--help` text.
+    pub fn help(self) -> String {
+        let (usage, about, options) = match self {
+            Self::Convert => return help(),
+            Self::Validate => (
+                "[OPTIONS] <INPUT>...",
+                "Check that inputs parse, printing OK or FAIL for each with any warnings",
+                format!(
+                    "{INPUT_OPTIONS}\n{PARSE_OPTIONS}\n{LOG_OPTIONS}\n  \
+                     -q, --quiet               Leave out the OK lines"
+                ),
+            ),
+            Self::Stats => (
+                "[OPTIONS] <INPUT>...",
+                "Print how many exchanges, edited files, and tool invocations each chat has,\n\
+                 which models it used, and the tokens it used when recorded, then the totals",
+                format!("{INPUT_OPTIONS}\n{PARSE_OPTIONS}\n{LOG_OPTIONS}\n{QUIET}"),
+            ),
+            Self::Diff => (
+                "[OPTIONS] <OLD> <NEW>",
+                "Compare two chats exchange by exchange, matching exchanges by their message,\n\
+                 and list those removed, added, or answered differently",
+                format!("{PARSE_OPTIONS}\n{LOG_OPTIONS}\n{QUIET}"),
+            ),
+            Self::Discover => (
+                "[OPTIONS] <DIR>...",
+                "List each chat session stored in VS Code user data directories, such as\n\
+                 ~/.config/Code, with its title and number of exchanges",
+                format!("{PARSE_OPTIONS}\n{LOG_OPTIONS}\n{QUIET}"),
+            ),
+        };
+        format!(
+            "\
+{name} {command}
+{about}
+
+Usage: {name} {command} {usage}
+
+Options:
+{options}
+{COMMON_OPTIONS}
+
+{EXIT_STATUS}",
+            name = env!("CARGO_PKG_NAME"),
+            command = self.name(),
+        )
+    }
+}
+
+/// Help line for `--quiet`.
+const QUIET: &str = "  -q, --quiet               Suppress progress messages";
+
+/// Returns the overview printed by `--help` without a command.
+pub fn overview() -> String {
+    let commands: Vec<_> = Subcommand::ALL
+        .iter()
+        .map(|command| format!("  {:<10}{}", command.name(), command.about()))
+        .collect();
+    format!(
+        "\
+{name} {version}
+Convert GitHub Copilot chat exports to Markdown
+
+Usage: {name} [COMMAND] [OPTIONS] <INPUT>...
+
+Commands:
+{commands}
+
+Run `{name} <COMMAND> --help` for a command's options. Without a command,
+the arguments are those of convert, as in `{name} chat.json -o out/`.
+
+Options:
+{COMMON_OPTIONS}
+
+{EXIT_STATUS}",
+        name = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        commands = commands.join("\n"),
+    )
+}
+
+/// Returns the `--help` text for converting.
+#[allow(clippy::too_many_lines)]
+pub fn help() -> String {
+    format!(
+        "\
+{name} {version}
+Convert GitHub Copilot chat exports to Markdown
+
+Usage: {name} [convert] [OPTIONS] [-o <OUTPUT>] <INPUT>...
+
+Arguments:
+  <INPUT>...  Input JSON files or directories containing exports
+
+Options:
+  -o, --output <OUTPUT>     Output directory (or file with --concat, or - for stdout);
+                            (default for a single input file: its directory)
+      --concat              Combine all inputs into a single output
+      --concat-toc          Like --concat, with a table of contents linking each chat
+      --concat-title <TEXT> Like --concat, under one TEXT heading with a heading per chat
+      --concat-per-dir      Combine the inputs from each directory into <DIR>.md
+      --separator <TEXT>    Put TEXT between concatenated chats, with \\n for newlines
+                            (default: ---)
+      --no-separator        Separate concatenated chats with only a blank line
+      --heading-offset <N>  Shift heading levels by N (0-5, default: 0)
+      --deep-headings <STYLE>
+                            Show headings past H6 as clamp or bold (default: clamp)
+      --edit-summary        Append a list of all edited files to each chat
+      --edits-section       List each response's edits under a sub-heading
+      --badges              Count tools, edited files, and references under each response
+      --show-usage          Show the tokens each request used, when the export records them
+      --dedupe-context      List each context item only the first time it appears
+      --hoist-instructions  Name instruction files once under the title, not in each context
+      --summary-only        Write only each chat's title, dates, models, and counts
+      --dedupe-metadata     Omit timestamps, models, and agents repeated from the previous request
+      --metadata <MODE>     Show metadata always, on-change, or first (default: always)
+      --metadata-alert      Write metadata as a GitHub [!NOTE] alert
+      --agent-speaker       Label assistant sections with the agent name
+      --nav                 Link output files with previous/next footers and an index.md
+      --line-ending <STYLE> Line endings to write: lf or crlf (default: lf)
+      --output-encoding <ENCODING>
+                            Encoding of written files: utf-8 or latin-1 (default: utf-8)
+      --encoding-fallback <TEXT>
+                            Written for characters the output encoding lacks (default: ?)
+      --path-style <STYLE>  Reference long paths as link or footnote (default: link)
+      --path-display <MODE> Show paths as auto[:N], full, short, or name (default: auto:30)
+      --embed-images        Show referenced image files as embedded images
+      --repo-url <URL>      Link repository-relative context paths to this GitHub repo
+      --ref <REF>           Commit, branch, or tag the --repo-url links point at
+      --embed-selections    Show each selection's lines, read from the file on disk
+      --selection-lines <N> Cut embedded selections after N lines (default: 20)
+      --frontmatter         Start each file with YAML front matter (not with --concat)
+      --frontmatter-format <FORMAT>
+                            Front matter format: yaml or toml (implies --frontmatter)
+      --wrap <COLUMN>       Hard-wrap prose at COLUMN (default: no wrapping)
+      --code-indent <MODE>  Indent fenced code with tabs[:N] or spaces[:N] (default: keep)
+      --quote-user          Render user messages as blockquotes
+      --reflow-paragraphs   Break run-on responses into paragraphs between sentences
+      --title-from-message  Title untitled chats after their first message
+      --no-title            Leave out the top-level heading and move the rest up a level
+                            (not with --concat; --no-doc-heading is an alias)
+      --preview <N>         Render only the first N exchanges of each chat
+      --max-tool-len <N>    Cut tool messages longer than N characters
+      --truncate <N>        Cut messages and response text longer than N characters
+      --hide-annotations    Hide vulnerability and content-filter warnings
+      --anonymize           Replace usernames and paths with placeholders
+      --anonymize-map <FILE>
+                            Write the placeholder mapping to FILE (implies --anonymize)
+      --only-agent <NAME>   Keep only requests handled by agent NAME (e.g. @workspace)
+      --redact <REGEX>      Replace text matching REGEX with [REDACTED] (repeatable)
+      --redact-common       Redact AWS access keys, GitHub tokens, and JWTs
+
+Metadata display (use --show-* or --hide-*):
+      --show-timestamps     Include timestamps (default: off)
+      --hide-timestamps     Hide timestamps
+      --timestamps <STYLE>  Timestamp style: absolute, iso, or relative (implies --show-timestamps)
+      --show-model          Include model ID (default: on)
+      --hide-model          Hide model ID
+      --friendly-models     Name known models, such as Claude Sonnet 4, instead of by ID
+      --show-agent          Include agent name (default: on)
+      --hide-agent          Hide agent name
+      --show-context        Include attached context (default: on)
+      --hide-context        Hide attached context
+      --context-only <KINDS>
+                            Show only these comma-separated context kinds: file,
+                            selection, folder, instructions, pasted, terminal
+      --context-exclude <KINDS>
+                            Hide these comma-separated context kinds
+      --context-position <POSITION>
+                            Put context before or after the message (default: before)
+      --context-index       Append an index of the context attached to each chat
+      --show-selection-text Include the text of selections in context (default: off)
+      --hide-selection-text Hide the text of selections
+      --show-tools          Include tool invocations (default: off)
+      --hide-tools          Hide tool invocations
+  -v, --verbose             Alias for --show-tools
+      --all                 Show all of the above except selection text
+      --minimal             Hide all of the above
+
+Other options:
+      --debug-unknown       Show unrecognized response elements as raw JSON
+      --source-comment      Note the source file, version, and options in the output
+{INPUT_OPTIONS}
+      --browse              Browse conversations interactively (requires the tui feature)
+      --validate            Check that inputs parse, without converting them
+      --detect              Print which export format features each input uses
+{PARSE_OPTIONS}
+      --dump-schema         Print a JSON Schema of the export format cp2md reads
+{LOG_OPTIONS}
+{QUIET}
+  -n, --dry-run             Show what would be processed without writing
+  -f, --force               Overwrite existing output files
+      --overwrite-if-changed
+                            Overwrite existing output files only if their content changes
+      --on-collision <POLICY>
+                            When inputs share an output name: error, rename, or
+                            overwrite (default: rename)
+      --strict-exit         Fail if any output is skipped because it already exists
+      --keep-empty          Convert chats with no requests instead of skipping them
+  -h, --help                Print help
+  -V, --version             Print version
+
+Exit status:
+  0  Success
+  1  Invalid arguments or option combinations
+  2  Inputs could not be found or read
+  3  Inputs could not be parsed
+  4  Outputs could not be written, or were skipped under --strict-exit",
+        name = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+    )
+}
+
+/// What the command line asks for.
+#[derive(Debug)]
+pub enum Command {
+    /// Convert, validate, browse, or otherwise read inputs, as the
+    /// [`Cli`] mode flags say.
+    Convert(Box<Cli>),
+    /// Print the help for a command, or the overview for none.
+    Help(Option<Subcommand>),
+    /// Print the version.
+    Version,
+    /// Print the export schema.
+    DumpSchema,
+}
+
+/// Parses command-line arguments, starting with the program name.
+///
+/// Nothing is printed and the process is never exited: asking for help,
+/// the version, or the schema is returned as a [`Command`] like anything
+/// else. With no arguments at all, help is returned.
+///
+/// A first argument naming a [`Subcommand`] selects it, and limits the
+/// options to its own; any other first argument is read as by `convert`.
+#[allow(clippy::too_many_lines)]
+pub fn parse_args(
+    args: impl IntoIterator<Item = impl Into<std::ffi::OsString>>,
+) -> Result<Command, Error> {
+    let mut args: Vec<std::ffi::OsString> = args.into_iter().map(Into::into).collect();
+    let named = args.get(1).and_then(|arg| {
+        Subcommand::ALL
+            .into_iter()
+            .find(|command| arg.as_os_str() == command.name())
+    });
+    if named.is_some() {
+        args.remove(1);
+    }
+    // Show help if no arguments provided
+    if args.len() <= 1 {
+        return Ok(Command::Help(named));
+    }
+    let subcommand = named.unwrap_or(Subcommand::Convert);
+
+    let mut input = Vec::new();
+    let mut output: Option<OutputTarget> = None;
+    let mut concat = false;
+    let mut concat_toc = false;
+    let mut concat_per_dir = false;
+    let mut concat_title = None;
+    let mut separator = Some(renderer::CONCAT_SEPARATOR.to_owned());
+    // Defaults: tools off, timestamps off, model on, agent on, context on, selection text off
+    let mut show_tools = false;
+    let mut show_timestamps = false;
+    let mut timestamp_style = renderer::TimestampStyle::Absolute;
+    let mut show_model = true;
+    let mut friendly_model_names = false;
+    let mut show_agent = true;
+    let mut show_context = true;
+    let mut context_kinds = renderer::ContextKindFilter::default();
+    let mut context_position = renderer::ContextPosition::Before;
+    let mut context_index = false;
+    let mut show_selection_text = false;
+    let mut dedupe_context = false;
+    let mut hoist_instructions = false;
+    let mut summary_only = false;
+    let mut dedupe_metadata = false;
+    let mut metadata_mode = renderer::MetadataMode::Always;
+    let mut metadata_alert = false;
+    let mut heading_offset: u8 = 0;
+    let mut deep_headings = renderer::DeepHeadingStyle::Clamp;
+    let mut edit_summary = false;
+    let mut edits_section = false;
+    let mut badges = false;
+    let mut show_usage = false;
+    let mut agent_speaker = false;
+    let mut nav = false;
+    let mut anonymize = false;
+    let mut anonymize_map = None;
+    let mut only_agent = None;
+    let mut redact_patterns = Vec::new();
+    let mut preview = None;
+    let mut max_tool_len = None;
+    let mut truncate = None;
+    let mut wrap = None;
+    let mut code_indent = renderer::CodeIndent::Keep;
+    let mut quote_user = false;
+    let mut reflow_paragraphs = false;
+    let mut title_from_message = false;
+    let mut document_heading = true;
+    let mut show_annotations = true;
+    let mut line_ending = renderer::LineEnding::Lf;
+    let mut output_encoding = OutputEncoding::default();
+    let mut encoding_fallback = "?".to_owned();
+    let mut path_style = renderer::PathStyle::Link;
+    let mut path_display = renderer::PathDisplay::default();
+    let mut embed_images = false;
+    let mut embed_selections = false;
+    let mut selection_lines = 20;
+    let mut repo_url = None;
+    let mut git_ref = None;
+    let mut front_matter = None;
+    let mut debug_unknown = false;
+    let mut source_comment = false;
+    let mut scan_vscode = false;
+    let mut browse = false;
+    let mut validate = false;
+    let mut detect = false;
+    let mut pretty_errors = false;
+    let mut lenient_json = false;
+    let mut strict = false;
+    let mut timestamp_unit = TimestampUnit::Auto;
+    let mut color = ColorChoice::default();
+    let mut log_format = LogFormat::default();
+    let mut extensions = Vec::new();
+    let mut quiet = false;
+    let mut dry_run = false;
+    let mut force = false;
+    let mut on_collision = CollisionPolicy::default();
+    let mut overwrite_if_changed = false;
+    let mut strict_exit = false;
+    let mut keep_empty = false;
+
+    let mut parser = lexopt::Parser::from_iter(args);
+    while let Some(arg) = parser.next().context(ParseArgsSnafu)? {
+        if !subcommand.takes(&arg) {
+            return Err(arg.unexpected()).context(ParseArgsSnafu);
+        }
+        match arg {
+            Short('o') | Long("output") => {
+                let val: PathBuf = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                output = Some(if val == Path::new("-") {
+                    OutputTarget::Stdout
+                } else {
+                    OutputTarget::Directory(val)
+                });
+            }
+            Long("concat") => concat = true,
+            Long("concat-per-dir") => concat_per_dir = true,
+            Long("concat-title") => {
+                let val: String = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                concat = true;
+                concat_title = Some(val);
+            }
+            Long("concat-toc") => {
+                concat = true;
+                concat_toc = true;
+            }
+            Long("separator") => {
+                let val: String = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                separator = Some(unescape_separator(&val));
+            }
+            Long("no-separator") => separator = None,
+            // Show/hide flags - last one wins
+            Short('v') | Long("verbose" | "show-tools") => show_tools = true,
+            Long("hide-tools") => show_tools = false,
+            Long("all" | "minimal") => {
+                let preset = if matches!(arg, Long("all")) {
+                    renderer::RenderOptions::all_metadata()
+                } else {
+                    renderer::RenderOptions::minimal()
+                };
+                show_tools = preset.show_tools;
+                show_timestamps = preset.show_timestamps;
+                show_model = preset.show_model;
+                show_agent = preset.show_agent;
+                show_context = preset.show_context;
+            }
+            Long("show-timestamps") => show_timestamps = true,
+            Long("hide-timestamps") => show_timestamps = false,
+            Long("timestamps") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                timestamp_style = match val.as_str() {
+                    "absolute" => renderer::TimestampStyle::Absolute,
+                    "iso" => renderer::TimestampStyle::Iso8601,
+                    "relative" => renderer::TimestampStyle::Relative,
+                    _ => return InvalidTimestampStyleSnafu { value: val }.fail(),
+                };
+                show_timestamps = true;
+            }
+            Long("show-model") => show_model = true,
+            Long("hide-model" | "no-model") => show_model = false,
+            Long("friendly-models") => friendly_model_names = true,
+            Long("show-agent") => show_agent = true,
+            Long("hide-agent") => show_agent = false,
+            Long("show-context") => show_context = true,
+            Long("hide-context") => show_context = false,
+            Long("context-only") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                context_kinds = renderer::ContextKindFilter::only(parse_context_kinds(&val)?);
+            }
+            Long("context-exclude") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                context_kinds = context_kinds.excluding(parse_context_kinds(&val)?);
+            }
+            Long("context-position") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                context_position = match val.as_str() {
+                    "before" => renderer::ContextPosition::Before,
+                    "after" => renderer::ContextPosition::After,
+                    _ => return InvalidContextPositionSnafu { value: val }.fail(),
+                };
+            }
+            Long("context-index") => context_index = true,
+            Long("show-selection-text") => show_selection_text = true,
+            Long("hide-selection-text") => show_selection_text = false,
+            Long("heading-offset") => {
+                let val: u8 = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                ensure!(val <= 5, InvalidHeadingOffsetSnafu);
+                heading_offset = val;
+            }
+            Long("deep-headings") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                deep_headings = match val.as_str() {
+                    "clamp" => renderer::DeepHeadingStyle::Clamp,
+                    "bold" => renderer::DeepHeadingStyle::Bold,
+                    _ => return InvalidDeepHeadingsSnafu { value: val }.fail(),
+                };
+            }
+            Long("dedupe-context") => dedupe_context = true,
+            Long("hoist-instructions") => hoist_instructions = true,
+            Long("summary-only") => summary_only = true,
+            Long("dedupe-metadata") => dedupe_metadata = true,
+            Long("metadata") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                metadata_mode = match val.as_str() {
+                    "always" => renderer::MetadataMode::Always,
+                    "on-change" => renderer::MetadataMode::OnChange,
+                    "first" => renderer::MetadataMode::FirstOnly,
+                    _ => return InvalidMetadataModeSnafu { value: val }.fail(),
+                };
+            }
+            Long("metadata-alert") => metadata_alert = true,
+            Long("edit-summary") => edit_summary = true,
+            Long("edits-section") => edits_section = true,
+            Long("badges") => badges = true,
+            Long("show-usage") => show_usage = true,
+            Long("agent-speaker") => agent_speaker = true,
+            Long("nav") => nav = true,
+            Long("preview") => {
+                let val: usize = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                preview = Some(val);
+            }
+            Long("max-tool-len") => {
+                let val: usize = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                ensure!(val > 0, InvalidToolLengthSnafu);
+                max_tool_len = Some(val);
+            }
+            Long("truncate") => {
+                let val: usize = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                ensure!(val > 0, InvalidMessageLengthSnafu);
+                truncate = Some(val);
+            }
+            Long("output-encoding") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                output_encoding = match val.to_ascii_lowercase().as_str() {
+                    "utf-8" | "utf8" => OutputEncoding::Utf8,
+                    "latin-1" | "latin1" | "iso-8859-1" => OutputEncoding::Latin1,
+                    _ => return InvalidOutputEncodingSnafu { value: val }.fail(),
+                };
+            }
+            Long("encoding-fallback") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                ensure!(val.is_ascii(), InvalidEncodingFallbackSnafu { value: val });
+                encoding_fallback = val;
+            }
+            Long("line-ending") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                line_ending = match val.as_str() {
+                    "lf" => renderer::LineEnding::Lf,
+                    "crlf" => renderer::LineEnding::Crlf,
+                    _ => return InvalidLineEndingSnafu { value: val }.fail(),
+                };
+            }
+            Long("path-style") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                path_style = match val.as_str() {
+                    "link" => renderer::PathStyle::Link,
+                    "footnote" => renderer::PathStyle::Footnote,
+                    _ => return InvalidPathStyleSnafu { value: val }.fail(),
+                };
+            }
+            Long("frontmatter") => {
+                front_matter.get_or_insert(renderer::FrontMatterFormat::Yaml);
+            }
+            Long("frontmatter-format") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                front_matter = Some(match val.as_str() {
+                    "yaml" => renderer::FrontMatterFormat::Yaml,
+                    "toml" => renderer::FrontMatterFormat::Toml,
+                    _ => return InvalidFrontMatterFormatSnafu { value: val }.fail(),
+                });
+            }
+            Long("embed-images") => embed_images = true,
+            Long("embed-selections") => embed_selections = true,
+            Long("selection-lines") => {
+                let val: usize = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                ensure!(val > 0, InvalidSelectionLinesSnafu);
+                selection_lines = val;
+            }
+            Long("repo-url") => {
+                let val: String = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                repo_url = Some(val);
+            }
+            Long("ref") => {
+                let val: String = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                git_ref = Some(val);
+            }
+            Long("path-display") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                path_display = parse_path_display(&val)?;
+            }
+            Long("wrap") => {
+                let val: usize = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                ensure!(val > 0, InvalidWrapWidthSnafu);
+                wrap = Some(val);
+            }
+            Long("code-indent") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                code_indent = parse_code_indent(&val)?;
+            }
+            Long("quote-user") => quote_user = true,
+            Long("reflow-paragraphs") => reflow_paragraphs = true,
+            Long("title-from-message") => title_from_message = true,
+            Long("no-title" | "no-doc-heading") => document_heading = false,
+            Long("hide-annotations") => show_annotations = false,
+            Long("anonymize") => anonymize = true,
+            Long("anonymize-map") => {
+                let val: PathBuf = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                anonymize_map = Some(val);
+                anonymize = true;
+            }
+            Long("only-agent") => {
+                let val: String = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .parse()
+                    .context(ParseArgsSnafu)?;
+                only_agent = Some(val.trim_start_matches('@').to_owned());
+            }
+            Long("redact") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                regex::Regex::new(&val).context(InvalidRedactPatternSnafu { pattern: &val })?;
+                redact_patterns.push(val);
+            }
+            Long("redact-common") => redact_patterns.extend(
+                renderer::COMMON_SECRET_PATTERNS
+                    .iter()
+                    .map(|&pattern| pattern.to_owned()),
+            ),
+            Long("debug-unknown") => debug_unknown = true,
+            Long("source-comment") => source_comment = true,
+            Long("scan-vscode") => scan_vscode = true,
+            Long("browse") => browse = true,
+            Long("validate") => validate = true,
+            Long("detect") => detect = true,
+            Long("pretty-errors") => pretty_errors = true,
+            Long("lenient-json") => lenient_json = true,
+            Long("strict") => strict = true,
+            Long("timestamp-unit") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                timestamp_unit = match val.as_str() {
+                    "auto" => TimestampUnit::Auto,
+                    "millis" => TimestampUnit::Millis,
+                    "seconds" => TimestampUnit::Seconds,
+                    _ => return InvalidTimestampUnitSnafu { value: val }.fail(),
+                };
+            }
+            Long("dump-schema") => return Ok(Command::DumpSchema),
+            Long("ext") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                let suffix = val.trim_start_matches('.');
+                ensure!(
+                    !suffix.is_empty() && !suffix.contains(['/', '\\']),
+                    InvalidExtensionSnafu { value: val }
+                );
+                extensions.push(suffix.to_owned());
+            }
+            Long("color") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                color = match val.as_str() {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    _ => return InvalidColorSnafu { value: val }.fail(),
+                };
+            }
+            Long("log-format") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                log_format = match val.as_str() {
+                    "human" => LogFormat::Human,
+                    "json" => LogFormat::Json,
+                    _ => return InvalidLogFormatSnafu { value: val }.fail(),
+                };
+            }
+            Short('q') | Long("quiet") => quiet = true,
+            Short('n') | Long("dry-run") => dry_run = true,
+            Short('f') | Long("force") => force = true,
+            Long("overwrite-if-changed") => overwrite_if_changed = true,
+            Long("on-collision") => {
+                let val = parser
+                    .value()
+                    .context(ParseArgsSnafu)?
+                    .string()
+                    .context(ParseArgsSnafu)?;
+                on_collision = match val.as_str() {
+                    "error" => CollisionPolicy::Error,
+                    "rename" => CollisionPolicy::Rename,
+                    "overwrite" => CollisionPolicy::Overwrite,
+                    _ => return InvalidCollisionPolicySnafu { value: val }.fail(),
+                };
+            }
+            Long("strict-exit") => strict_exit = true,
+            Long("keep-empty") => keep_empty = true,
+            Short('h') | Long("help") => return Ok(Command::Help(named)),
+            Short('V') | Long("version") => return Ok(Command::Version),
+            Value(val) => input.push(val.parse().context(ParseArgsSnafu)?),
+            _ => return Err(arg.unexpected()).context(ParseArgsSnafu),
+        }
+    }
+
+    // Each mode reads the inputs its own way, and each layout writes the
+    // outputs its own way, so only one of each can be chosen
+    ensure_one_mode(&[
+        ("browse", browse),
+        ("validate", validate),
+        ("detect", detect),
+    ])?;
+    ensure_one_mode(&[
+        ("concat", concat),
+        ("concat-per-dir", concat_per_dir),
+        ("nav", nav),
+    ])?;
+    let to_stdout = matches!(output, Some(OutputTarget::Stdout));
+    ensure_one_mode(&[
+        ("concat-per-dir", concat_per_dir),
+        ("nav", nav),
+        ("output -", to_stdout),
+    ])?;
+
+    let validate = validate || subcommand == Subcommand::Validate;
+    let stats = subcommand == Subcommand::Stats;
+    let diff = subcommand == Subcommand::Diff;
+    let discover = subcommand == Subcommand::Discover;
+    // Commands other than a conversion report on empty chats too
+    let keep_empty = keep_empty || subcommand != Subcommand::Convert;
+    let scan_vscode = scan_vscode || discover;
+
+    // Browsing writes to the current directory unless told otherwise, and
+    // the other modes write nothing. Any other missing output is resolved
+    // once the inputs are known.
+    let output = match output {
+        None if browse || validate || detect || subcommand != Subcommand::Convert => {
+            Some(OutputTarget::Directory(PathBuf::from(".")))
+        }
+        output => output,
+    };
+    let output = output.map(|output| match (concat, output) {
+        (true, OutputTarget::Directory(path)) => OutputTarget::File(path),
+        (_, output) => output,
+    });
+
+    if extensions.is_empty() {
+        extensions.extend(["json".to_owned(), "jsonl".to_owned()]);
+    }
+
+    let repo_permalink = match (repo_url, git_ref) {
+        (Some(url), Some(git_ref)) => Some(renderer::RepoInfo { url, git_ref }),
+        (None, None) => None,
+        _ => return IncompleteRepoSnafu.fail(),
+    };
+
+    Ok(Command::Convert(Box::new(Cli {
+        input,
+        extensions,
+        output,
+        concat,
+        concat_toc,
+        concat_per_dir,
+        concat_title,
+        separator,
+        show_tools,
+        show_timestamps,
+        timestamp_style,
+        show_model,
+        friendly_model_names,
+        show_agent,
+        show_context,
+        context_kinds,
+        context_position,
+        context_index,
+        show_selection_text,
+        dedupe_context,
+        hoist_instructions,
+        summary_only,
+        dedupe_metadata,
+        metadata_mode,
+        metadata_alert,
+        heading_offset,
+        deep_headings,
+        edit_summary,
+        edits_section,
+        badges,
+        show_usage,
+        agent_speaker,
+        nav,
+        anonymize,
+        anonymize_map,
+        only_agent,
+        redact_patterns,
+        preview,
+        max_tool_len,
+        truncate,
+        wrap,
+        code_indent,
+        quote_user,
+        reflow_paragraphs,
+        title_from_message,
+        document_heading,
+        show_annotations,
+        line_ending,
+        output_encoding,
+        encoding_fallback,
+        path_style,
+        path_display,
+        embed_images,
+        repo_permalink,
+        embed_selections: embed_selections.then_some(selection_lines),
+        front_matter,
+        debug_unknown,
+        source_comment,
+        scan_vscode,
+        browse,
+        validate,
+        detect,
+        stats,
+        diff,
+        discover,
+        pretty_errors,
+        lenient_json,
+        strict,
+        timestamp_unit,
+        color,
+        log_format,
+        quiet,
+        dry_run,
+        force,
+        overwrite_if_changed,
+        on_collision,
+        strict_exit,
+        keep_empty,
+    })))
+}
+
+/// Parses a comma-separated list of context kinds.
+fn parse_context_kinds(val: &str) -> Result<Vec<renderer::ContextKind>, Error> {
+    val.split(',')
+        .map(|name| {
+            Ok(match name.trim() {
+                "file" => renderer::ContextKind::File,
+                "selection" => renderer::ContextKind::Selection,
+                "folder" => renderer::ContextKind::Folder,
+                "instructions" => renderer::ContextKind::Instructions,
+                "pasted" => renderer::ContextKind::PastedText,
+                "terminal" => renderer::ContextKind::TerminalOutput,
+                _ => return InvalidContextKindSnafu { value: name }.fail(),
+            })
+        })
+        .collect()
+}
+
+/// Parses a `--path-display` value: `auto[:N]`, `full`, `short`, or `name`.
+fn parse_path_display(val: &str) -> Result<renderer::PathDisplay, Error> {
+    let invalid = || InvalidPathDisplaySnafu { value: val }.build();
+    Ok(match val {
+        "auto" => renderer::PathDisplay::default(),
+        "full" => renderer::PathDisplay::Always,
+        "short" => renderer::PathDisplay::Never,
+        "name" => renderer::PathDisplay::FilenameOnly,
+        _ => {
+            let max_inline = val
+                .strip_prefix("auto:")
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(invalid)?;
+            renderer::PathDisplay::Auto { max_inline }
+        }
+    })
+}
+
+/// Parses a `--code-indent` value: `keep`, `tabs[:N]`, or `spaces[:N]`,
+/// where N is at least 1 and defaults to 4.
+fn parse_code_indent(val: &str) -> Result<renderer::CodeIndent, Error> {
+    let invalid = || InvalidCodeIndentSnafu { value: val }.build();
+    let (mode, width) = match val.split_once(':') {
+        Some((mode, width)) => (
+            mode,
+            width.parse().ok().filter(|&n| n > 0).ok_or_else(invalid)?,
+        ),
+        None => (val, 4),
+    };
+    Ok(match mode {
+        "keep" if val == "keep" => renderer::CodeIndent::Keep,
+        "tabs" => renderer::CodeIndent::Tabs(width),
+        "spaces" => renderer::CodeIndent::Spaces(width),
+        _ => return Err(invalid()),
+    })
+}
+
+/// Interprets the `\n`, `\t`, and `\\` escapes in a `--separator` value.
+///
+/// Any other backslash is kept as it is.
+fn unescape_separator(val: &str) -> String {
+    let mut out = String::with_capacity(val.len());
+    let mut chars = val.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('\\') | None => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+        }
+    }
+    out
+}
+
+/// Fails with [`Error::ConflictingModes`], naming the first two, if more
+/// than one of `modes`, given by name with whether each was chosen, was
+/// chosen.
+fn ensure_one_mode(modes: &[(&'static str, bool)]) -> Result<(), Error> {
+    let mut chosen = modes
+        .iter()
+        .filter(|(_, chosen)| *chosen)
+        .map(|(name, _)| *name);
+    match (chosen.next(), chosen.next()) {
+        (Some(first), Some(second)) => ConflictingModesSnafu { first, second }.fail(),
+        _ => Ok(()),
+    }
+}
+
+/// Parses a command line that is expected to ask for a conversion.
+#[cfg(test)]
+pub(super) fn parse_args_from<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Cli, Error> {
+    match parse_args(args)? {
+        Command::Convert(cli) => Ok(*cli),
+        command => panic!("expected a conversion, got {command:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::run::{make_render_options, render_options_for};
+
+    /// Helper to create args from a string for testing.
+    fn args(s: &str) -> impl Iterator<Item = &str> {
+        s.split_whitespace()
+    }
+
+    #[test]
+    fn help_and_version_stop_parsing() {
+        assert!(matches!(parse_args(args("cp2md")), Ok(Command::Help(None))));
+        assert!(matches!(
+            parse_args(args("cp2md x.json --help --bogus")),
+            Ok(Command::Help(None))
+        ));
+        assert!(matches!(
+            parse_args(args("cp2md stats -h")),
+            Ok(Command::Help(Some(Subcommand::Stats)))
+        ));
+        assert!(matches!(
+            parse_args(args("cp2md -V x.json")),
+            Ok(Command::Version)
+        ));
+        assert!(matches!(
+            parse_args(args("cp2md --dump-schema")),
+            Ok(Command::DumpSchema)
+        ));
+    }
+
+    // =========================================================================
+    // Pure argument parsing tests (no I/O)
+    // =========================================================================
+
+    #[test]
+    fn parses_output_to_stdout() {
+        let cli = parse_args_from(args("cp2md input.json -o -")).unwrap();
+        assert!(matches!(cli.output, Some(OutputTarget::Stdout)));
+    }
+
+    #[test]
+    fn skips_binary_name_when_collecting_inputs() {
+        let cli = parse_args_from(args("cp2md input.json -o -")).unwrap();
+        assert_eq!(cli.input, vec![PathBuf::from("input.json")]);
+    }
+
+    #[test]
+    fn parses_output_to_directory() {
+        let cli = parse_args_from(args("cp2md input.json -o out/")).unwrap();
+        assert!(matches!(cli.output, Some(OutputTarget::Directory(_))));
+    }
+
+    #[test]
+    fn output_is_optional() {
+        let cli = parse_args_from(args("cp2md input.json")).unwrap();
+        assert!(cli.output.is_none());
+    }
+
+    #[test]
+    fn error_on_invalid_heading_offset() {
+        let err = parse_args_from(args("cp2md -o - --heading-offset 7 x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidHeadingOffset));
+    }
+
+    #[test]
+    fn concat_converts_directory_to_file_target() {
+        let cli = parse_args_from(args("cp2md --concat -o out.md input.json")).unwrap();
+        assert!(matches!(cli.output, Some(OutputTarget::File(_))));
+    }
+
+    #[test]
+    fn parses_metadata_presets() {
+        let cli = parse_args_from(args("cp2md --all -o - x.json")).unwrap();
+        let opts = make_render_options(&cli);
+        assert!(opts.show_tools && opts.show_timestamps && opts.show_context);
+
+        let cli = parse_args_from(args("cp2md --minimal --show-model -o - x.json")).unwrap();
+        let opts = make_render_options(&cli);
+        assert!(opts.show_model);
+        assert!(!opts.show_tools && !opts.show_agent && !opts.show_context);
+    }
+
+    #[test]
+    fn verbose_enables_show_tools() {
+        let cli = parse_args_from(args("cp2md -v -o - x.json")).unwrap();
+        assert!(cli.show_tools);
+    }
+
+    #[test]
+    fn parses_preview_count() {
+        let cli = parse_args_from(args("cp2md --preview 3 -o - x.json")).unwrap();
+        assert_eq!(cli.preview, Some(3));
+    }
+
+    #[test]
+    fn parses_line_ending() {
+        let cli = parse_args_from(args("cp2md --line-ending crlf -o - x.json")).unwrap();
+        assert_eq!(cli.line_ending, renderer::LineEnding::Crlf);
+
+        let err = parse_args_from(args("cp2md --line-ending cr -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidLineEnding { .. }));
+    }
+
+    #[test]
+    fn parses_timestamp_style() {
+        let cli = parse_args_from(args("cp2md --timestamps relative -o - x.json")).unwrap();
+        assert!(cli.show_timestamps);
+        assert_eq!(cli.timestamp_style, renderer::TimestampStyle::Relative);
+
+        // A later --hide-timestamps still wins, keeping the style for --show-timestamps
+        let cli =
+            parse_args_from(args("cp2md --timestamps iso --hide-timestamps -o - x.json")).unwrap();
+        assert!(!cli.show_timestamps);
+        assert_eq!(cli.timestamp_style, renderer::TimestampStyle::Iso8601);
+
+        let err = parse_args_from(args("cp2md --timestamps local -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidTimestampStyle { .. }));
+    }
+
+    #[test]
+    fn parses_deep_headings() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(cli.deep_headings, renderer::DeepHeadingStyle::Clamp);
+
+        let cli = parse_args_from(args("cp2md --deep-headings bold -o - x.json")).unwrap();
+        assert_eq!(
+            make_render_options(&cli).deep_heading_style,
+            renderer::DeepHeadingStyle::Bold
+        );
+
+        let err = parse_args_from(args("cp2md --deep-headings h7 -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidDeepHeadings { .. }));
+    }
+
+    #[test]
+    fn parses_path_style() {
+        let cli = parse_args_from(args("cp2md --path-style footnote -o - x.json")).unwrap();
+        assert_eq!(cli.path_style, renderer::PathStyle::Footnote);
+
+        let err = parse_args_from(args("cp2md --path-style inline -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidPathStyle { .. }));
+    }
+
+    #[test]
+    fn parses_path_display() {
+        let parse = |val| parse_path_display(val).unwrap();
+        assert_eq!(
+            parse("auto"),
+            renderer::PathDisplay::Auto { max_inline: 30 }
+        );
+        assert_eq!(
+            parse("auto:50"),
+            renderer::PathDisplay::Auto { max_inline: 50 }
+        );
+        assert_eq!(parse("full"), renderer::PathDisplay::Always);
+        assert_eq!(parse("short"), renderer::PathDisplay::Never);
+        assert_eq!(parse("name"), renderer::PathDisplay::FilenameOnly);
+
+        for bad in ["auto:", "auto:x", "long"] {
+            let err = parse_path_display(bad).unwrap_err();
+            assert!(matches!(err, Error::InvalidPathDisplay { .. }));
+        }
+    }
+
+    #[test]
+    fn parses_code_indent() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(
+            make_render_options(&cli).code_indent,
+            renderer::CodeIndent::Keep
+        );
+
+        let parse = |val| parse_code_indent(val).unwrap();
+        assert_eq!(parse("keep"), renderer::CodeIndent::Keep);
+        assert_eq!(parse("tabs"), renderer::CodeIndent::Tabs(4));
+        assert_eq!(parse("tabs:2"), renderer::CodeIndent::Tabs(2));
+        assert_eq!(parse("spaces:8"), renderer::CodeIndent::Spaces(8));
+
+        for bad in ["tabs:0", "spaces:", "spaces:x", "keep:2", "tab"] {
+            let err = parse_code_indent(bad).unwrap_err();
+            assert!(matches!(err, Error::InvalidCodeIndent { .. }));
+        }
+    }
+
+    #[test]
+    fn parses_frontmatter_format() {
+        let cli = parse_args_from(args("cp2md --frontmatter -o - x.json")).unwrap();
+        assert_eq!(cli.front_matter, Some(renderer::FrontMatterFormat::Yaml));
+
+        let cli = parse_args_from(args(
+            "cp2md --frontmatter-format toml --frontmatter -o - x.json",
+        ))
+        .unwrap();
+        assert_eq!(cli.front_matter, Some(renderer::FrontMatterFormat::Toml));
+
+        let err = parse_args_from(args("cp2md --frontmatter-format json -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidFrontMatterFormat { .. }));
+    }
+
+    #[test]
+    fn parses_context_kinds() {
+        use renderer::ContextKind;
+
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(
+            ContextKind::ALL
+                .iter()
+                .all(|&k| cli.context_kinds.allows(k))
+        );
+
+        let cli = parse_args_from(args("cp2md --context-only file,selection -o - x.json")).unwrap();
+        let kinds = make_render_options(&cli).context_kinds;
+        assert!(kinds.allows(ContextKind::File) && kinds.allows(ContextKind::Selection));
+        assert!(!kinds.allows(ContextKind::Folder));
+
+        let cli = parse_args_from(args(
+            "cp2md --context-exclude folder --context-exclude instructions -o - x.json",
+        ))
+        .unwrap();
+        assert!(!cli.context_kinds.allows(ContextKind::Folder));
+        assert!(!cli.context_kinds.allows(ContextKind::Instructions));
+        assert!(cli.context_kinds.allows(ContextKind::TerminalOutput));
+
+        let err = parse_args_from(args("cp2md --context-only file,image -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidContextKind { value } if value == "image"));
+    }
+
+    #[test]
+    fn parses_context_position_and_index() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        let opts = make_render_options(&cli);
+        assert_eq!(opts.context_position, renderer::ContextPosition::Before);
+        assert!(!opts.context_index);
+
+        let cli = parse_args_from(args(
+            "cp2md --context-position after --context-index -o - x.json",
+        ))
+        .unwrap();
+        let opts = make_render_options(&cli);
+        assert_eq!(opts.context_position, renderer::ContextPosition::After);
+        assert!(opts.context_index);
+
+        let err = parse_args_from(args("cp2md --context-position end -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidContextPosition { value } if value == "end"));
+    }
+
+    #[test]
+    fn parses_quote_user() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.quote_user);
+
+        let cli = parse_args_from(args("cp2md --quote-user -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).quote_user);
+    }
+
+    #[test]
+    fn parses_title_from_message() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).title_from_first_message);
+
+        let cli = parse_args_from(args("cp2md --title-from-message -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).title_from_first_message);
+    }
+
+    #[test]
+    fn parses_no_title() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(make_render_options(&cli).document_heading, Some(1));
+
+        for flag in ["--no-title", "--no-doc-heading"] {
+            let cli = parse_args_from(args(&format!("cp2md {flag} -o - x.json"))).unwrap();
+            assert_eq!(make_render_options(&cli).document_heading, None, "{flag}");
+        }
+    }
+
+    #[test]
+    fn parses_repo_url_and_ref() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(make_render_options(&cli).repo_permalink, None);
+
+        let cli = parse_args_from(args(
+            "cp2md --repo-url https://github.com/whee/cp2md --ref main -o - x.json",
+        ))
+        .unwrap();
+        assert_eq!(
+            make_render_options(&cli).repo_permalink,
+            Some(renderer::RepoInfo {
+                url: "https://github.com/whee/cp2md".into(),
+                git_ref: "main".into(),
+            })
+        );
+
+        for line in [
+            "cp2md --repo-url https://github.com/whee/cp2md -o - x.json",
+            "cp2md --ref main -o - x.json",
+        ] {
+            let err = parse_args_from(args(line)).unwrap_err();
+            assert!(matches!(err, Error::IncompleteRepo));
+            assert_eq!(err.exit_code(), 1);
+        }
+    }
+
+    #[test]
+    fn parses_embed_images() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).embed_images);
+
+        let cli = parse_args_from(args("cp2md --embed-images -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).embed_images);
+    }
+
+    #[test]
+    fn parses_hide_annotations() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).show_annotations);
+
+        let cli = parse_args_from(args("cp2md --hide-annotations -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).show_annotations);
+    }
+
+    #[test]
+    fn parses_max_tool_len() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(make_render_options(&cli).max_tool_message_len, None);
+
+        let cli = parse_args_from(args("cp2md --max-tool-len 80 -o - x.json")).unwrap();
+        assert_eq!(make_render_options(&cli).max_tool_message_len, Some(80));
+
+        let err = parse_args_from(args("cp2md --max-tool-len 0 -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidToolLength));
+    }
+
+    #[test]
+    fn parses_truncate() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(make_render_options(&cli).max_message_chars, None);
+
+        let cli = parse_args_from(args("cp2md --truncate 200 -o - x.json")).unwrap();
+        assert_eq!(make_render_options(&cli).max_message_chars, Some(200));
+
+        let err = parse_args_from(args("cp2md --truncate 0 -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidMessageLength));
+    }
+
+    #[test]
+    fn error_on_zero_wrap_width() {
+        let err = parse_args_from(args("cp2md --wrap 0 -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidWrapWidth));
+    }
+
+    #[test]
+    fn anonymize_map_implies_anonymize() {
+        let cli = parse_args_from(args("cp2md --anonymize-map map.json -o - x.json")).unwrap();
+        assert!(cli.anonymize);
+        assert_eq!(cli.anonymize_map, Some(PathBuf::from("map.json")));
+    }
+
+    #[test]
+    fn browse_defaults_output_to_current_directory() {
+        let cli = parse_args_from(args("cp2md --browse x.json")).unwrap();
+        assert!(cli.browse);
+        assert!(
+            matches!(cli.output, Some(OutputTarget::Directory(ref dir)) if dir == Path::new("."))
+        );
+    }
+
+    #[test]
+    fn parses_extensions() {
+        let cli = parse_args_from(args("cp2md -o out x")).unwrap();
+        assert_eq!(cli.extensions, ["json", "jsonl"]);
+
+        let cli = parse_args_from(args("cp2md --ext .copilot.json --ext chat -o out x")).unwrap();
+        assert_eq!(cli.extensions, ["copilot.json", "chat"]);
+
+        let err = parse_args_from(args("cp2md --ext . -o out x")).unwrap_err();
+        assert!(matches!(err, Error::InvalidExtension { .. }));
+    }
+
+    #[test]
+    fn parses_keep_empty() {
+        let cli = parse_args_from(args("cp2md -o out x.json")).unwrap();
+        assert!(!cli.keep_empty);
+
+        let cli = parse_args_from(args("cp2md --keep-empty -o out x.json")).unwrap();
+        assert!(cli.keep_empty);
+    }
+
+    #[test]
+    fn parses_output_encoding() {
+        let cli = parse_args_from(args("cp2md -o out x.json")).unwrap();
+        assert_eq!(cli.output_encoding, OutputEncoding::Utf8);
+        assert_eq!(cli.encoding_fallback, "?");
+
+        let cli = parse_args_from(args(
+            "cp2md --output-encoding Latin1 --encoding-fallback _ -o out x.json",
+        ))
+        .unwrap();
+        assert_eq!(cli.output_encoding, OutputEncoding::Latin1);
+        assert_eq!(cli.encoding_fallback, "_");
+
+        let err = parse_args_from(args("cp2md --output-encoding utf-16 -o out x.json"));
+        assert!(matches!(err, Err(Error::InvalidOutputEncoding { .. })));
+        let err = parse_args_from(args("cp2md --encoding-fallback ¿ -o out x.json"));
+        assert!(matches!(err, Err(Error::InvalidEncodingFallback { .. })));
+    }
+
+    #[test]
+    fn parses_color() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(cli.color, ColorChoice::Auto);
+
+        let cli = parse_args_from(args("cp2md --color never -o - x.json")).unwrap();
+        assert_eq!(cli.color, ColorChoice::Never);
+
+        let err = parse_args_from(args("cp2md --color sometimes -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidColor { .. }));
+    }
+
+    #[test]
+    fn parses_log_format() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(cli.log_format, LogFormat::Human);
+
+        let cli = parse_args_from(args("cp2md --log-format json -o - x.json")).unwrap();
+        assert_eq!(cli.log_format, LogFormat::Json);
+
+        let err = parse_args_from(args("cp2md --log-format yaml -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidLogFormat { .. }));
+    }
+
+    #[test]
+    fn parses_on_collision() {
+        let cli = parse_args_from(args("cp2md -o out x.json")).unwrap();
+        assert_eq!(cli.on_collision, CollisionPolicy::Rename);
+
+        let cli = parse_args_from(args("cp2md --on-collision error -o out x.json")).unwrap();
+        assert_eq!(cli.on_collision, CollisionPolicy::Error);
+
+        let cli = parse_args_from(args("cp2md --on-collision overwrite -o out x.json")).unwrap();
+        assert_eq!(cli.on_collision, CollisionPolicy::Overwrite);
+
+        let err = parse_args_from(args("cp2md --on-collision skip -o out x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidCollisionPolicy { .. }));
+    }
+
+    #[test]
+    fn parses_pretty_errors() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.pretty_errors);
+
+        let cli = parse_args_from(args("cp2md --pretty-errors -o - x.json")).unwrap();
+        assert!(cli.pretty_errors);
+    }
+
+    #[test]
+    fn validate_needs_no_output() {
+        let cli = parse_args_from(args("cp2md --validate x.json")).unwrap();
+        assert!(cli.validate);
+    }
+
+    #[test]
+    fn rejects_conflicting_modes() {
+        for (line, modes) in [
+            ("cp2md --validate --browse x.json", ("browse", "validate")),
+            ("cp2md --detect --validate x.json", ("validate", "detect")),
+            (
+                "cp2md --browse --detect --validate x.json",
+                ("browse", "validate"),
+            ),
+            (
+                "cp2md --concat --concat-per-dir -o out x",
+                ("concat", "concat-per-dir"),
+            ),
+            (
+                "cp2md --concat-per-dir --nav -o out x",
+                ("concat-per-dir", "nav"),
+            ),
+            ("cp2md --concat --nav -o out x", ("concat", "nav")),
+            (
+                "cp2md -o - --concat-per-dir x",
+                ("concat-per-dir", "output -"),
+            ),
+            ("cp2md --nav -o - x.json", ("nav", "output -")),
+        ] {
+            match parse_args(args(line)) {
+                Err(Error::ConflictingModes { first, second }) => {
+                    assert_eq!((first, second), modes, "{line}");
+                }
+                other => panic!("{line}: expected ConflictingModes, got {other:?}"),
+            }
+        }
+        // A command's mode cannot be combined with another either
+        assert!(matches!(
+            parse_args(args("cp2md stats --detect x.json")),
+            Err(Error::ParseArgs { .. })
+        ));
+        assert!(matches!(
+            parse_args(args("cp2md validate --browse x.json")),
+            Err(Error::ParseArgs { .. })
+        ));
+    }
+
+    #[test]
+    fn parses_timestamp_unit() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(cli.timestamp_unit, TimestampUnit::Auto);
+
+        for (arg, unit) in [
+            ("auto", TimestampUnit::Auto),
+            ("millis", TimestampUnit::Millis),
+            ("seconds", TimestampUnit::Seconds),
+        ] {
+            let line = format!("cp2md --timestamp-unit {arg} -o - x.json");
+            let cli = parse_args_from(args(&line)).unwrap();
+            assert_eq!(cli.timestamp_unit, unit);
+        }
+
+        let err = parse_args_from(args("cp2md --timestamp-unit ms -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidTimestampUnit { .. }));
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn parses_lenient_json() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.lenient_json);
+
+        let cli = parse_args_from(args("cp2md --lenient-json -o - x.json")).unwrap();
+        assert!(cli.lenient_json);
+    }
+
+    #[test]
+    fn parses_strict() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.strict);
+
+        let cli = parse_args_from(args("cp2md --strict -o - x.json")).unwrap();
+        assert!(cli.strict);
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let cli = parse_args_from(args("cp2md convert -o out x.json")).unwrap();
+        assert_eq!(cli.input, [PathBuf::from("x.json")]);
+        assert!(!cli.validate && !cli.stats);
+
+        let cli = parse_args_from(args("cp2md validate --strict x.json")).unwrap();
+        assert!(cli.validate && cli.strict);
+        assert!(
+            matches!(cli.output, Some(OutputTarget::Directory(ref dir)) if dir == Path::new("."))
+        );
+
+        let cli = parse_args_from(args("cp2md stats -q x.json")).unwrap();
+        assert!(cli.stats && cli.quiet && cli.keep_empty);
+
+        let cli = parse_args_from(args("cp2md diff a.json b.json")).unwrap();
+        assert!(cli.diff);
+        assert_eq!(cli.input.len(), 2);
+
+        let cli = parse_args_from(args("cp2md discover ~/.config/Code")).unwrap();
+        assert!(cli.discover && cli.scan_vscode);
+
+        // A command's name only counts as the first argument
+        let cli = parse_args_from(args("cp2md -o out stats")).unwrap();
+        assert!(!cli.stats);
+        assert_eq!(cli.input, [PathBuf::from("stats")]);
+    }
+
+    #[test]
+    fn subcommands_take_only_their_options() {
+        for line in [
+            "cp2md stats --concat x.json",
+            "cp2md validate -o out x.json",
+            "cp2md diff --ext md a.json b.json",
+            "cp2md discover --dump-schema x",
+        ] {
+            assert!(
+                matches!(parse_args(args(line)), Err(Error::ParseArgs { .. })),
+                "{line}"
+            );
+        }
+        assert!(parse_args(args("cp2md convert --concat -o - x.json")).is_ok());
+    }
+
+    #[test]
+    fn parses_debug_unknown() {
+        let cli = parse_args_from(args("cp2md --debug-unknown -o - x.json")).unwrap();
+        assert!(cli.debug_unknown);
+        assert!(make_render_options(&cli).debug_unknown);
+    }
+
+    #[test]
+    fn parses_reflow_paragraphs() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).reflow_paragraphs);
+
+        let cli = parse_args_from(args("cp2md --reflow-paragraphs -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).reflow_paragraphs);
+    }
+
+    #[test]
+    fn parses_show_usage() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).show_usage);
+
+        let cli = parse_args_from(args("cp2md --show-usage -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).show_usage);
+    }
+
+    #[test]
+    fn parses_badges() {
+        let cli = parse_args_from(args("cp2md --badges -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).show_activity_badges);
+    }
+
+    #[test]
+    fn parses_edits_section() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).edits_as_section);
+
+        let cli = parse_args_from(args("cp2md --edits-section -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).edits_as_section);
+    }
+
+    #[test]
+    fn parses_hoist_instructions() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).hoist_instructions);
+
+        let cli = parse_args_from(args("cp2md --hoist-instructions -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).hoist_instructions);
+    }
+
+    #[test]
+    fn parses_friendly_models() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).friendly_model_names);
+
+        let cli = parse_args_from(args("cp2md --friendly-models -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).friendly_model_names);
+    }
+
+    #[test]
+    fn parses_summary_only() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).summary_only);
+
+        let cli = parse_args_from(args("cp2md --summary-only -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).summary_only);
+    }
+
+    #[test]
+    fn parses_dedupe_context() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.dedupe_context);
+
+        let cli = parse_args_from(args("cp2md --dedupe-context -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).dedupe_context);
+    }
+
+    #[test]
+    fn parses_dedupe_metadata() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.dedupe_metadata);
+
+        let cli = parse_args_from(args("cp2md --dedupe-metadata -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).dedupe_metadata);
+    }
+
+    #[test]
+    fn parses_metadata_alert() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!make_render_options(&cli).metadata_as_alert);
+
+        let cli = parse_args_from(args("cp2md --metadata-alert -o - x.json")).unwrap();
+        assert!(make_render_options(&cli).metadata_as_alert);
+    }
+
+    #[test]
+    fn parses_metadata_mode() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert_eq!(cli.metadata_mode, renderer::MetadataMode::Always);
+
+        let cli = parse_args_from(args("cp2md --metadata on-change -o - x.json")).unwrap();
+        assert_eq!(
+            make_render_options(&cli).metadata_mode,
+            renderer::MetadataMode::OnChange
+        );
+
+        let cli = parse_args_from(args("cp2md --metadata first -o - x.json")).unwrap();
+        assert_eq!(cli.metadata_mode, renderer::MetadataMode::FirstOnly);
+
+        let err = parse_args_from(args("cp2md --metadata never -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidMetadataMode { .. }));
+    }
+
+    #[test]
+    fn parses_source_comment() {
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.source_comment);
+
+        let cli = parse_args_from(args("cp2md --source-comment -o - x.json")).unwrap();
+        let chat = cp2md::parser::parse_chat(r#"{"responderUsername":"","requests":[]}"#).unwrap();
+        let opts = render_options_for(Path::new("x.json"), &chat, &cli);
+        assert!(opts.source_comment);
+        assert_eq!(opts.source_path.as_deref(), Some("x.json"));
+    }
+
+    #[test]
+    fn parses_embed_selections() {
+        let cli = parse_args_from(args("cp2md --selection-lines 5 -o - x.json")).unwrap();
+        assert_eq!(cli.embed_selections, None);
+
+        let cli = parse_args_from(args("cp2md --embed-selections -o - x.json")).unwrap();
+        assert_eq!(cli.embed_selections, Some(20));
+
+        let cli = parse_args_from(args(
+            "cp2md --selection-lines 5 --embed-selections -o - x.json",
+        ))
+        .unwrap();
+        assert_eq!(cli.embed_selections, Some(5));
+
+        let err = parse_args_from(args("cp2md --selection-lines 0 -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidSelectionLines));
+    }
+
+    #[test]
+    fn parses_selection_text_flags() {
+        let cli = parse_args_from(args("cp2md --show-selection-text -o - x.json")).unwrap();
+        assert!(cli.show_selection_text);
+
+        let cli = parse_args_from(args("cp2md -o - x.json")).unwrap();
+        assert!(!cli.show_selection_text);
+    }
+
+    #[test]
+    fn parses_only_agent_without_at_sign() {
+        let cli = parse_args_from(args("cp2md --only-agent @code-reviewer -o - x.json")).unwrap();
+        assert_eq!(cli.only_agent.as_deref(), Some("code-reviewer"));
+    }
+
+    #[test]
+    fn parses_redact_patterns() {
+        let cli = parse_args_from(args(
+            "cp2md --redact sk-[a-z]+ --redact-common --redact hunter2 -o - x.json",
+        ))
+        .unwrap();
+        let opts = make_render_options(&cli);
+        assert_eq!(
+            opts.redact_patterns.first().map(String::as_str),
+            Some("sk-[a-z]+")
+        );
+        assert_eq!(
+            opts.redact_patterns.last().map(String::as_str),
+            Some("hunter2")
+        );
+        assert_eq!(
+            opts.redact_patterns.len(),
+            renderer::COMMON_SECRET_PATTERNS.len() + 2
+        );
+
+        let err = parse_args_from(args("cp2md --redact ( -o - x.json")).unwrap_err();
+        assert!(matches!(err, Error::InvalidRedactPattern { .. }));
+    }
+
+    #[test]
+    fn parses_nav() {
+        let cli = parse_args_from(args("cp2md --nav -o out x.json")).unwrap();
+        assert!(cli.nav);
+    }
+
+    #[test]
+    fn parses_strict_exit() {
+        let cli = parse_args_from(args("cp2md -o out x.json")).unwrap();
+        assert!(!cli.strict_exit);
+
+        let cli = parse_args_from(args("cp2md --strict-exit -o out x.json")).unwrap();
+        assert!(cli.strict_exit);
+    }
+
+    #[test]
+    fn last_flag_wins() {
+        let cli = parse_args_from(args("cp2md --show-model --hide-model -o - x.json")).unwrap();
+        assert!(!cli.show_model);
+    }
+
+    #[test]
+    fn concat_toc_implies_concat() {
+        let cli = parse_args_from(args("cp2md --concat-toc -o out.md x.json")).unwrap();
+        assert!(cli.concat && cli.concat_toc);
+    }
+
+    #[test]
+    fn concat_title_implies_concat() {
+        let cli = parse_args_from(args("cp2md --concat-title Notes -o out.md x.json")).unwrap();
+        assert!(cli.concat);
+        assert_eq!(cli.concat_title.as_deref(), Some("Notes"));
+        assert!(matches!(cli.output, Some(OutputTarget::File(_))));
+    }
+
+    #[test]
+    fn parses_separator() {
+        let cli = parse_args_from(args("cp2md --concat -o out.md x.json")).unwrap();
+        assert_eq!(cli.separator.as_deref(), Some("---"));
+
+        let cli = parse_args_from(args(r"cp2md --separator \n***\n\t\\\x x.json")).unwrap();
+        assert_eq!(cli.separator.as_deref(), Some("\n***\n\t\\\\x"));
+
+        let cli = parse_args_from(args("cp2md --separator *** --no-separator x.json")).unwrap();
+        assert_eq!(cli.separator, None);
+        let cli = parse_args_from(args("cp2md --no-separator --separator *** x.json")).unwrap();
+        assert_eq!(cli.separator.as_deref(), Some("***"));
+    }
+}
@@ -0,0 +1,356 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Interactive terminal browser for discovered conversations.
+//!
+//! Available with the `tui` feature. Lists every input conversation, shows
+//! the rendered Markdown of the selected one in a scrollable pane, and writes
+//! it to the output directory on request.
+
+use super::run::{encode_output, is_unchanged, render_options_for};
+use super::{Cli, Error, TerminalSnafu};
+use cp2md::{parser, renderer};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Block, List, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use snafu::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Lines scrolled by Page Up / Page Down.
+const PAGE_LINES: u16 = 20;
+
+/// A chat listed in the browser.
+#[derive(Debug)]
+pub struct Conversation {
+    /// The chat as named in messages.
+    pub label: PathBuf,
+    /// Where writing the chat puts it.
+    pub out_path: PathBuf,
+    /// The chat, filtered and anonymized as a conversion would, or why it
+    /// could not be loaded.
+    pub chat: Result<parser::ChatExport, Error>,
+}
+
+/// The browser's state, independent of any terminal.
+#[derive(Debug)]
+struct BrowseState {
+    /// Conversations in display order.
+    conversations: Vec<Conversation>,
+    /// Rendered Markdown per conversation, filled on demand.
+    previews: Vec<Option<String>>,
+    /// Index of the selected conversation.
+    selected: usize,
+    /// Vertical scroll offset of the preview pane.
+    scroll: u16,
+    /// Message shown in the status line.
+    status: String,
+}
+
+impl BrowseState {
+    fn new(conversations: Vec<Conversation>) -> Self {
+        let previews = vec![None; conversations.len()];
+        Self {
+            conversations,
+            previews,
+            selected: 0,
+            scroll: 0,
+            status: "↑/↓ select · PgUp/PgDn scroll · w write · q quit".to_owned(),
+        }
+    }
+
+    fn selected_conversation(&self) -> Option<&Conversation> {
+        self.conversations.get(self.selected)
+    }
+
+    const fn select_next(&mut self) {
+        if self.selected + 1 < self.conversations.len() {
+            self.selected += 1;
+            self.scroll = 0;
+        }
+    }
+
+    const fn select_previous(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+            self.scroll = 0;
+        }
+    }
+
+    fn scroll_down(&mut self, lines: u16) {
+        let max = match self.preview() {
+            Some(Ok(text)) => u16::try_from(text.lines().count()).unwrap_or(u16::MAX),
+            _ => 0,
+        };
+        self.scroll = self.scroll.saturating_add(lines).min(max);
+    }
+
+    const fn scroll_up(&mut self, lines: u16) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    /// Renders the selected conversation if it loaded and has not been
+    /// rendered yet.
+    fn ensure_preview(&mut self, render: impl FnOnce(&Path, &parser::ChatExport) -> String) {
+        let Some(Conversation {
+            label,
+            chat: Ok(chat),
+            ..
+        }) = self.conversations.get(self.selected)
+        else {
+            return;
+        };
+        if self.previews[self.selected].is_none() {
+            self.previews[self.selected] = Some(render(label, chat));
+        }
+    }
+
+    /// Returns the selected conversation's rendered Markdown, or why it
+    /// could not be loaded, or `None` if nothing is selected or rendered.
+    fn preview(&self) -> Option<Result<&str, &Error>> {
+        match &self.selected_conversation()?.chat {
+            Ok(_) => self.previews[self.selected].as_deref().map(Ok),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Runs the interactive browser over `conversations` until the user quits.
+pub fn run(conversations: Vec<Conversation>, cli: &Cli) -> Result<(), Error> {
+    let mut state = BrowseState::new(conversations);
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut state, cli);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    state: &mut BrowseState,
+    cli: &Cli,
+) -> Result<(), Error> {
+    let render = |label: &Path, chat: &parser::ChatExport| {
+        renderer::render_chat(chat, &render_options_for(label, chat, cli))
+    };
+
+    loop {
+        state.ensure_preview(render);
+        terminal
+            .draw(|frame| draw(frame, state))
+            .context(TerminalSnafu)?;
+
+        let Event::Key(key) = event::read().context(TerminalSnafu)? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+            KeyCode::PageDown | KeyCode::Char(' ') => state.scroll_down(PAGE_LINES),
+            KeyCode::PageUp => state.scroll_up(PAGE_LINES),
+            KeyCode::Char('w') => state.status = write_selected(state, cli),
+            _ => {}
+        }
+    }
+}
+
+/// Writes the selected conversation and returns a status message.
+///
+/// A conversation that could not be loaded is not written.
+fn write_selected(state: &BrowseState, cli: &Cli) -> String {
+    let Some(conversation) = state.selected_conversation() else {
+        return "Nothing to write".to_owned();
+    };
+    let markdown = match state.preview() {
+        Some(Ok(markdown)) => markdown,
+        Some(Err(e)) => {
+            return format!("Not writing {}: {e}", conversation.label.display());
+        }
+        None => return "Nothing to write".to_owned(),
+    };
+    let out_path = &conversation.out_path;
+
+    if out_path.exists() && !cli.force && !cli.overwrite_if_changed {
+        return format!(
+            "Skipping {} (already exists, use --force to overwrite)",
+            out_path.display()
+        );
+    }
+
+    let markdown = renderer::convert_line_endings(markdown, cli.line_ending);
+    let markdown = encode_output(&markdown, cli);
+    if cli.overwrite_if_changed && is_unchanged(out_path, &markdown) {
+        return format!("Unchanged {}", out_path.display());
+    }
+    let out_dir = out_path.parent().unwrap_or_else(|| Path::new(""));
+    let written =
+        std::fs::create_dir_all(out_dir).and_then(|()| std::fs::write(out_path, markdown));
+    match written {
+        Ok(()) => format!("Wrote {}", out_path.display()),
+        Err(e) => format!("Error: failed to write {}: {e}", out_path.display()),
+    }
+}
+
+fn draw(frame: &mut Frame, state: &BrowseState) {
+    let [main, status] =
+        Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+    let [list_area, preview_area] =
+        Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(main);
+
+    // Output names tell the chats of an archive apart
+    let items = state.conversations.iter().map(|conversation| {
+        let path = &conversation.out_path;
+        path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |s| s.to_string_lossy().into_owned(),
+        )
+    });
+    let list = List::new(items)
+        .block(Block::bordered().title("Conversations"))
+        .highlight_style(Style::new().reversed())
+        .highlight_symbol("> ");
+    let mut list_state = ListState::default().with_selected(Some(state.selected));
+    frame.render_stateful_widget(list, list_area, &mut list_state);
+
+    let title = state
+        .selected_conversation()
+        .map_or_else(String::new, |c| c.label.display().to_string());
+    let text = match state.preview() {
+        Some(Ok(text)) => text.to_owned(),
+        Some(Err(e)) => format!("Error: {e}"),
+        None => String::new(),
+    };
+    let preview = Paragraph::new(text)
+        .block(Block::bordered().title(title))
+        .scroll((state.scroll, 0));
+    frame.render_widget(preview, preview_area);
+
+    frame.render_widget(Paragraph::new(state.status.as_str()), status);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::InvalidFilenameSnafu;
+    use crate::cli::args::parse_args_from;
+    use tempfile::TempDir;
+
+    fn chat() -> parser::ChatExport {
+        parser::parse_chat(
+            r#"{"requests": [{"message": {"text": "hi"}, "response": [{"value": "ok"}]}]}"#,
+        )
+        .unwrap()
+    }
+
+    fn state_with(names: &[&str]) -> BrowseState {
+        BrowseState::new(
+            names
+                .iter()
+                .map(|name| Conversation {
+                    label: PathBuf::from(name),
+                    out_path: Path::new(name).with_extension("md"),
+                    chat: Ok(chat()),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn selection_moves_within_bounds_and_resets_scroll() {
+        let mut state = state_with(&["a.json", "b.json"]);
+        state.previews[0] = Some("line\n".repeat(50));
+        state.scroll_down(10);
+        assert_eq!(state.scroll, 10);
+
+        state.select_next();
+        let selected = state.selected_conversation().unwrap();
+        assert_eq!(selected.label, Path::new("b.json"));
+        assert_eq!(state.scroll, 0);
+
+        state.select_next();
+        assert_eq!(state.selected, 1);
+
+        state.select_previous();
+        state.select_previous();
+        assert_eq!(state.selected, 0);
+    }
+
+    #[test]
+    fn scroll_is_clamped_to_preview_length() {
+        let mut state = state_with(&["a.json"]);
+        state.previews[0] = Some("one\ntwo\nthree".into());
+
+        state.scroll_down(PAGE_LINES);
+        assert_eq!(state.scroll, 3);
+        state.scroll_up(PAGE_LINES);
+        assert_eq!(state.scroll, 0);
+    }
+
+    #[test]
+    fn preview_is_rendered_once_per_selection() {
+        let mut state = state_with(&["a.json", "b.json"]);
+        let mut calls = 0;
+
+        state.ensure_preview(|label, _| {
+            calls += 1;
+            format!("rendered {}", label.display())
+        });
+        state.ensure_preview(|_, _| unreachable!("preview should be cached"));
+        assert!(matches!(state.preview(), Some(Ok("rendered a.json"))));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn chat_that_failed_to_load_previews_its_error_and_is_not_written() {
+        let dir = TempDir::new().unwrap();
+        let out_path = dir.path().join("bad.md");
+        let mut state = BrowseState::new(vec![Conversation {
+            label: PathBuf::from("bad.json"),
+            out_path: out_path.clone(),
+            chat: InvalidFilenameSnafu.fail(),
+        }]);
+        state.ensure_preview(|_, _| unreachable!("nothing to render"));
+        assert!(matches!(state.preview(), Some(Err(Error::InvalidFilename))));
+
+        let cli = parse_args_from(["cp2md", "--browse", "bad.json"]).unwrap();
+        assert_eq!(
+            write_selected(&state, &cli),
+            "Not writing bad.json: invalid input filename: no file stem"
+        );
+        assert!(!out_path.exists());
+    }
+
+    #[test]
+    fn writes_the_rendered_preview() {
+        let dir = TempDir::new().unwrap();
+        let out_path = dir.path().join("out").join("a.md");
+        let mut state = BrowseState::new(vec![Conversation {
+            label: PathBuf::from("a.json"),
+            out_path: out_path.clone(),
+            chat: Ok(chat()),
+        }]);
+        let cli = parse_args_from(["cp2md", "--browse", "a.json"]).unwrap();
+        assert_eq!(write_selected(&state, &cli), "Nothing to write");
+
+        state.ensure_preview(|_, _| "# Chat\n".to_owned());
+        assert_eq!(
+            write_selected(&state, &cli),
+            format!("Wrote {}", out_path.display())
+        );
+        assert_eq!(std::fs::read_to_string(&out_path).unwrap(), "# Chat\n");
+    }
+
+    #[test]
+    fn empty_state_has_no_selection() {
+        let mut state = state_with(&[]);
+        state.select_next();
+        state.ensure_preview(|_, _| unreachable!("nothing to render"));
+        assert!(state.selected_conversation().is_none());
+        assert!(state.preview().is_none());
+    }
+}
@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Coloring messages on stderr with ANSI escape codes.
+
+use super::args::Command;
+
+/// When to color messages on stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when stderr is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    /// Always color.
+    Always,
+    /// Never color.
+    Never,
+}
+
+impl ColorChoice {
+    /// Settles [`Self::Auto`] into [`Self::Always`] or [`Self::Never`].
+    ///
+    /// Automatic coloring needs stderr to be a terminal, and is turned off
+    /// by a non-empty `NO_COLOR` environment variable.
+    #[must_use]
+    pub fn resolve(self, is_terminal: bool) -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        match self {
+            Self::Auto if is_terminal && !no_color => Self::Always,
+            Self::Auto => Self::Never,
+            choice => choice,
+        }
+    }
+}
+
+impl Command {
+    /// Settles the command's `--color` choice, returning the result.
+    ///
+    /// Commands other than a conversion color as [`ColorChoice::Auto`] would.
+    pub fn resolve_color(&mut self, is_terminal: bool) -> ColorChoice {
+        match self {
+            Self::Convert(cli) => {
+                cli.color = cli.color.resolve(is_terminal);
+                cli.color
+            }
+            _ => ColorChoice::Auto.resolve(is_terminal),
+        }
+    }
+}
+
+/// What a message reports, which decides its color.
+#[derive(Clone, Copy, Debug)]
+pub enum Tone {
+    /// Something was done, such as writing a file.
+    Success,
+    /// Something was left alone.
+    Skip,
+    /// The run failed.
+    Error,
+}
+
+/// Wraps `text` in the color for `tone`, unless `color` is
+/// [`ColorChoice::Always`].
+///
+/// An unresolved [`ColorChoice::Auto`] is left uncolored, since whether the
+/// text is going to a terminal is not known here.
+pub fn paint(text: &str, tone: Tone, color: ColorChoice) -> String {
+    if color != ColorChoice::Always {
+        return text.to_owned();
+    }
+    let code = match tone {
+        Tone::Success => "32",
+        Tone::Skip => "33",
+        Tone::Error => "31",
+    };
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paints_only_when_always() {
+        assert_eq!(
+            paint("Wrote a.md", Tone::Success, ColorChoice::Always),
+            "\x1b[32mWrote a.md\x1b[0m"
+        );
+        for color in [ColorChoice::Auto, ColorChoice::Never] {
+            assert_eq!(paint("Wrote a.md", Tone::Success, color), "Wrote a.md");
+        }
+    }
+
+    #[test]
+    fn explicit_choices_ignore_the_terminal() {
+        for is_terminal in [false, true] {
+            assert_eq!(
+                ColorChoice::Always.resolve(is_terminal),
+                ColorChoice::Always
+            );
+            assert_eq!(ColorChoice::Never.resolve(is_terminal), ColorChoice::Never);
+        }
+        assert_eq!(ColorChoice::Auto.resolve(false), ColorChoice::Never);
+    }
+}
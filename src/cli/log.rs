@@ -0,0 +1,360 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Reporting progress on stderr, as text or as JSON lines.
+//!
+//! Every message a run writes to stderr is an [`Event`], so the two formats
+//! always report the same things.
+
+use super::args::Command;
+use super::run::RunSummary;
+use super::{ColorChoice, Error, Tone, paint};
+use serde_json::{Value, json};
+use std::fmt;
+use std::path::Path;
+
+/// How messages on stderr are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Lines of text for people.
+    #[default]
+    Human,
+    /// One JSON object per line.
+    Json,
+}
+
+impl Command {
+    /// Returns the command's `--log-format`.
+    ///
+    /// Commands other than a conversion log as [`LogFormat::Human`].
+    pub const fn log_format(&self) -> LogFormat {
+        match self {
+            Self::Convert(cli) => cli.log_format,
+            _ => LogFormat::Human,
+        }
+    }
+}
+
+/// Why an input or output was left alone.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum SkipReason<'a> {
+    /// The output already exists.
+    Exists,
+    /// The chat has no requests to show.
+    Empty,
+    /// A file found by `--scan-vscode` is not a chat.
+    NotChat,
+    /// `--only-agent` left no requests.
+    NoAgentRequests(&'a str),
+}
+
+/// What went into a concatenated output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct ConcatCounts {
+    /// Inputs included.
+    pub files: usize,
+    /// Exchanges rendered across the included inputs.
+    pub exchanges: usize,
+    /// Inputs left out.
+    pub skipped: usize,
+}
+
+impl fmt::Display for ConcatCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files = if self.files == 1 { "file" } else { "files" };
+        let noun = if self.exchanges == 1 {
+            "exchange"
+        } else {
+            "exchanges"
+        };
+        write!(f, "{} {files}, {} {noun}", self.files, self.exchanges)?;
+        if self.skipped > 0 {
+            write!(f, ", {} skipped", self.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+/// Something a run reports on stderr.
+#[derive(Debug)]
+pub(super) enum Event<'a> {
+    /// An output file was written, from `input` when it has just one.
+    Wrote {
+        input: Option<&'a Path>,
+        output: &'a Path,
+        bytes: usize,
+        concat: Option<ConcatCounts>,
+    },
+    /// An input, or for [`SkipReason::Exists`] an output, was left alone.
+    Skipped {
+        path: &'a Path,
+        reason: SkipReason<'a>,
+    },
+    /// An output was left alone because its content would not change.
+    Unchanged { output: &'a Path },
+    /// Something unusual about an input's shape.
+    Warning { input: &'a Path, message: String },
+    /// What `--dry-run` would have done; no `output` means stdout.
+    DryRun {
+        input: Option<&'a Path>,
+        output: Option<&'a Path>,
+        inputs: Option<usize>,
+    },
+    /// Two inputs would have been written to `output`: `first`'s output
+    /// keeps it, and `input`'s is written to `renamed`, or over it when
+    /// there is none.
+    Collision {
+        input: &'a Path,
+        output: &'a Path,
+        first: &'a Path,
+        renamed: Option<&'a Path>,
+    },
+    /// Where in an input a JSON error happened, for `--pretty-errors`.
+    ParseError { input: &'a Path, message: String },
+    /// What the run did, reported once it finishes.
+    Summary(RunSummary),
+}
+
+impl Event<'_> {
+    /// Returns the line a person reads, with the tone to color it in.
+    ///
+    /// Returns `None` for events only reported as JSON.
+    fn human(&self) -> Option<(Option<Tone>, String)> {
+        let line = match self {
+            Self::Wrote {
+                output,
+                concat: None,
+                ..
+            } => (Some(Tone::Success), format!("Wrote {}", output.display())),
+            Self::Wrote {
+                output,
+                concat: Some(counts),
+                ..
+            } => (
+                Some(Tone::Success),
+                format!("Wrote {} ({counts})", output.display()),
+            ),
+            Self::Skipped { path, reason } => {
+                let why = match reason {
+                    SkipReason::Exists => "already exists, use --force to overwrite".to_owned(),
+                    SkipReason::Empty => "empty chat, use --keep-empty to convert it".to_owned(),
+                    SkipReason::NotChat => "not a chat session".to_owned(),
+                    SkipReason::NoAgentRequests(agent) => {
+                        format!("no requests handled by @{agent}")
+                    }
+                };
+                (
+                    Some(Tone::Skip),
+                    format!("Skipping {} ({why})", path.display()),
+                )
+            }
+            Self::Unchanged { output } => (None, format!("Unchanged {}", output.display())),
+            Self::Warning { input, message } => {
+                (None, format!("Warning: {}: {message}", input.display()))
+            }
+            Self::DryRun {
+                input,
+                output,
+                inputs,
+            } => {
+                let text = match (output, input, inputs) {
+                    (Some(output), _, Some(inputs)) => format!(
+                        "Would write {} ({inputs} files concatenated)",
+                        output.display()
+                    ),
+                    (Some(output), _, None) => format!("Would write {}", output.display()),
+                    (None, Some(input), _) => format!("Would output {}", input.display()),
+                    (None, None, inputs) => {
+                        format!("Would output {} files concatenated", inputs.unwrap_or(0))
+                    }
+                };
+                (None, text)
+            }
+            Self::Collision {
+                input,
+                output,
+                first,
+                renamed: Some(renamed),
+            } => (
+                None,
+                format!(
+                    "Writing {} to {} ({} is taken by {})",
+                    input.display(),
+                    renamed.display(),
+                    output.display(),
+                    first.display()
+                ),
+            ),
+            Self::Collision {
+                input,
+                output,
+                first,
+                renamed: None,
+            } => (
+                None,
+                format!(
+                    "Warning: {} overwrites {}, written from {}",
+                    input.display(),
+                    output.display(),
+                    first.display()
+                ),
+            ),
+            Self::ParseError { input, message } => {
+                (None, format!("{}: {message}", input.display()))
+            }
+            Self::Summary(_) => return None,
+        };
+        Some(line)
+    }
+
+    /// Returns the event as a JSON object, as documented in the README.
+    fn json(&self) -> Value {
+        match self {
+            Self::Wrote {
+                input,
+                output,
+                bytes,
+                concat,
+            } => {
+                let mut value = json!({ "event": "wrote", "output": path(output), "bytes": bytes });
+                if let Some(input) = input {
+                    value["input"] = path(input);
+                }
+                if let Some(counts) = concat {
+                    value["files"] = json!(counts.files);
+                    value["exchanges"] = json!(counts.exchanges);
+                    value["skipped"] = json!(counts.skipped);
+                }
+                value
+            }
+            Self::Skipped {
+                path: skipped,
+                reason,
+            } => {
+                let (name, key) = match reason {
+                    SkipReason::Exists => ("exists", "output"),
+                    SkipReason::Empty => ("empty", "input"),
+                    SkipReason::NotChat => ("not-chat", "input"),
+                    SkipReason::NoAgentRequests(_) => ("no-agent-requests", "input"),
+                };
+                let mut value = json!({ "event": "skipped", "reason": name, key: path(skipped) });
+                if let SkipReason::NoAgentRequests(agent) = reason {
+                    value["agent"] = json!(agent);
+                }
+                value
+            }
+            Self::Unchanged { output } => json!({ "event": "unchanged", "output": path(output) }),
+            Self::Warning { input, message } => {
+                json!({ "event": "warning", "input": path(input), "message": message })
+            }
+            Self::DryRun {
+                input,
+                output,
+                inputs,
+            } => {
+                let mut value = json!({
+                    "event": "dry-run",
+                    "output": output.map_or_else(|| json!("-"), path),
+                });
+                if let Some(input) = input {
+                    value["input"] = path(input);
+                }
+                if let Some(inputs) = inputs {
+                    value["inputs"] = json!(inputs);
+                }
+                value
+            }
+            Self::Collision {
+                input,
+                output,
+                first,
+                renamed,
+            } => {
+                let mut value = json!({
+                    "event": "collision",
+                    "input": path(input),
+                    "output": path(output),
+                    "first": path(first),
+                });
+                if let Some(renamed) = renamed {
+                    value["renamed"] = path(renamed);
+                }
+                value
+            }
+            Self::ParseError { input, message } => {
+                json!({ "event": "parse-error", "input": path(input), "message": message })
+            }
+            Self::Summary(summary) => json!({
+                "event": "summary",
+                "written": summary.written,
+                "skipped_existing": summary.skipped_existing,
+                "unchanged": summary.unchanged,
+                "skipped_empty": summary.skipped_empty,
+            }),
+        }
+    }
+
+    /// Returns the event as a line in `format`, colored per `color` for people;
+    /// `None` when nothing is written.
+    pub fn line(&self, format: LogFormat, color: ColorChoice) -> Option<String> {
+        match format {
+            LogFormat::Human => {
+                let (tone, text) = self.human()?;
+                Some(tone.map_or_else(|| text.clone(), |tone| paint(&text, tone, color)))
+            }
+            LogFormat::Json => Some(self.json().to_string()),
+        }
+    }
+}
+
+/// Returns a path as a JSON string, with anything not UTF-8 replaced.
+fn path(path: &Path) -> Value {
+    json!(path.to_string_lossy())
+}
+
+/// Returns the line reporting that a run failed with `error`.
+pub fn error_line(error: &Error, format: LogFormat, color: ColorChoice) -> String {
+    match format {
+        LogFormat::Human => paint(&format!("Error: {error:?}"), Tone::Error, color),
+        LogFormat::Json => json!({
+            "event": "error",
+            "message": error.to_string(),
+            "exit_code": error.exit_code(),
+        })
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_events_carry_their_paths() {
+        let event = Event::Skipped {
+            path: Path::new("out/a.md"),
+            reason: SkipReason::Exists,
+        };
+        let value: Value =
+            serde_json::from_str(&event.line(LogFormat::Json, ColorChoice::Never).unwrap())
+                .unwrap();
+        assert_eq!(
+            value,
+            json!({ "event": "skipped", "reason": "exists", "output": "out/a.md" })
+        );
+        assert_eq!(
+            event.line(LogFormat::Human, ColorChoice::Never).unwrap(),
+            "Skipping out/a.md (already exists, use --force to overwrite)"
+        );
+    }
+
+    #[test]
+    fn summary_is_only_written_as_json() {
+        let event = Event::Summary(RunSummary::default());
+        assert_eq!(event.line(LogFormat::Human, ColorChoice::Never), None);
+        let line = event.line(LogFormat::Json, ColorChoice::Never).unwrap();
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "summary");
+        assert_eq!(value["written"], 0);
+    }
+}
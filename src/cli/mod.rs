@@ -0,0 +1,394 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! The `cp2md` command.
+//!
+//! [`parse_args`] turns the command line into a command without touching
+//! the environment, and [`run`] carries it out, writing to the streams it is
+//! given. `main` only connects the two to the process.
+
+use cp2md::{parser, renderer};
+use snafu::prelude::*;
+use std::path::PathBuf;
+
+mod args;
+#[cfg(feature = "tui")]
+mod browse;
+mod color;
+mod log;
+mod run;
+
+pub use args::parse_args;
+pub use color::{ColorChoice, Tone, paint};
+pub use log::{LogFormat, error_line};
+pub use run::run;
+
+/// Where to write the rendered output.
+#[derive(Clone, Debug)]
+enum OutputTarget {
+    /// Write each file to the specified directory.
+    Directory(PathBuf),
+    /// Write concatenated output to a single file.
+    File(PathBuf),
+    /// Write to stdout.
+    Stdout,
+}
+
+/// The character encoding of written output files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputEncoding {
+    /// UTF-8, written as rendered.
+    #[default]
+    Utf8,
+    /// Latin-1 (ISO-8859-1): the first 256 code points, one byte each.
+    Latin1,
+}
+
+/// What to do when two inputs would be written to the same output file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum CollisionPolicy {
+    /// Fail before writing the second output.
+    Error,
+    /// Write the second output under a name made from its input's
+    /// directory, or a number.
+    #[default]
+    Rename,
+    /// Write the second output over the first.
+    Overwrite,
+}
+
+/// Options parsed from the command line.
+#[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Cli {
+    input: Vec<PathBuf>,
+    /// File name suffixes, without the leading dot, that directory inputs
+    /// are searched for.
+    extensions: Vec<String>,
+    /// Where to write output; `None` writes next to a single input file.
+    output: Option<OutputTarget>,
+    concat: bool,
+    concat_toc: bool,
+    concat_per_dir: bool,
+    concat_title: Option<String>,
+    /// Text between concatenated chats; `None` leaves only a blank line.
+    separator: Option<String>,
+    show_tools: bool,
+    show_timestamps: bool,
+    timestamp_style: renderer::TimestampStyle,
+    show_model: bool,
+    friendly_model_names: bool,
+    show_agent: bool,
+    show_context: bool,
+    context_kinds: renderer::ContextKindFilter,
+    context_position: renderer::ContextPosition,
+    context_index: bool,
+    show_selection_text: bool,
+    dedupe_context: bool,
+    hoist_instructions: bool,
+    summary_only: bool,
+    dedupe_metadata: bool,
+    metadata_mode: renderer::MetadataMode,
+    metadata_alert: bool,
+    heading_offset: u8,
+    deep_headings: renderer::DeepHeadingStyle,
+    edit_summary: bool,
+    edits_section: bool,
+    badges: bool,
+    show_usage: bool,
+    agent_speaker: bool,
+    nav: bool,
+    anonymize: bool,
+    anonymize_map: Option<PathBuf>,
+    only_agent: Option<String>,
+    redact_patterns: Vec<String>,
+    preview: Option<usize>,
+    max_tool_len: Option<usize>,
+    truncate: Option<usize>,
+    wrap: Option<usize>,
+    code_indent: renderer::CodeIndent,
+    quote_user: bool,
+    reflow_paragraphs: bool,
+    title_from_message: bool,
+    document_heading: bool,
+    show_annotations: bool,
+    line_ending: renderer::LineEnding,
+    output_encoding: OutputEncoding,
+    /// Written in place of characters the output encoding cannot represent.
+    encoding_fallback: String,
+    path_style: renderer::PathStyle,
+    path_display: renderer::PathDisplay,
+    embed_images: bool,
+    repo_permalink: Option<renderer::RepoInfo>,
+    /// Longest selection excerpt read from disk, if any are.
+    embed_selections: Option<usize>,
+    front_matter: Option<renderer::FrontMatterFormat>,
+    debug_unknown: bool,
+    source_comment: bool,
+    scan_vscode: bool,
+    browse: bool,
+    validate: bool,
+    detect: bool,
+    /// Count what each chat holds, for `cp2md stats`.
+    stats: bool,
+    /// Compare two chats, for `cp2md diff`.
+    diff: bool,
+    /// List VS Code chat sessions, for `cp2md discover`.
+    discover: bool,
+    pretty_errors: bool,
+    lenient_json: bool,
+    strict: bool,
+    timestamp_unit: parser::TimestampUnit,
+    color: ColorChoice,
+    log_format: LogFormat,
+    quiet: bool,
+    dry_run: bool,
+    force: bool,
+    overwrite_if_changed: bool,
+    on_collision: CollisionPolicy,
+    strict_exit: bool,
+    keep_empty: bool,
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(super)))]
+pub enum Error {
+    #[snafu(display("failed to parse arguments: {source}"))]
+    ParseArgs { source: lexopt::Error },
+
+    #[snafu(display("heading-offset must be 0-5"))]
+    InvalidHeadingOffset,
+
+    #[snafu(display("line-ending must be lf or crlf (got {value})"))]
+    InvalidLineEnding { value: String },
+
+    #[snafu(display("output-encoding must be utf-8 or latin-1 (got {value})"))]
+    InvalidOutputEncoding { value: String },
+
+    #[snafu(display("encoding-fallback must be ASCII (got {value:?})"))]
+    InvalidEncodingFallback { value: String },
+
+    #[snafu(display("frontmatter-format must be yaml or toml (got {value})"))]
+    InvalidFrontMatterFormat { value: String },
+
+    #[snafu(display("timestamps must be absolute, iso, or relative (got {value})"))]
+    InvalidTimestampStyle { value: String },
+
+    #[snafu(display("timestamp-unit must be auto, millis, or seconds (got {value})"))]
+    InvalidTimestampUnit { value: String },
+
+    #[snafu(display("deep-headings must be clamp or bold (got {value})"))]
+    InvalidDeepHeadings { value: String },
+
+    #[snafu(display("color must be auto, always, or never (got {value})"))]
+    InvalidColor { value: String },
+
+    #[snafu(display("log-format must be human or json (got {value})"))]
+    InvalidLogFormat { value: String },
+
+    #[snafu(display("on-collision must be error, rename, or overwrite (got {value})"))]
+    InvalidCollisionPolicy { value: String },
+
+    #[snafu(display("ext must be a file name suffix such as json (got {value:?})"))]
+    InvalidExtension { value: String },
+
+    #[snafu(display("metadata must be always, on-change, or first (got {value})"))]
+    InvalidMetadataMode { value: String },
+
+    #[snafu(display(
+        "context kinds must be file, selection, folder, instructions, pasted, or terminal (got {value})"
+    ))]
+    InvalidContextKind { value: String },
+
+    #[snafu(display("context-position must be before or after (got {value})"))]
+    InvalidContextPosition { value: String },
+
+    #[snafu(display("path-style must be link or footnote (got {value})"))]
+    InvalidPathStyle { value: String },
+
+    #[snafu(display("path-display must be auto[:N], full, short, or name (got {value})"))]
+    InvalidPathDisplay { value: String },
+
+    #[snafu(display("code-indent must be keep, tabs[:N], or spaces[:N] (got {value})"))]
+    InvalidCodeIndent { value: String },
+
+    #[snafu(display("invalid --redact pattern {pattern:?}: {source}"))]
+    InvalidRedactPattern {
+        pattern: String,
+        source: regex::Error,
+    },
+
+    #[snafu(display("--repo-url and --ref must be given together"))]
+    IncompleteRepo,
+
+    #[snafu(display("selection-lines must be at least 1"))]
+    InvalidSelectionLines,
+
+    #[snafu(display("wrap width must be at least 1"))]
+    InvalidWrapWidth,
+
+    #[snafu(display("max tool message length must be at least 1"))]
+    InvalidToolLength,
+
+    #[snafu(display("message truncation length must be at least 1"))]
+    InvalidMessageLength,
+
+    #[snafu(display("--{first} cannot be combined with --{second}"))]
+    ConflictingModes {
+        first: &'static str,
+        second: &'static str,
+    },
+
+    #[snafu(display(
+        "missing required option: --output (only a single input file can be converted without it)"
+    ))]
+    MissingOutput,
+
+    #[snafu(display("failed to list inputs under {}: {source}", path.display()))]
+    ListInputs {
+        path: PathBuf,
+        source: walkdir::Error,
+    },
+
+    #[snafu(display("no VS Code chat storage found under {}", path.display()))]
+    NoVscodeStorage { path: PathBuf },
+
+    #[snafu(display("at least one input file or directory is required"))]
+    NoInputFiles,
+
+    #[snafu(display("{failed} of {total} inputs failed validation"))]
+    ValidationFailed { failed: usize, total: usize },
+
+    #[snafu(display("cannot output multiple files to stdout without --concat"))]
+    MultipleFilesToStdout,
+
+    #[snafu(display(
+        "cannot output the chats in {} to stdout without --concat",
+        path.display()
+    ))]
+    MultipleChatsToStdout { path: PathBuf },
+
+    #[snafu(display("diff compares two chats, but {count} were given"))]
+    DiffNeedsTwoChats { count: usize },
+
+    #[snafu(display("failed to create output directory: {source}"))]
+    CreateOutputDir { source: std::io::Error },
+
+    #[snafu(display("failed to read {}: {source}", path.display()))]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("failed to parse {}: {source}", path.display()))]
+    ParseFile {
+        path: PathBuf,
+        source: parser::ParseError,
+    },
+
+    #[snafu(display("invalid input filename: no file stem"))]
+    InvalidFilename,
+
+    #[snafu(display("failed to write {}: {source}", path.display()))]
+    WriteFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("file output requires --concat (got {})", path.display()))]
+    FileOutputRequiresConcat { path: PathBuf },
+
+    #[snafu(display("--nav writes index.md, which conflicts with input {}", path.display()))]
+    NavIndexConflict { path: PathBuf },
+
+    #[snafu(display(
+        "inputs {} and {} would both be written to {} (see --on-collision)",
+        first.display(),
+        second.display(),
+        path.display()
+    ))]
+    OutputCollision {
+        path: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+
+    #[snafu(display("failed to serialize anonymization map: {source}"))]
+    SerializeAnonymizeMap { source: serde_json::Error },
+
+    #[snafu(display("--browse requires cp2md to be built with the `tui` feature"))]
+    BrowseUnavailable,
+
+    #[cfg(feature = "tui")]
+    #[snafu(display("terminal error: {source}"))]
+    Terminal { source: std::io::Error },
+
+    #[snafu(display("failed to write to the console: {source}"))]
+    Console { source: std::io::Error },
+
+    #[snafu(display("{count} outputs skipped because they already exist (--strict-exit)"))]
+    OutputsSkipped { count: usize },
+}
+
+impl Error {
+    /// Returns the process exit status for this error, as listed in `--help`.
+    pub const fn exit_code(&self) -> u8 {
+        match self {
+            Self::ParseArgs { .. }
+            | Self::InvalidHeadingOffset
+            | Self::InvalidLineEnding { .. }
+            | Self::InvalidOutputEncoding { .. }
+            | Self::InvalidEncodingFallback { .. }
+            | Self::InvalidFrontMatterFormat { .. }
+            | Self::InvalidTimestampStyle { .. }
+            | Self::InvalidTimestampUnit { .. }
+            | Self::InvalidDeepHeadings { .. }
+            | Self::InvalidColor { .. }
+            | Self::InvalidLogFormat { .. }
+            | Self::InvalidCollisionPolicy { .. }
+            | Self::InvalidExtension { .. }
+            | Self::InvalidMetadataMode { .. }
+            | Self::InvalidContextKind { .. }
+            | Self::InvalidContextPosition { .. }
+            | Self::InvalidPathStyle { .. }
+            | Self::InvalidPathDisplay { .. }
+            | Self::InvalidCodeIndent { .. }
+            | Self::InvalidRedactPattern { .. }
+            | Self::IncompleteRepo
+            | Self::InvalidSelectionLines
+            | Self::InvalidWrapWidth
+            | Self::InvalidToolLength
+            | Self::InvalidMessageLength
+            | Self::ConflictingModes { .. }
+            | Self::MissingOutput
+            | Self::NoInputFiles
+            | Self::MultipleFilesToStdout
+            | Self::MultipleChatsToStdout { .. }
+            | Self::DiffNeedsTwoChats { .. }
+            | Self::FileOutputRequiresConcat { .. }
+            | Self::NavIndexConflict { .. }
+            | Self::OutputCollision { .. }
+            | Self::BrowseUnavailable => 1,
+            Self::ListInputs { .. }
+            | Self::NoVscodeStorage { .. }
+            | Self::ReadFile { .. }
+            | Self::InvalidFilename => 2,
+            Self::ParseFile { .. } | Self::ValidationFailed { .. } => 3,
+            Self::CreateOutputDir { .. }
+            | Self::WriteFile { .. }
+            | Self::SerializeAnonymizeMap { .. }
+            | Self::Console { .. }
+            | Self::OutputsSkipped { .. } => 4,
+            #[cfg(feature = "tui")]
+            Self::Terminal { .. } => 4,
+        }
+    }
+
+    /// Returns whether this error is the console going away mid-write, as
+    /// when output is piped into `head`.
+    ///
+    /// That ends the run early but is not a failure, so nothing is reported.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, Self::Console { source } if source.kind() == std::io::ErrorKind::BrokenPipe)
+    }
+}
@@ -0,0 +1,3402 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Carrying out a parsed command.
+
+use super::args::{Command, Subcommand, overview};
+use super::log::{ConcatCounts, Event, SkipReason};
+use super::{
+    Cli, CollisionPolicy, ConsoleSnafu, CreateOutputDirSnafu, DiffNeedsTwoChatsSnafu, Error,
+    FileOutputRequiresConcatSnafu, InvalidFilenameSnafu, ListInputsSnafu, MissingOutputSnafu,
+    MultipleChatsToStdoutSnafu, MultipleFilesToStdoutSnafu, NavIndexConflictSnafu,
+    NoInputFilesSnafu, NoVscodeStorageSnafu, OutputCollisionSnafu, OutputEncoding, OutputTarget,
+    OutputsSkippedSnafu, ParseFileSnafu, ReadFileSnafu, SerializeAnonymizeMapSnafu,
+    ValidationFailedSnafu, WriteFileSnafu,
+};
+use cp2md::{anonymize, parser, renderer};
+use snafu::{OptionExt, ensure, prelude::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// File name of the index written by `--nav`.
+const NAV_INDEX: &str = "index.md";
+
+/// Largest file `--embed-selections` reads a selection from.
+const MAX_SELECTION_SOURCE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Anonymization mappings for each converted input, keyed by input path.
+type AnonymizeMaps = BTreeMap<PathBuf, anonymize::AnonymizeMap>;
+
+/// A chat read from an input: the whole file, or one line of a JSONL
+/// archive.
+struct Entry {
+    /// The chat as named in messages; see [`entry_label`].
+    label: PathBuf,
+    /// The archive line the chat was read from.
+    line: Option<usize>,
+    chat: parser::ChatExport,
+}
+
+/// What a run did with its inputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Output files written, including any index or anonymization map.
+    pub written: usize,
+    /// Output files left alone because they already existed.
+    pub skipped_existing: usize,
+    /// Output files left alone because their content would not change.
+    pub unchanged: usize,
+    /// Inputs not converted because they hold no requests to show.
+    pub skipped_empty: usize,
+}
+
+/// Carries out `command`, writing output to `out` and progress to `err`.
+///
+/// A conversion ends by reporting its [`RunSummary`], which only
+/// `--log-format json` writes.
+///
+/// # Errors
+///
+/// Returns an error if inputs cannot be found, read, or parsed, or if
+/// output cannot be written.
+pub fn run(
+    command: &Command,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> Result<RunSummary, Error> {
+    let cli = match command {
+        Command::Convert(cli) => cli,
+        Command::Help(subcommand) => {
+            let text = subcommand.map_or_else(overview, Subcommand::help);
+            writeln!(out, "{text}").context(ConsoleSnafu)?;
+            return Ok(RunSummary::default());
+        }
+        Command::Version => {
+            writeln!(
+                out,
+                "{} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )
+            .context(ConsoleSnafu)?;
+            return Ok(RunSummary::default());
+        }
+        Command::DumpSchema => {
+            write!(out, "{}", parser::EXPORT_SCHEMA).context(ConsoleSnafu)?;
+            return Ok(RunSummary::default());
+        }
+    };
+
+    let mut runner = Runner::new(cli, out, err);
+    runner.convert()?;
+    runner.log(&Event::Summary(runner.summary))?;
+
+    let count = runner.summary.skipped_existing;
+    ensure!(
+        !cli.strict_exit || count == 0,
+        OutputsSkippedSnafu { count }
+    );
+    Ok(runner.summary)
+}
+
+/// A run in progress: its options, where it reports, and what it has done.
+struct Runner<'a> {
+    cli: &'a Cli,
+    /// Where rendered Markdown and validation reports go.
+    out: &'a mut dyn Write,
+    /// Where progress messages and warnings go.
+    err: &'a mut dyn Write,
+    maps: AnonymizeMaps,
+    summary: RunSummary,
+    /// The input each output name was given to, keyed by [`output_key`].
+    claimed: HashMap<String, PathBuf>,
+    /// Outputs written so far, which later ones may replace.
+    written: HashSet<PathBuf>,
+}
+
+impl<'a> Runner<'a> {
+    fn new(cli: &'a Cli, out: &'a mut dyn Write, err: &'a mut dyn Write) -> Self {
+        Self {
+            cli,
+            out,
+            err,
+            maps: AnonymizeMaps::new(),
+            summary: RunSummary::default(),
+            claimed: HashMap::new(),
+            written: HashSet::new(),
+        }
+    }
+
+    /// Collects the inputs and converts them as the options say.
+    fn convert(&mut self) -> Result<(), Error> {
+        let cli = self.cli;
+        ensure!(!cli.input.is_empty(), NoInputFilesSnafu);
+
+        // Collect all input files first
+        let groups = if cli.scan_vscode {
+            collect_vscode_sessions(&cli.input)?
+        } else {
+            collect_input_files(&cli.input, &cli.extensions)?
+        };
+        let files: Vec<PathBuf> = groups
+            .iter()
+            .flat_map(|group| group.files.iter().cloned())
+            .collect();
+
+        if cli.validate {
+            return self.validate_files(&files);
+        }
+
+        if cli.detect {
+            return self.detect_formats(&files);
+        }
+
+        if cli.stats {
+            return self.print_stats(&files);
+        }
+
+        if cli.diff {
+            return self.print_diff(&files);
+        }
+
+        if cli.discover {
+            return self.list_sessions(&files);
+        }
+
+        if cli.browse {
+            self.browse(&files)?;
+            return self.write_anonymize_sidecar();
+        }
+
+        let output = resolve_output(cli, &files)?;
+        if cli.concat {
+            self.process_concat(&files, &output)?;
+        } else {
+            match &output {
+                OutputTarget::Stdout => {
+                    // Without concat, we can only output one file to stdout
+                    ensure!(files.len() == 1, MultipleFilesToStdoutSnafu);
+                    self.process_to_stdout(&files[0])?;
+                }
+                OutputTarget::Directory(dir) => {
+                    if !cli.dry_run {
+                        std::fs::create_dir_all(dir).context(CreateOutputDirSnafu)?;
+                    }
+                    if cli.concat_per_dir {
+                        self.process_concat_per_dir(&groups, dir)?;
+                    } else if cli.nav {
+                        self.process_with_nav(&files, dir)?;
+                    } else {
+                        for file in &files {
+                            self.process_file(file, dir)?;
+                        }
+                    }
+                }
+                OutputTarget::File(path) => {
+                    return FileOutputRequiresConcatSnafu { path: path.clone() }.fail();
+                }
+            }
+        }
+
+        self.write_anonymize_sidecar()
+    }
+
+    /// Writes the `--anonymize-map` sidecar for the chats anonymized so far.
+    fn write_anonymize_sidecar(&mut self) -> Result<(), Error> {
+        let cli = self.cli;
+        if let Some(path) = &cli.anonymize_map
+            && !cli.dry_run
+        {
+            let bytes = write_anonymize_map(path, &self.maps)?;
+            self.wrote(None, path, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the interactive browser over the chats in `files`, writing into
+    /// the output directory.
+    #[cfg(feature = "tui")]
+    fn browse(&mut self, files: &[PathBuf]) -> Result<(), Error> {
+        let conversations = self.browse_conversations(files)?;
+        super::browse::run(conversations, self.cli)
+    }
+
+    /// Loads the chats in `files` as a conversion would, each with where the
+    /// browser writes it. An input that cannot be loaded is listed with its
+    /// error.
+    #[cfg(feature = "tui")]
+    fn browse_conversations(
+        &mut self,
+        files: &[PathBuf],
+    ) -> Result<Vec<super::browse::Conversation>, Error> {
+        use super::browse::Conversation;
+
+        let out_dir = match &self.cli.output {
+            Some(OutputTarget::Directory(dir)) => dir.clone(),
+            Some(OutputTarget::File(path)) => {
+                return FileOutputRequiresConcatSnafu { path: path.clone() }.fail();
+            }
+            Some(OutputTarget::Stdout) | None => PathBuf::from("."),
+        };
+        let mut conversations = Vec::with_capacity(files.len());
+        for file in files {
+            match self.prepare_chats(file) {
+                Ok((entries, _)) => {
+                    for entry in entries {
+                        conversations.push(Conversation {
+                            out_path: output_path_for(file, entry.line, &out_dir)?,
+                            label: entry.label,
+                            chat: Ok(entry.chat),
+                        });
+                    }
+                }
+                Err(error) => conversations.push(Conversation {
+                    label: file.clone(),
+                    out_path: output_path_for(file, None, &out_dir)?,
+                    chat: Err(error),
+                }),
+            }
+        }
+        Ok(conversations)
+    }
+
+    /// Reports that the interactive browser was not compiled in.
+    #[cfg(not(feature = "tui"))]
+    #[allow(clippy::unused_self)]
+    fn browse(&self, _files: &[PathBuf]) -> Result<(), Error> {
+        super::BrowseUnavailableSnafu.fail()
+    }
+
+    /// Writes `event` to stderr in the `--log-format`.
+    fn log(&mut self, event: &Event) -> Result<(), Error> {
+        let Some(line) = event.line(self.cli.log_format, self.cli.color) else {
+            return Ok(());
+        };
+        writeln!(self.err, "{line}").context(ConsoleSnafu)
+    }
+
+    /// Counts a written file of `bytes`, from `input` if it has just one, and
+    /// says so unless `--quiet`.
+    fn wrote(&mut self, input: Option<&Path>, output: &Path, bytes: usize) -> Result<(), Error> {
+        self.summary.written += 1;
+        if self.cli.quiet {
+            return Ok(());
+        }
+        self.log(&Event::Wrote {
+            input,
+            output,
+            bytes,
+            concat: None,
+        })
+    }
+
+    /// Counts an output left alone because it exists, and says so.
+    fn skipped_existing(&mut self, path: &Path) -> Result<(), Error> {
+        self.summary.skipped_existing += 1;
+        self.log(&Event::Skipped {
+            path,
+            reason: SkipReason::Exists,
+        })
+    }
+
+    /// Counts an output left alone because it would not change, and says so
+    /// unless `--quiet`.
+    fn unchanged(&mut self, path: &Path) -> Result<(), Error> {
+        self.summary.unchanged += 1;
+        if self.cli.quiet {
+            return Ok(());
+        }
+        self.log(&Event::Unchanged { output: path })
+    }
+
+    /// Counts an input left out because it has no requests to show, and says
+    /// so unless `--quiet`.
+    fn skipped_empty(&mut self, path: &Path) -> Result<(), Error> {
+        self.summary.skipped_empty += 1;
+        if self.cli.quiet {
+            return Ok(());
+        }
+        self.log(&Event::Skipped {
+            path,
+            reason: SkipReason::Empty,
+        })
+    }
+
+    /// Says what `--dry-run` would write to `output`, or to stdout when
+    /// there is none.
+    fn dry_run(
+        &mut self,
+        input: Option<&Path>,
+        output: Option<&Path>,
+        inputs: Option<usize>,
+    ) -> Result<(), Error> {
+        self.log(&Event::DryRun {
+            input,
+            output,
+            inputs,
+        })
+    }
+
+    /// Claims `path` as the output for `input`, returning where to write it.
+    ///
+    /// When another input already has `path`, `--on-collision` decides
+    /// whether to fail, to rename this output, or to write over the first.
+    /// Renamed outputs add the input's directory name to the stem, as in
+    /// `chat-projB.md`, or else a number, as in `chat-2.md`. Claiming again
+    /// for the same input gives the same output.
+    fn claim_output(&mut self, input: &Path, path: PathBuf) -> Result<PathBuf, Error> {
+        let first = match self.claimed.get(&output_key(&path)) {
+            None => {
+                self.claimed.insert(output_key(&path), input.to_owned());
+                return Ok(path);
+            }
+            Some(first) if first == input => return Ok(path),
+            Some(first) => first.clone(),
+        };
+        let renamed = match self.cli.on_collision {
+            CollisionPolicy::Error => {
+                return OutputCollisionSnafu {
+                    path,
+                    first,
+                    second: input,
+                }
+                .fail();
+            }
+            CollisionPolicy::Overwrite => None,
+            CollisionPolicy::Rename => {
+                let renamed = renamed_outputs(input, &path)
+                    .find(|candidate| {
+                        self.claimed
+                            .get(&output_key(candidate))
+                            .is_none_or(|owner| owner == input)
+                    })
+                    .expect("numbered names never run out");
+                self.claimed.insert(output_key(&renamed), input.to_owned());
+                Some(renamed)
+            }
+        };
+        if !self.cli.quiet {
+            self.log(&Event::Collision {
+                input,
+                output: &path,
+                first: &first,
+                renamed: renamed.as_deref(),
+            })?;
+        }
+        Ok(renamed.unwrap_or(path))
+    }
+
+    /// Returns whether `path` exists and may not be written over: it was not
+    /// written earlier in this run, and neither `--force` nor
+    /// `--overwrite-if-changed` is given.
+    fn is_protected(&self, path: &Path) -> bool {
+        let cli = self.cli;
+        path.exists() && !cli.force && !cli.overwrite_if_changed && !self.written.contains(path)
+    }
+
+    /// Parses every input, reporting each as OK or FAIL with any warnings.
+    ///
+    /// Fails once all inputs are checked if any of them could not be parsed.
+    fn validate_files(&mut self, files: &[PathBuf]) -> Result<(), Error> {
+        let mut failed = 0_usize;
+        let mut total = 0_usize;
+        for file in files {
+            // The report names the path, so keep it out of the reasons
+            let bytes = match std::fs::read(file) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    failed += 1;
+                    total += 1;
+                    writeln!(self.out, "FAIL {}: {e}", file.display()).context(ConsoleSnafu)?;
+                    continue;
+                }
+            };
+            for (line, json) in chat_sources(file, &bytes) {
+                total += 1;
+                let path = entry_label(file, line);
+                failed += usize::from(!self.validate_chat(&path, json)?);
+            }
+        }
+
+        ensure!(failed == 0, ValidationFailedSnafu { failed, total });
+        Ok(())
+    }
+
+    /// Parses the chat named `path` from `json` for `--validate`, reporting
+    /// it as OK or FAIL with any warnings, and returns whether it parsed.
+    fn validate_chat(&mut self, path: &Path, json: &[u8]) -> Result<bool, Error> {
+        match parser::parse_chat_bytes(json, &parse_options_for(self.cli)) {
+            Ok((_, warnings)) => {
+                if !self.cli.quiet {
+                    writeln!(self.out, "OK {}", path.display()).context(ConsoleSnafu)?;
+                }
+                for warning in warnings {
+                    writeln!(self.out, "  warning: {warning}").context(ConsoleSnafu)?;
+                }
+                Ok(true)
+            }
+            Err(e) => {
+                writeln!(self.out, "FAIL {}: {e}", path.display()).context(ConsoleSnafu)?;
+                if self.cli.pretty_errors
+                    && let Some(json_error) = e.json_error()
+                {
+                    // The FAIL line already gives the message
+                    let json = String::from_utf8_lossy(json);
+                    let pretty = parser::format_json_error(&json, json_error);
+                    for line in pretty.lines().skip(1) {
+                        writeln!(self.out, "  {line}").context(ConsoleSnafu)?;
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+
+    /// Prints which export format features each input uses.
+    fn detect_formats(&mut self, files: &[PathBuf]) -> Result<(), Error> {
+        for file in files {
+            let bytes = std::fs::read(file).context(ReadFileSnafu { path: file })?;
+            for (line, json) in chat_sources(file, &bytes) {
+                let path = entry_label(file, line);
+                let (json, _) = parser::decode_export(json, &parse_options_for(self.cli))
+                    .context(ParseFileSnafu { path: &path })?;
+                let hint =
+                    parser::detect_format_version(&json).context(ParseFileSnafu { path: &path })?;
+                writeln!(self.out, "{}: {hint}", path.display()).context(ConsoleSnafu)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints what each chat holds for `cp2md stats`, then the totals.
+    fn print_stats(&mut self, files: &[PathBuf]) -> Result<(), Error> {
+        let mut total = ChatStats::default();
+        let mut chats = 0_usize;
+        for file in files {
+            let (entries, _) = self.prepare_chats(file)?;
+            for entry in entries {
+                let stats = ChatStats::of(&entry.chat);
+                writeln!(self.out, "{}: {stats}", entry.label.display()).context(ConsoleSnafu)?;
+                total.add(stats);
+                chats += 1;
+            }
+        }
+        let noun = if chats == 1 { "chat" } else { "chats" };
+        writeln!(self.out, "Total: {chats} {noun}, {total}").context(ConsoleSnafu)
+    }
+
+    /// Prints how the second chat in `files` differs from the first for
+    /// `cp2md diff`.
+    fn print_diff(&mut self, files: &[PathBuf]) -> Result<(), Error> {
+        let mut entries = Vec::new();
+        for file in files {
+            entries.extend(self.prepare_chats(file)?.0);
+        }
+        let [old, new] = <[Entry; 2]>::try_from(entries).map_err(|entries| {
+            DiffNeedsTwoChatsSnafu {
+                count: entries.len(),
+            }
+            .build()
+        })?;
+        write!(self.out, "{}", diff_chats(&old, &new)).context(ConsoleSnafu)
+    }
+
+    /// Lists each chat session for `cp2md discover`, with its title and
+    /// number of exchanges.
+    fn list_sessions(&mut self, files: &[PathBuf]) -> Result<(), Error> {
+        for file in files {
+            let (entries, _) = self.prepare_chats(file)?;
+            for Entry { label, line, chat } in entries {
+                let count = chat.requests.len();
+                let noun = if count == 1 { "exchange" } else { "exchanges" };
+                let title = chat_title(file, line, &chat);
+                writeln!(self.out, "{}: {title} ({count} {noun})", label.display())
+                    .context(ConsoleSnafu)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the chats in an input file, reporting anything unusual about
+    /// their exports' shape unless `--quiet`.
+    fn load_entries(&mut self, path: &Path) -> Result<Vec<Entry>, Error> {
+        let loaded = load_entries(path, self.cli)?;
+        let mut entries = Vec::with_capacity(loaded.len());
+        for (entry, warnings) in loaded {
+            if !self.cli.quiet {
+                for warning in warnings {
+                    self.log(&Event::Warning {
+                        input: &entry.label,
+                        message: warning.to_string(),
+                    })?;
+                }
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Loads the chats in an input file and applies any requested filtering
+    /// and anonymization, returning those kept and how many were left out.
+    ///
+    /// A file found by `--scan-vscode` that is not a chat is left out, after
+    /// saying so, as are chats [`prepare_chat`](Self::prepare_chat) leaves
+    /// out.
+    fn prepare_chats(&mut self, path: &Path) -> Result<(Vec<Entry>, usize), Error> {
+        let cli = self.cli;
+        let entries = match self.load_entries(path) {
+            // Scanned directories can hold other JSON, such as editing sessions
+            Err(Error::ParseFile { .. }) if cli.scan_vscode => {
+                self.log(&Event::Skipped {
+                    path,
+                    reason: SkipReason::NotChat,
+                })?;
+                return Ok((Vec::new(), 1));
+            }
+            Err(error @ Error::ParseFile { .. }) if cli.pretty_errors => {
+                self.explain_parse_error(&error)?;
+                return Err(error);
+            }
+            result => result?,
+        };
+        let total = entries.len();
+        let mut kept = Vec::with_capacity(total);
+        for Entry { label, line, chat } in entries {
+            if let Some(chat) = self.prepare_chat(&label, chat)? {
+                kept.push(Entry { label, line, chat });
+            }
+        }
+        let skipped = total - kept.len();
+        Ok((kept, skipped))
+    }
+
+    /// Applies any requested filtering and anonymization to the chat named
+    /// `path` in messages.
+    ///
+    /// Returns `None`, after saying so, when `--only-agent` leaves no requests,
+    /// or the chat has no requests that would be shown and `--keep-empty` is
+    /// not set.
+    /// The anonymization mapping is recorded under `path` so it can be
+    /// written out as a sidecar once all inputs are processed.
+    fn prepare_chat(
+        &mut self,
+        path: &Path,
+        mut chat: parser::ChatExport,
+    ) -> Result<Option<parser::ChatExport>, Error> {
+        let cli = self.cli;
+        if let Some(agent) = &cli.only_agent {
+            retain_agent_requests(&mut chat, agent);
+            if chat.requests.is_empty() {
+                self.log(&Event::Skipped {
+                    path,
+                    reason: SkipReason::NoAgentRequests(agent),
+                })?;
+                return Ok(None);
+            }
+        }
+        // Hidden requests are never rendered, so a chat of only those is empty
+        if !cli.keep_empty && chat.requests.iter().all(|request| request.hidden) {
+            self.skipped_empty(path)?;
+            return Ok(None);
+        }
+        if !cli.anonymize {
+            return Ok(Some(chat));
+        }
+
+        let (chat, map) = anonymize::anonymize(&chat);
+        self.maps.insert(path.to_path_buf(), map);
+        Ok(Some(chat))
+    }
+
+    /// Shows where in its input a JSON error happened, for `--pretty-errors`.
+    fn explain_parse_error(&mut self, error: &Error) -> Result<(), Error> {
+        let Error::ParseFile { path, source } = error else {
+            return Ok(());
+        };
+        let Some(json_error) = source.json_error() else {
+            return Ok(());
+        };
+        // Inputs are read again here rather than kept around in case they fail
+        let Ok(bytes) = std::fs::read(path) else {
+            return Ok(());
+        };
+        self.log(&Event::ParseError {
+            input: path,
+            message: parser::format_json_error(&String::from_utf8_lossy(&bytes), json_error),
+        })
+    }
+
+    /// Processes a single file and outputs to stdout.
+    fn process_to_stdout(&mut self, input: &Path) -> Result<(), Error> {
+        if self.cli.dry_run {
+            return self.dry_run(Some(input), None, None);
+        }
+
+        let (entries, _) = self.prepare_chats(input)?;
+        ensure!(
+            entries.len() <= 1,
+            MultipleChatsToStdoutSnafu { path: input }
+        );
+        let Some(entry) = entries.first() else {
+            return Ok(());
+        };
+
+        let opts = render_options_for(&entry.label, &entry.chat, self.cli);
+        let markdown = renderer::render_chat(&entry.chat, &opts);
+        let markdown = renderer::convert_line_endings(&markdown, self.cli.line_ending);
+
+        write!(self.out, "{markdown}").context(ConsoleSnafu)
+    }
+
+    /// Processes multiple files and concatenates them into a single output.
+    fn process_concat(&mut self, files: &[PathBuf], target: &OutputTarget) -> Result<(), Error> {
+        let cli = self.cli;
+        let (chats, skipped, output) = self.concat_files(files)?;
+
+        match target {
+            OutputTarget::Stdout => {
+                if cli.dry_run {
+                    self.dry_run(None, None, Some(files.len()))?;
+                } else {
+                    write!(self.out, "{output}").context(ConsoleSnafu)?;
+                }
+            }
+            OutputTarget::File(path) | OutputTarget::Directory(path) => {
+                // In concat mode, treat path as a file, not directory
+                self.write_concat(path, files.len(), (&chats, skipped), &output)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Concatenates each group of inputs into `<directory name>.md` in
+    /// `out_dir`.
+    ///
+    /// Groups with no inputs are left out. Two groups that would share an
+    /// output file, as directories with the same name do, are handled per
+    /// `--on-collision` before anything is written.
+    fn process_concat_per_dir(
+        &mut self,
+        groups: &[InputGroup],
+        out_dir: &Path,
+    ) -> Result<(), Error> {
+        let mut outputs = Vec::with_capacity(groups.len());
+        for group in groups.iter().filter(|group| !group.files.is_empty()) {
+            let path = out_dir.join(group.output_name()?);
+            let path = self.claim_output(&group.dir, path)?;
+            outputs.push((group, path));
+        }
+
+        for (group, path) in outputs {
+            let (chats, skipped, output) = self.concat_files(&group.files)?;
+            self.write_concat(&path, group.files.len(), (&chats, skipped), &output)?;
+        }
+        Ok(())
+    }
+
+    /// Loads and renders `files` as one concatenated document, returning
+    /// the chats in it and how many were left out along with it.
+    fn concat_files(
+        &mut self,
+        files: &[PathBuf],
+    ) -> Result<(Vec<parser::ChatExport>, usize, String), Error> {
+        let cli = self.cli;
+        let (chats, sources, skipped) = self.load_chats(files)?;
+        let opts = renderer::ConcatOptions {
+            render: renderer::RenderOptions {
+                selection_excerpts: read_selection_excerpts(
+                    sources.iter().map(Path::new).zip(&chats),
+                    cli,
+                ),
+                ..make_render_options(cli)
+            },
+            title: cli.concat_title.clone(),
+            toc: cli.concat_toc,
+            separator: cli.separator.clone(),
+            sources,
+        };
+        let output = renderer::render_concat(&chats, &opts);
+        let output = renderer::convert_line_endings(&output, cli.line_ending);
+        Ok((chats, skipped, output))
+    }
+
+    /// Writes a concatenated document rendered from `inputs` files, honoring
+    /// `--dry-run`, `--force`, `--overwrite-if-changed`, and `--quiet`.
+    ///
+    /// `included` holds the chats in the document and how many were left
+    /// out.
+    fn write_concat(
+        &mut self,
+        path: &Path,
+        inputs: usize,
+        included: (&[parser::ChatExport], usize),
+        output: &str,
+    ) -> Result<(), Error> {
+        let (chats, skipped) = included;
+        let cli = self.cli;
+        if cli.dry_run {
+            self.dry_run(None, Some(path), Some(inputs))?;
+        } else if path.exists() && !cli.force && !cli.overwrite_if_changed {
+            self.skipped_existing(path)?;
+        } else {
+            let contents = encode_output(output, cli);
+            if cli.overwrite_if_changed && is_unchanged(path, &contents) {
+                return self.unchanged(path);
+            }
+            // Create parent directory if needed
+            if let Some(parent) = path.parent()
+                && !parent.as_os_str().is_empty()
+            {
+                std::fs::create_dir_all(parent).context(CreateOutputDirSnafu)?;
+            }
+            std::fs::write(path, &contents).context(WriteFileSnafu { path })?;
+            self.summary.written += 1;
+            if !cli.quiet {
+                self.log(&Event::Wrote {
+                    input: None,
+                    output: path,
+                    bytes: contents.len(),
+                    concat: Some(concat_counts(chats, skipped, cli.preview)),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the chats in each input to be concatenated, leaving out any
+    /// that are skipped, along with where each chat came from and how many
+    /// were skipped.
+    ///
+    /// Every chat gets a title, so it can be told apart from the others.
+    fn load_chats(
+        &mut self,
+        files: &[PathBuf],
+    ) -> Result<(Vec<parser::ChatExport>, Vec<String>, usize), Error> {
+        let mut chats = Vec::with_capacity(files.len());
+        let mut sources = Vec::with_capacity(files.len());
+        let mut skipped = 0;
+        for path in files {
+            let (entries, left_out) = self.prepare_chats(path)?;
+            skipped += left_out;
+            for Entry {
+                label,
+                line,
+                mut chat,
+            } in entries
+            {
+                chat.custom_title = Some(chat_title(path, line, &chat));
+                chats.push(chat);
+                sources.push(label.display().to_string());
+            }
+        }
+        Ok((chats, sources, skipped))
+    }
+
+    /// Processes files into `out_dir`, linking them with footers and an index.
+    ///
+    /// Each file links to its neighbours in input order and to `index.md`,
+    /// which lists every output file. All chats are loaded first so inputs
+    /// skipped by filtering are left out of the links.
+    fn process_with_nav(&mut self, files: &[PathBuf], out_dir: &Path) -> Result<(), Error> {
+        for file in files.iter().filter(|file| !is_archive(file)) {
+            ensure!(
+                output_path_for(file, None, Path::new(""))? != Path::new(NAV_INDEX),
+                NavIndexConflictSnafu { path: file.clone() }
+            );
+        }
+
+        let mut names = Vec::with_capacity(files.len());
+        let mut entries = Vec::with_capacity(files.len());
+        for file in files {
+            for entry in self.prepare_chats(file)?.0 {
+                let name = output_path_for(file, entry.line, Path::new(""))?;
+                let name = self.claim_output(&entry.label, name)?;
+                names.push(name.to_string_lossy().into_owned());
+                entries.push(entry);
+            }
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            let links = renderer::NavLinks {
+                previous: i.checked_sub(1).map(|prev| names[prev].as_str()),
+                index: Some(NAV_INDEX),
+                next: names.get(i + 1).map(String::as_str),
+            };
+            let opts = render_options_for(&entry.label, &entry.chat, self.cli);
+            let mut markdown = renderer::render_chat(&entry.chat, &opts);
+            markdown.push_str(&renderer::render_nav_footer(&links));
+            self.write_output(Some(&entry.label), &out_dir.join(&names[i]), &markdown)?;
+        }
+
+        let entries: Vec<_> = names
+            .iter()
+            .map(|name| (name.strip_suffix(".md").unwrap_or(name), name.as_str()))
+            .collect();
+        let index = renderer::render_nav_index(&entries, self.cli.heading_offset);
+        self.write_output(None, &out_dir.join(NAV_INDEX), &index)
+    }
+
+    /// Writes output generated from `input`, if from just one, honoring
+    /// `--dry-run`, `--force`, `--overwrite-if-changed`, and `--quiet`.
+    fn write_output(
+        &mut self,
+        input: Option<&Path>,
+        path: &Path,
+        contents: &str,
+    ) -> Result<(), Error> {
+        let cli = self.cli;
+        if cli.dry_run {
+            return self.dry_run(input, Some(path), None);
+        }
+
+        if self.is_protected(path) {
+            return self.skipped_existing(path);
+        }
+
+        let contents = renderer::convert_line_endings(contents, cli.line_ending);
+        let contents = encode_output(&contents, cli);
+        if cli.overwrite_if_changed && is_unchanged(path, &contents) {
+            return self.unchanged(path);
+        }
+        std::fs::write(path, &contents).context(WriteFileSnafu { path })?;
+        self.written.insert(path.to_owned());
+        self.wrote(input, path, contents.len())
+    }
+
+    /// Processes a single file and writes to the output directory.
+    fn process_file(&mut self, input: &Path, out_dir: &Path) -> Result<(), Error> {
+        let cli = self.cli;
+        if is_archive(input) {
+            return self.process_archive(input, out_dir);
+        }
+        let out_path = self.claim_output(input, output_path_for(input, None, out_dir)?)?;
+
+        // Handle dry-run mode
+        if cli.dry_run {
+            return self.dry_run(Some(input), Some(&out_path), None);
+        }
+
+        // Check if output exists and handle overwrite; with
+        // --overwrite-if-changed the output must be rendered to compare
+        if self.is_protected(&out_path) {
+            return self.skipped_existing(&out_path);
+        }
+
+        let Some(entry) = self.prepare_chats(input)?.0.pop() else {
+            return Ok(());
+        };
+
+        let opts = render_options_for(input, &entry.chat, cli);
+        let markdown = renderer::render_chat(&entry.chat, &opts);
+        self.write_output(Some(input), &out_path, &markdown)
+    }
+
+    /// Writes each chat in a JSONL archive to the output directory, named
+    /// after the archive and the chat's line, as in `chats-3.md`.
+    fn process_archive(&mut self, input: &Path, out_dir: &Path) -> Result<(), Error> {
+        let cli = self.cli;
+        if cli.dry_run {
+            let bytes = std::fs::read(input).context(ReadFileSnafu { path: input })?;
+            for (line, _) in chat_sources(input, &bytes) {
+                let label = entry_label(input, line);
+                let out_path = self.claim_output(&label, output_path_for(input, line, out_dir)?)?;
+                self.dry_run(Some(&label), Some(&out_path), None)?;
+            }
+            return Ok(());
+        }
+
+        for entry in self.prepare_chats(input)?.0 {
+            let out_path = output_path_for(input, entry.line, out_dir)?;
+            let out_path = self.claim_output(&entry.label, out_path)?;
+            if self.is_protected(&out_path) {
+                self.skipped_existing(&out_path)?;
+                continue;
+            }
+            let opts = render_options_for(&entry.label, &entry.chat, cli);
+            let markdown = renderer::render_chat(&entry.chat, &opts);
+            self.write_output(Some(&entry.label), &out_path, &markdown)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns where to write output: the `--output` target, or for a single
+/// input file given without one, the directory it is in.
+fn resolve_output(cli: &Cli, files: &[PathBuf]) -> Result<OutputTarget, Error> {
+    if let Some(output) = &cli.output {
+        return Ok(output.clone());
+    }
+
+    let single_file = match (&cli.input[..], files) {
+        ([input], [file]) if input == file => Some(file),
+        _ => None,
+    };
+    let file = single_file.context(MissingOutputSnafu)?;
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    Ok(if cli.concat {
+        OutputTarget::File(output_path_for(file, None, dir)?)
+    } else {
+        OutputTarget::Directory(dir.to_path_buf())
+    })
+}
+
+/// The input files that came from one directory.
+#[derive(Debug, PartialEq, Eq)]
+struct InputGroup {
+    /// The input directory, or the directory holding input files given
+    /// directly.
+    dir: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+impl InputGroup {
+    /// Name of the file `--concat-per-dir` combines this group into.
+    fn output_name(&self) -> Result<String, Error> {
+        let name = match self.dir.file_name() {
+            Some(name) => name.to_owned(),
+            // "." and ".." are named by where they lead
+            None => std::fs::canonicalize(&self.dir)
+                .ok()
+                .and_then(|dir| dir.file_name().map(ToOwned::to_owned))
+                .context(InvalidFilenameSnafu)?,
+        };
+        Ok(portable_file_name(&name.to_string_lossy(), "md"))
+    }
+}
+
+/// Collects all input files from the given inputs (files and directories),
+/// grouped by the directory they came from.
+///
+/// Directories are searched for files ending in one of `suffixes` (see
+/// [`has_input_suffix`]); files given directly are taken whatever their
+/// name. Each directory input is its own group, and files given directly
+/// join the group of the directory holding them. Directory traversal is sorted and
+/// deduplicated so multi-run output is deterministic and we never re-render
+/// the same file twice. Traversal errors are surfaced instead of silently
+/// skipping entries so the caller can fail fast when input discovery is
+/// incomplete.
+fn collect_input_files(inputs: &[PathBuf], suffixes: &[String]) -> Result<Vec<InputGroup>, Error> {
+    let mut groups: Vec<InputGroup> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for input in inputs {
+        let (dir, found) = if input.is_dir() {
+            let mut found = Vec::new();
+            for entry in WalkDir::new(input).sort_by_file_name() {
+                let entry = entry.context(ListInputsSnafu {
+                    path: input.clone(),
+                })?;
+
+                if has_input_suffix(entry.path(), suffixes) {
+                    found.push(entry.into_path());
+                }
+            }
+            (input.clone(), found)
+        } else {
+            let dir = input
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            (dir.to_path_buf(), vec![input.clone()])
+        };
+
+        let found = found.into_iter().filter(|path| seen.insert(path.clone()));
+        if let Some(group) = groups.iter_mut().find(|group| group.dir == dir) {
+            group.files.extend(found);
+        } else {
+            let files = found.collect();
+            groups.push(InputGroup { dir, files });
+        }
+    }
+
+    Ok(groups)
+}
+
+/// Returns whether `path`'s file name ends in `.` and one of `suffixes`.
+///
+/// Suffixes may span several dots, so `copilot.json` matches
+/// `chat.copilot.json` but not `chat.json`. Something must come before the
+/// suffix: a file named just `.json` is not an input.
+fn has_input_suffix(path: &Path, suffixes: &[impl AsRef<str>]) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    suffixes.iter().any(|suffix| {
+        name.strip_suffix(suffix.as_ref())
+            .and_then(|stem| stem.strip_suffix('.'))
+            .is_some_and(|stem| !stem.is_empty())
+    })
+}
+
+/// Collects chat session files from VS Code user data directories.
+///
+/// VS Code keeps each workspace's chats in
+/// `User/workspaceStorage/<hash>/chatSessions/<session>.json`, and chats
+/// opened without a workspace in
+/// `User/globalStorage/emptyWindowChatSessions/<session>.json`. Each input
+/// may be the user data directory or its `User` subdirectory, and its
+/// sessions form one group. Files are sorted by path so runs are
+/// deterministic.
+fn collect_vscode_sessions(inputs: &[PathBuf]) -> Result<Vec<InputGroup>, Error> {
+    let mut groups = Vec::new();
+
+    for input in inputs {
+        let mut files = Vec::new();
+        let user = if input.join("User").is_dir() {
+            input.join("User")
+        } else {
+            input.clone()
+        };
+        let workspaces = user.join("workspaceStorage");
+        let empty_window = user.join("globalStorage").join("emptyWindowChatSessions");
+        ensure!(
+            workspaces.is_dir() || empty_window.is_dir(),
+            NoVscodeStorageSnafu {
+                path: input.clone()
+            }
+        );
+
+        // <hash>/chatSessions/<session>.json sits three levels down
+        for (root, depth) in [(workspaces, 3), (empty_window, 1)] {
+            if !root.is_dir() {
+                continue;
+            }
+            for entry in WalkDir::new(&root)
+                .min_depth(depth)
+                .max_depth(depth)
+                .sort_by_file_name()
+            {
+                let entry = entry.context(ListInputsSnafu { path: root.clone() })?;
+                let path = entry.path();
+                let in_sessions = depth == 1
+                    || path
+                        .parent()
+                        .and_then(Path::file_name)
+                        .is_some_and(|name| name == "chatSessions");
+                if in_sessions && entry.file_type().is_file() && has_input_suffix(path, &["json"]) {
+                    files.push(entry.into_path());
+                }
+            }
+        }
+        groups.push(InputGroup {
+            dir: input.clone(),
+            files,
+        });
+    }
+
+    Ok(groups)
+}
+
+/// Creates render options from CLI arguments.
+#[allow(clippy::missing_const_for_fn)]
+pub(super) fn make_render_options(cli: &Cli) -> renderer::RenderOptions {
+    renderer::RenderOptions {
+        show_tools: cli.show_tools,
+        show_annotations: cli.show_annotations,
+        max_tool_message_len: cli.max_tool_len,
+        max_message_chars: cli.truncate,
+        show_timestamps: cli.show_timestamps,
+        timestamp_style: cli.timestamp_style,
+        relative_to: None,
+        show_model: cli.show_model,
+        friendly_model_names: cli.friendly_model_names,
+        show_agent: cli.show_agent,
+        show_context: cli.show_context,
+        context_kinds: cli.context_kinds.clone(),
+        context_position: cli.context_position,
+        context_index: cli.context_index,
+        show_selection_text: cli.show_selection_text,
+        dedupe_context: cli.dedupe_context,
+        hoist_instructions: cli.hoist_instructions,
+        summary_only: cli.summary_only,
+        dedupe_metadata: cli.dedupe_metadata,
+        metadata_mode: cli.metadata_mode,
+        metadata_as_alert: cli.metadata_alert,
+        path_style: cli.path_style,
+        path_display: cli.path_display,
+        embed_images: cli.embed_images,
+        repo_permalink: cli.repo_permalink.clone(),
+        selection_excerpts: None,
+        front_matter: cli.front_matter,
+        heading_offset: cli.heading_offset,
+        deep_heading_style: cli.deep_headings,
+        edit_summary: cli.edit_summary,
+        edits_as_section: cli.edits_section,
+        show_activity_badges: cli.badges,
+        show_usage: cli.show_usage,
+        use_agent_as_speaker: cli.agent_speaker,
+        max_exchanges: cli.preview,
+        show_hidden: false,
+        wrap: cli.wrap,
+        code_indent: cli.code_indent,
+        quote_user: cli.quote_user,
+        reflow_paragraphs: cli.reflow_paragraphs,
+        redact_patterns: cli.redact_patterns.clone(),
+        debug_unknown: cli.debug_unknown,
+        source_comment: cli.source_comment,
+        source_path: None,
+        title: None,
+        document_heading: cli.document_heading.then_some(1),
+        title_from_first_message: cli.title_from_message,
+        normalize_blank_lines: true,
+    }
+}
+
+/// Creates render options for rendering `chat`, read from `input`, on its
+/// own.
+pub(super) fn render_options_for(
+    input: &Path,
+    chat: &parser::ChatExport,
+    cli: &Cli,
+) -> renderer::RenderOptions {
+    renderer::RenderOptions {
+        source_path: Some(input.display().to_string()),
+        selection_excerpts: read_selection_excerpts([(input, chat)], cli),
+        ..make_render_options(cli)
+    }
+}
+
+/// Reads the selected lines of every selection in `chats` from disk, for
+/// `--embed-selections`. Each chat comes with the input it was read from.
+///
+/// Selections in files that [`read_selection_source`] cannot read, or that
+/// are shorter than the selection, are left out, so they render as
+/// unavailable.
+fn read_selection_excerpts<'c>(
+    chats: impl IntoIterator<Item = (&'c Path, &'c parser::ChatExport)>,
+    cli: &Cli,
+) -> Option<renderer::SelectionExcerpts> {
+    let max_lines = cli.embed_selections?;
+    let mut sources: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut excerpts = BTreeMap::new();
+    for (input, chat) in chats {
+        let base = input.parent().unwrap_or_else(|| Path::new(""));
+        for item in chat.requests.iter().flat_map(|req| &req.context) {
+            let parser::ContextItem::Selection {
+                path,
+                start_line,
+                end_line,
+                ..
+            } = item
+            else {
+                continue;
+            };
+            let source = sources
+                .entry(base.join(path))
+                .or_insert_with_key(|path| read_selection_source(path));
+            if let Some(excerpt) = source
+                .as_deref()
+                .and_then(|source| renderer::select_lines(source, *start_line, *end_line))
+            {
+                excerpts.insert((path.clone(), *start_line, *end_line), excerpt);
+            }
+        }
+    }
+    Some(renderer::SelectionExcerpts {
+        max_lines,
+        excerpts,
+    })
+}
+
+/// Reads the file a selection is in, for `--embed-selections`.
+///
+/// Exports name the file as it was on the machine that recorded the chat,
+/// so `path` is untrusted: it must be a regular file of at most
+/// [`MAX_SELECTION_SOURCE_BYTES`] in UTF-8, or there is no source.
+/// [`read_selection_excerpts`] resolves a relative path against the
+/// directory of the export that names it.
+fn read_selection_source(path: &Path) -> Option<String> {
+    use std::io::Read as _;
+
+    // Opening a FIFO would block, so check before opening
+    if !std::fs::metadata(path).ok()?.is_file() {
+        return None;
+    }
+    let file = std::fs::File::open(path).ok()?;
+    let mut bytes = Vec::new();
+    file.take(MAX_SELECTION_SOURCE_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    if bytes.len() as u64 > MAX_SELECTION_SOURCE_BYTES {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Loads the chats in an input file, each with anything unusual about its
+/// export's shape: the file's one chat, or for a JSONL archive, one for
+/// each line that is not blank.
+fn load_entries(path: &Path, cli: &Cli) -> Result<Vec<(Entry, Vec<parser::ParseWarning>)>, Error> {
+    let bytes = std::fs::read(path).context(ReadFileSnafu { path })?;
+    chat_sources(path, &bytes)
+        .into_iter()
+        .map(|(line, json)| {
+            let label = entry_label(path, line);
+            let (chat, warnings) = parser::parse_chat_bytes(json, &parse_options_for(cli))
+                .context(ParseFileSnafu { path: &label })?;
+            Ok((Entry { label, line, chat }, warnings))
+        })
+        .collect()
+}
+
+/// Returns whether `path` names a JSONL archive, which holds a chat on each
+/// line.
+fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("jsonl"))
+}
+
+/// Returns the JSON of each chat in the `bytes` of the input at `path`, with
+/// the line it is on in an archive.
+///
+/// An archive's chats are its lines that are not blank, numbered from 1.
+/// Any other input is one chat. The JSON is left undecoded, so each chat
+/// can be decoded on its own.
+fn chat_sources<'b>(path: &Path, bytes: &'b [u8]) -> Vec<(Option<usize>, &'b [u8])> {
+    if !is_archive(path) {
+        return vec![(None, bytes)];
+    }
+    bytes
+        .split(|&byte| byte == b'\n')
+        .enumerate()
+        .filter(|(_, json)| !json.trim_ascii().is_empty())
+        .map(|(index, json)| (Some(index + 1), json))
+        .collect()
+}
+
+/// Returns how the chat on `line` of the input at `path` is named in messages:
+/// the path, followed for an archive by `:` and the line, as in
+/// `chats.jsonl:3`.
+fn entry_label(path: &Path, line: Option<usize>) -> PathBuf {
+    let Some(line) = line else {
+        return path.to_owned();
+    };
+    let mut label = path.as_os_str().to_owned();
+    label.push(format!(":{line}"));
+    label.into()
+}
+
+/// Returns the parse options the command line calls for.
+const fn parse_options_for(cli: &Cli) -> parser::ParseOptions {
+    parser::ParseOptions {
+        keep_raw: cli.debug_unknown,
+        lenient: cli.lenient_json,
+        timestamp_unit: cli.timestamp_unit,
+        strict_utf8: cli.strict,
+    }
+}
+
+/// Keeps only the requests handled by `agent`.
+fn retain_agent_requests(chat: &mut parser::ChatExport, agent: &str) {
+    chat.requests
+        .retain(|req| req.agent_name.as_deref() == Some(agent));
+}
+
+/// Writes the anonymization mappings as pretty-printed JSON.
+fn write_anonymize_map(path: &Path, maps: &AnonymizeMaps) -> Result<usize, Error> {
+    let entries: BTreeMap<_, _> = maps
+        .iter()
+        .map(|(input, map)| (input.to_string_lossy(), map))
+        .collect();
+    let mut json = serde_json::to_string_pretty(&entries).context(SerializeAnonymizeMapSnafu)?;
+    json.push('\n');
+    std::fs::write(path, &json).context(WriteFileSnafu { path })?;
+    Ok(json.len())
+}
+
+/// Returns the title for a chat: its custom title, else the file stem,
+/// followed for a chat on `line` of an archive by `-` and the line.
+fn chat_title(path: &Path, line: Option<usize>, chat: &parser::ChatExport) -> String {
+    chat.custom_title
+        .clone()
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| output_stem(path, line).unwrap_or_else(|_| path.display().to_string()))
+}
+
+/// What a chat holds, as `cp2md stats` reports it, which reads as
+/// `3 exchanges, 2 files edited, 5 tool invocations, models: gpt-4o`,
+/// followed by `, tokens: 1234 in / 567 out` when any request records its
+/// usage.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ChatStats {
+    exchanges: usize,
+    /// Files edited, each counted once per chat.
+    files_edited: usize,
+    /// Tool invocations with a message to show.
+    tools: usize,
+    /// Models that answered, or the chat's default model when no request
+    /// names one.
+    models: BTreeSet<String>,
+    /// Tokens used by the requests that record them, if any do.
+    usage: Option<parser::Usage>,
+}
+
+impl ChatStats {
+    /// Counts what `chat` holds.
+    fn of(chat: &parser::ChatExport) -> Self {
+        let mut models: BTreeSet<_> = chat
+            .exchanges()
+            .filter_map(|req| req.model_id.clone())
+            .collect();
+        if models.is_empty() {
+            models.extend(chat.default_model.clone());
+        }
+        Self {
+            exchanges: chat.requests.len(),
+            files_edited: chat.all_edited_paths().len(),
+            tools: chat
+                .exchanges()
+                .map(|req| req.tool_invocations().count())
+                .sum(),
+            models,
+            usage: chat
+                .exchanges()
+                .filter_map(|req| req.usage)
+                .reduce(add_usage),
+        }
+    }
+
+    /// Adds `other`'s counts to these, and its models to these models.
+    fn add(&mut self, other: Self) {
+        self.exchanges += other.exchanges;
+        self.files_edited += other.files_edited;
+        self.tools += other.tools;
+        self.models.extend(other.models);
+        self.usage = match (self.usage, other.usage) {
+            (Some(a), Some(b)) => Some(add_usage(a, b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+impl fmt::Display for ChatStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count =
+            |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+        write!(
+            f,
+            "{}, {}, {}",
+            count(self.exchanges, "exchange", "exchanges"),
+            count(self.files_edited, "file edited", "files edited"),
+            count(self.tools, "tool invocation", "tool invocations"),
+        )?;
+        if !self.models.is_empty() {
+            let models: Vec<_> = self.models.iter().map(String::as_str).collect();
+            write!(f, ", models: {}", models.join(", "))?;
+        }
+        if let Some(usage) = self.usage {
+            write!(
+                f,
+                ", tokens: {} in / {} out",
+                usage.prompt, usage.completion
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the tokens of two usages together, stopping at `u32::MAX`.
+const fn add_usage(a: parser::Usage, b: parser::Usage) -> parser::Usage {
+    parser::Usage {
+        prompt: a.prompt.saturating_add(b.prompt),
+        completion: a.completion.saturating_add(b.completion),
+    }
+}
+
+/// Returns how `new`'s exchanges differ from `old`'s, for `cp2md diff`.
+///
+/// Exchanges are matched by their message, keeping the most matches in
+/// order. Each exchange gets a line with the first line of its message,
+/// marked `-` when only `old` has it, `+` when only `new` has it, `~` when
+/// both have it with different responses, and a space otherwise. Numbers
+/// are the exchange's place in `new`, or in `old` for those removed, and a
+/// final line gives the counts.
+fn diff_chats(old: &Entry, new: &Entry) -> String {
+    use std::fmt::Write as _;
+
+    let (a, b) = (&old.chat.requests, &new.chat.requests);
+    // lcs[i][j] is the number of matches between a[i..] and b[j..]
+    let mut lcs = vec![vec![0_usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i].message.text == b[j].message.text {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old.label.display(), new.label.display());
+    let mut line = |mark: char, number: usize, req: &parser::Request| {
+        let first = req.message.text.lines().next().unwrap_or_default().trim();
+        writeln!(out, "{mark} {number}. {first}").unwrap();
+    };
+    let [mut same, mut changed, mut removed, mut added] = [0_usize; 4];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i].message.text == b[j].message.text {
+            if a[i].response == b[j].response {
+                same += 1;
+                line(' ', j + 1, &b[j]);
+            } else {
+                changed += 1;
+                line('~', j + 1, &b[j]);
+            }
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            removed += 1;
+            line('-', i + 1, &a[i]);
+            i += 1;
+        } else {
+            added += 1;
+            line('+', j + 1, &b[j]);
+            j += 1;
+        }
+    }
+    writeln!(
+        out,
+        "{same} same, {changed} changed, {removed} removed, {added} added"
+    )
+    .unwrap();
+    out
+}
+
+/// Counts what a concatenated output holds, which reads as
+/// `3 files, 42 exchanges, 1 skipped`.
+///
+/// Exchanges left out by `--preview` are not counted, and the skipped count
+/// is omitted when nothing was skipped.
+fn concat_counts(
+    chats: &[parser::ChatExport],
+    skipped: usize,
+    preview: Option<usize>,
+) -> ConcatCounts {
+    let exchanges = chats
+        .iter()
+        .map(|chat| {
+            let count = chat.requests.len();
+            preview.map_or(count, |max| max.min(count))
+        })
+        .sum();
+    ConcatCounts {
+        files: chats.len(),
+        exchanges,
+        skipped,
+    }
+}
+
+/// Returns the Markdown output path in `out_dir` for an input file, or
+/// for the chat on `line` of an archive.
+///
+/// The file name is made portable with [`portable_file_name`].
+pub(super) fn output_path_for(
+    input: &Path,
+    line: Option<usize>,
+    out_dir: &Path,
+) -> Result<PathBuf, Error> {
+    Ok(out_dir.join(portable_file_name(&output_stem(input, line)?, "md")))
+}
+
+/// Returns the stem of the output for an input file: its own stem,
+/// followed for the chat on `line` of an archive by `-` and the line.
+fn output_stem(input: &Path, line: Option<usize>) -> Result<String, Error> {
+    let stem = input.file_stem().context(InvalidFilenameSnafu)?;
+    let stem = stem.to_string_lossy();
+    Ok(match line {
+        Some(line) => format!("{stem}-{line}"),
+        None => stem.into_owned(),
+    })
+}
+
+/// Returns the key two output paths share when they would name the same file,
+/// even on a case-insensitive file system.
+fn output_key(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+/// Returns the names to try, in order, for `input`'s output when `path` is
+/// taken.
+///
+/// The first adds the name of `input`'s directory to the stem, and the rest
+/// add 2, 3, and so on. The stem is cut short, rather than the suffix, so
+/// every name fits in 255 bytes and the numbered names all differ.
+fn renamed_outputs<'a>(input: &'a Path, path: &'a Path) -> impl Iterator<Item = PathBuf> + 'a {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let parent = input
+        .parent()
+        .and_then(Path::file_name)
+        .map(|name| format!("-{}", name.to_string_lossy()));
+    let numbered = (2_usize..).map(|n| format!("-{n}"));
+    parent.into_iter().chain(numbered).map(move |suffix| {
+        let max = (MAX_FILE_NAME_BYTES - ".md".len()).saturating_sub(suffix.len());
+        let end = (0..=max.min(stem.len()))
+            .rev()
+            .find(|&i| stem.is_char_boundary(i))
+            .unwrap_or(0);
+        path.with_file_name(portable_file_name(
+            &format!("{}{suffix}", &stem[..end]),
+            "md",
+        ))
+    })
+}
+
+/// Longest file name, in bytes, that common file systems allow.
+const MAX_FILE_NAME_BYTES: usize = 255;
+
+/// Device names Windows reserves, with or without an extension.
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Builds a file name from `stem` and `extension` that is valid on every
+/// platform.
+///
+/// Characters Windows forbids and control characters become `_`, trailing
+/// dots and spaces are trimmed, and a reserved device name such as `CON`
+/// gets an `_` appended. The stem is cut short, on a character boundary, so
+/// the whole name fits in 255 bytes.
+fn portable_file_name(stem: &str, extension: &str) -> String {
+    let trim = |s: &str| s.trim_end_matches(['.', ' ']).to_owned();
+    let replaced: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"<>:"/\|?*"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let mut stem = trim(&replaced);
+
+    // Windows reserves these names even when followed by an extension
+    let base_len = stem.find('.').unwrap_or(stem.len());
+    if RESERVED_FILE_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(&stem[..base_len]))
+    {
+        stem.insert(base_len, '_');
+    }
+
+    let max = MAX_FILE_NAME_BYTES - extension.len() - 1;
+    if stem.len() > max {
+        let end = (0..=max)
+            .rev()
+            .find(|&i| stem.is_char_boundary(i))
+            .unwrap_or(0);
+        stem = trim(&stem[..end]);
+    }
+    if stem.is_empty() {
+        stem.push('_');
+    }
+    format!("{stem}.{extension}")
+}
+
+/// Returns whether the file at `path` already holds exactly `contents`.
+pub(super) fn is_unchanged(path: &Path, contents: &[u8]) -> bool {
+    std::fs::read(path).is_ok_and(|existing| existing == contents)
+}
+
+/// Returns `contents` in the `--output-encoding`, with `--encoding-fallback`
+/// in place of each character the encoding cannot represent.
+pub(super) fn encode_output<'a>(contents: &'a str, cli: &Cli) -> Cow<'a, [u8]> {
+    match cli.output_encoding {
+        OutputEncoding::Utf8 => Cow::Borrowed(contents.as_bytes()),
+        OutputEncoding::Latin1 => {
+            let mut bytes = Vec::with_capacity(contents.len());
+            for c in contents.chars() {
+                // Latin-1 is the first 256 code points, one byte each
+                match u8::try_from(c) {
+                    Ok(byte) => bytes.push(byte),
+                    Err(_) => bytes.extend_from_slice(cli.encoding_fallback.as_bytes()),
+                }
+            }
+            Cow::Owned(bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::args::{parse_args, parse_args_from};
+    use std::fs;
+    use std::io;
+    use tempfile::TempDir;
+
+    /// Helper to create args from a string for testing.
+    fn args(s: &str) -> impl Iterator<Item = &str> {
+        s.split_whitespace()
+    }
+
+    /// Runs a command line, returning the result and what it wrote to
+    /// stdout and stderr.
+    fn run_args(args: &[&str]) -> (Result<RunSummary, Error>, String, String) {
+        let command = parse_args(args.iter().copied()).unwrap();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let result = run(&command, &mut out, &mut err);
+        (
+            result,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    /// A console whose reader has gone away, as when piping into `head`.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes two small chats into `dir`, returning their paths.
+    fn write_chats(dir: &Path) -> [String; 2] {
+        ["a", "b"].map(|name| {
+            let path = dir.join(format!("{name}.json"));
+            let json = format!(
+                r#"{{"responderUsername":"Copilot","requests":[
+                    {{"message":{{"text":"from {name}"}},"response":[{{"value":"ok"}}]}}]}}"#
+            );
+            fs::write(&path, json).unwrap();
+            path.display().to_string()
+        })
+    }
+
+    // =========================================================================
+    // Whole runs (captured output)
+    // =========================================================================
+
+    #[test]
+    fn help_matches_snapshot() {
+        let snapshot = include_str!("../../tests/snapshots/help.txt");
+        for args in [&["cp2md", "--help"][..], &["cp2md"]] {
+            let (result, out, err) = run_args(args);
+            assert_eq!(result.unwrap(), RunSummary::default());
+            assert_eq!(
+                out.replacen(env!("CARGO_PKG_VERSION"), "{version}", 1),
+                snapshot
+            );
+            assert!(err.is_empty());
+        }
+    }
+
+    #[test]
+    fn command_help_matches_snapshots() {
+        let snapshots = [
+            (
+                "convert",
+                include_str!("../../tests/snapshots/help-convert.txt"),
+            ),
+            (
+                "validate",
+                include_str!("../../tests/snapshots/help-validate.txt"),
+            ),
+            (
+                "stats",
+                include_str!("../../tests/snapshots/help-stats.txt"),
+            ),
+            ("diff", include_str!("../../tests/snapshots/help-diff.txt")),
+            (
+                "discover",
+                include_str!("../../tests/snapshots/help-discover.txt"),
+            ),
+        ];
+        for (command, snapshot) in snapshots {
+            for args in [&["cp2md", command, "--help"][..], &["cp2md", command]] {
+                let (result, out, _) = run_args(args);
+                result.unwrap();
+                assert_eq!(
+                    out.replacen(env!("CARGO_PKG_VERSION"), "{version}", 1),
+                    snapshot,
+                    "{command}"
+                );
+            }
+        }
+    }
+
+    /// Runs each command line in turn in one directory and records its exit
+    /// code, stdout, stderr, and the files in the directory afterwards.
+    ///
+    /// The transcript is compared with `tests/snapshots/cli-matrix.txt`.
+    /// With `UPDATE_SNAPSHOTS=1` set, the transcript is written there
+    /// instead.
+    #[test]
+    fn flag_combinations_match_snapshot() {
+        use std::fmt::Write as _;
+
+        const MATRIX: &[&str] = &[
+            "$A -o -",
+            "$A $B -o -",
+            "--concat $A $B -o -",
+            "--concat --hide-model --show-usage $A $B -o -",
+            "$A $B",
+            "$A $B -o $DIR/out",
+            "$A $B -o $DIR/out",
+            "-v $A $B -o $DIR/out",
+            "--force -v $A $B -o $DIR/out",
+            "-q --force $A -o $DIR/out",
+            "--dry-run $A $B -o $DIR/dry",
+            "--concat $A $B -o $DIR/all.md",
+            "--concat --dry-run $A $B -o $DIR/none.md",
+            "--log-format json --force $A -o $DIR/out",
+            "--heading-offset 9 $A -o -",
+            "--validate $A $B",
+            "--detect $A",
+            "--validate --browse $A",
+            "--concat --concat-per-dir $A $B -o $DIR/per",
+            "--concat-per-dir --nav $A $B -o $DIR/per",
+            "--concat-per-dir $A $B -o -",
+            "$DIR/missing.json -o -",
+        ];
+
+        let dir = TempDir::new().unwrap();
+        let [a, b] = write_chats(dir.path());
+        let root = dir.path().display().to_string();
+        let mut transcript = String::new();
+        for line in MATRIX {
+            let line = line
+                .replace("$A", &a)
+                .replace("$B", &b)
+                .replace("$DIR", &root);
+            let argv: Vec<&str> = std::iter::once("cp2md").chain(args(&line)).collect();
+            let (mut out, mut err) = (Vec::new(), Vec::new());
+            let code = match parse_args(argv.iter().copied()) {
+                Ok(command) => match run(&command, &mut out, &mut err) {
+                    Ok(_) => 0,
+                    Err(e) => {
+                        writeln!(err, "error: {e}").unwrap();
+                        e.exit_code()
+                    }
+                },
+                Err(e) => {
+                    writeln!(err, "error: {e}").unwrap();
+                    e.exit_code()
+                }
+            };
+
+            let mut files: Vec<_> = walkdir::WalkDir::new(dir.path())
+                .sort_by_file_name()
+                .into_iter()
+                .map(Result::unwrap)
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| {
+                    let len = entry.metadata().unwrap().len();
+                    let path = entry.path().strip_prefix(dir.path()).unwrap();
+                    format!("{} ({len} bytes)", path.display())
+                })
+                .collect();
+            files.retain(|file| !file.starts_with("a.json") && !file.starts_with("b.json"));
+
+            writeln!(transcript, "$ cp2md {line}").unwrap();
+            writeln!(transcript, "exit: {code}").unwrap();
+            for (name, bytes) in [("stdout", out), ("stderr", err)] {
+                writeln!(transcript, "{name}:").unwrap();
+                for text in String::from_utf8(bytes).unwrap().lines() {
+                    writeln!(transcript, "  {text}").unwrap();
+                }
+            }
+            writeln!(transcript, "files:").unwrap();
+            for file in files {
+                writeln!(transcript, "  {file}").unwrap();
+            }
+            transcript.push('\n');
+        }
+        let transcript = transcript.replace(&root, "$DIR");
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/cli-matrix.txt");
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|value| value == "1") {
+            fs::write(&path, &transcript).unwrap();
+        }
+        assert_eq!(
+            transcript,
+            fs::read_to_string(&path).unwrap(),
+            "rerun with UPDATE_SNAPSHOTS=1 to accept the new output"
+        );
+    }
+
+    #[test]
+    fn no_title_combines_with_front_matter_and_concat() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let cases: [(&[&str], &str, &[&str]); 4] = [
+            (&["--no-title"], "# User\n", &["# User", "# Assistant"]),
+            (
+                &["--no-title", "--frontmatter", "--title-from-message"],
+                "---\ntitle: \"from a\"\n",
+                &["# User", "# Assistant"],
+            ),
+            // Concatenated chats keep their headings to be told apart
+            (
+                &["--no-title", "--concat"],
+                "# a\n",
+                &[
+                    "# a",
+                    "## User",
+                    "## Assistant",
+                    "# b",
+                    "## User",
+                    "## Assistant",
+                ],
+            ),
+            (
+                &["--no-title", "--concat", "--concat-title", "All"],
+                "# All\n",
+                &[
+                    "# All",
+                    "## a",
+                    "### User",
+                    "### Assistant",
+                    "## b",
+                    "### User",
+                    "### Assistant",
+                ],
+            ),
+        ];
+        for (flags, start, headings) in cases {
+            let mut argv = vec!["cp2md"];
+            argv.extend(flags);
+            argv.extend(["-o", "-", &a]);
+            if flags.contains(&"--concat") {
+                argv.push(&b);
+            }
+            let (result, out, _) = run_args(&argv);
+            result.unwrap();
+            assert!(out.starts_with(start), "{flags:?}:\n{out}");
+            let found: Vec<_> = out.lines().filter(|line| line.starts_with('#')).collect();
+            assert_eq!(found, headings, "{flags:?}");
+            assert!(!out.contains("\n\n\n"), "{flags:?}:\n{out}");
+        }
+    }
+
+    #[test]
+    fn converts_without_naming_a_command() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let bare = temp.path().join("bare");
+        let named = temp.path().join("named");
+
+        let (result, _, _) = run_args(&["cp2md", &a, "-o", bare.to_str().unwrap()]);
+        assert_eq!(result.unwrap().written, 1);
+        let (result, _, _) = run_args(&["cp2md", "convert", &a, "-o", named.to_str().unwrap()]);
+        assert_eq!(result.unwrap().written, 1);
+        assert_eq!(
+            fs::read_to_string(bare.join("a.md")).unwrap(),
+            fs::read_to_string(named.join("a.md")).unwrap()
+        );
+    }
+
+    #[test]
+    fn stats_counts_each_chat_and_the_total() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let agent = temp.path().join("agent.json");
+        fs::write(
+            &agent,
+            r#"{"responderUsername":"Copilot","requests":[
+                {"modelId":"gpt-4o","message":{"text":"fix"},"response":[
+                    {"kind":"toolInvocationSerialized","pastTenseMessage":{"value":"Read a.rs"}},
+                    {"kind":"textEditGroup","uri":{"path":"/a.rs"},"edits":[[{"text":"x"}]]}]},
+                {"modelId":"o3","message":{"text":"again"},"response":[],
+                 "result":{"metadata":{"usage":{"promptTokens":100,"completionTokens":20}}}}]}"#,
+        )
+        .unwrap();
+
+        let (result, out, _) = run_args(&["cp2md", "stats", &a, agent.to_str().unwrap()]);
+        result.unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "{a}: 1 exchange, 0 files edited, 0 tool invocations\n\
+                 {}: 2 exchanges, 1 file edited, 1 tool invocation, models: gpt-4o, o3, \
+                 tokens: 100 in / 20 out\n\
+                 Total: 2 chats, 3 exchanges, 1 file edited, 1 tool invocation, models: gpt-4o, o3, \
+                 tokens: 100 in / 20 out\n",
+                agent.display()
+            )
+        );
+        let (result, out, _) = run_args(&["cp2md", "stats", &b]);
+        result.unwrap();
+        assert!(out.ends_with("Total: 1 chat, 1 exchange, 0 files edited, 0 tool invocations\n"));
+    }
+
+    #[test]
+    fn diff_matches_exchanges_by_message() {
+        let entry = |label: &str, json: &str| Entry {
+            label: PathBuf::from(label),
+            line: None,
+            chat: parser::parse_chat(json).unwrap(),
+        };
+        let old = entry(
+            "old.json",
+            r#"{"requests":[
+                {"message":{"text":"one"},"response":[{"value":"1"}]},
+                {"message":{"text":"two\nmore"},"response":[{"value":"2"}]},
+                {"message":{"text":"three"},"response":[{"value":"3"}]}]}"#,
+        );
+        let new = entry(
+            "new.json",
+            r#"{"requests":[
+                {"message":{"text":"one"},"response":[{"value":"1"}]},
+                {"message":{"text":"three"},"response":[{"value":"III"}]},
+                {"message":{"text":"four"},"response":[{"value":"4"}]}]}"#,
+        );
+        assert_eq!(
+            diff_chats(&old, &new),
+            "--- old.json\n+++ new.json\n\
+             \x20 1. one\n\
+             - 2. two\n\
+             ~ 2. three\n\
+             + 3. four\n\
+             1 same, 1 changed, 1 removed, 1 added\n"
+        );
+    }
+
+    #[test]
+    fn diff_needs_two_chats() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let (result, out, _) = run_args(&["cp2md", "diff", &a, &b]);
+        result.unwrap();
+        assert!(out.contains("- 1. from a\n+ 1. from b\n"));
+
+        let (result, _, _) = run_args(&["cp2md", "diff", &a]);
+        assert!(matches!(
+            result.unwrap_err(),
+            Error::DiffNeedsTwoChats { count: 1 }
+        ));
+    }
+
+    #[test]
+    fn closed_stdout_ends_the_run_quietly() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        for line in [
+            &["cp2md", "-o", "-", "--concat", &a, &b][..],
+            &["cp2md", "-o", "-", &a],
+        ] {
+            let command = parse_args(line.iter().copied()).unwrap();
+            let mut err = Vec::new();
+            let error = run(&command, &mut ClosedPipe, &mut err).unwrap_err();
+            assert!(error.is_broken_pipe(), "{error:?}");
+            assert!(err.is_empty());
+        }
+
+        let error = Error::Console {
+            source: io::Error::other("disk full"),
+        };
+        assert!(!error.is_broken_pipe());
+        assert_eq!(error.exit_code(), 4);
+    }
+
+    #[test]
+    fn prints_version() {
+        let (result, out, _) = run_args(&["cp2md", "-V"]);
+        result.unwrap();
+        assert_eq!(out, format!("cp2md {}\n", env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn stdout_takes_one_file_unless_concatenating() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+
+        let (result, out, _) = run_args(&["cp2md", "-o", "-", &a, &b]);
+        assert!(matches!(result, Err(Error::MultipleFilesToStdout)));
+        assert!(out.is_empty());
+
+        let (result, out, err) = run_args(&["cp2md", "-o", "-", &a]);
+        result.unwrap();
+        assert!(out.contains("from a") && !out.contains("from b"));
+        assert!(err.is_empty());
+
+        let (result, out, _) = run_args(&["cp2md", "--concat", "-o", "-", &a, &b]);
+        result.unwrap();
+        assert!(out.find("from a") < out.find("from b"));
+    }
+
+    #[test]
+    fn single_file_without_output_is_written_next_to_it() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let md = temp.path().join("a.md");
+
+        let (result, out, _) = run_args(&["cp2md", &a]);
+        assert_eq!(result.unwrap().written, 1);
+        assert!(out.is_empty());
+        assert!(fs::read_to_string(&md).unwrap().contains("from a"));
+
+        let (result, _, _) = run_args(&["cp2md", "--concat", &a]);
+        assert_eq!(result.unwrap().skipped_existing, 1);
+    }
+
+    #[test]
+    fn output_next_to_input_respects_existing_files() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let md = temp.path().join("a.md");
+        fs::write(&md, "existing").unwrap();
+
+        let (result, _, _) = run_args(&["cp2md", &a]);
+        assert_eq!(result.unwrap().skipped_existing, 1);
+        assert_eq!(fs::read_to_string(&md).unwrap(), "existing");
+
+        let (result, _, _) = run_args(&["cp2md", "-f", &a]);
+        assert_eq!(result.unwrap().written, 1);
+        assert!(fs::read_to_string(&md).unwrap().contains("from a"));
+    }
+
+    #[test]
+    fn explicit_output_overrides_writing_next_to_input() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let out_dir = temp.path().join("out");
+
+        let (result, _, _) = run_args(&["cp2md", "-o", &out_dir.display().to_string(), &a]);
+        result.unwrap();
+        assert!(out_dir.join("a.md").exists());
+        assert!(!temp.path().join("a.md").exists());
+
+        let (result, out, _) = run_args(&["cp2md", "-o", "-", &a]);
+        result.unwrap();
+        assert!(out.contains("from a"));
+        assert!(!temp.path().join("a.md").exists());
+    }
+
+    #[test]
+    fn output_is_required_for_more_than_one_file() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+
+        let (result, _, _) = run_args(&["cp2md", &a, &b]);
+        assert!(matches!(result, Err(Error::MissingOutput)));
+
+        let dir = temp.path().display().to_string();
+        let (result, _, _) = run_args(&["cp2md", &dir]);
+        assert!(matches!(result, Err(Error::MissingOutput)));
+        assert!(!temp.path().join("a.md").exists());
+    }
+
+    #[test]
+    fn concat_writes_one_file_where_a_directory_would_be() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let target = temp.path().join("all.md");
+        let target_arg = target.display().to_string();
+
+        let (result, out, err) = run_args(&["cp2md", "--concat", "-o", &target_arg, &a, &b]);
+        assert_eq!(result.unwrap().written, 1);
+        assert!(out.is_empty());
+        assert_eq!(err, format!("Wrote {target_arg} (2 files, 2 exchanges)\n"));
+        assert!(target.is_file());
+
+        let (result, _, err) = run_args(&["cp2md", "--concat", "-o", &target_arg, &a, &b]);
+        assert_eq!(result.unwrap().skipped_existing, 1);
+        assert!(err.starts_with("Skipping"));
+    }
+
+    #[test]
+    fn writes_latin1_output_with_fallback() {
+        let temp = TempDir::new().unwrap();
+        let input = temp.path().join("chat.json");
+        let json = r#"{"responderUsername":"Copilot","requests":[
+            {"message":{"text":"Café?"},"response":[{"value":"“Oui” — 日本"}]}]}"#;
+        fs::write(&input, json).unwrap();
+        let input = input.display().to_string();
+        let out_arg = temp.path().join("out").display().to_string();
+        let output = temp.path().join("out").join("chat.md");
+
+        let args = [
+            "cp2md",
+            "--output-encoding",
+            "latin-1",
+            "-o",
+            &out_arg,
+            &input,
+        ];
+        run_args(&args).0.unwrap();
+        let bytes = fs::read(&output).unwrap();
+        assert!(std::str::from_utf8(&bytes).is_err());
+        let text = decode_latin1(&bytes);
+        assert!(text.contains("Café?"));
+        assert!(text.contains("?Oui? ? ??\n"));
+
+        // Unchanged output is recognized in its encoding
+        let (result, _, _) = run_args(&[
+            "cp2md",
+            "--output-encoding",
+            "latin-1",
+            "--overwrite-if-changed",
+            "-o",
+            &out_arg,
+            &input,
+        ]);
+        assert_eq!(result.unwrap().unchanged, 1);
+
+        let args = [
+            "cp2md",
+            "--output-encoding",
+            "latin-1",
+            "--encoding-fallback",
+            "[?]",
+            "-f",
+            "-o",
+            &out_arg,
+            &input,
+        ];
+        run_args(&args).0.unwrap();
+        let bytes = fs::read(&output).unwrap();
+        assert!(decode_latin1(&bytes).contains("[?]Oui[?] [?] [?][?]\n"));
+    }
+
+    /// Reads Latin-1 bytes, each of which is the code point of its value.
+    fn decode_latin1(bytes: &[u8]) -> String {
+        bytes.iter().copied().map(char::from).collect()
+    }
+
+    #[test]
+    fn latin1_output_leaves_no_c1_control_bytes() {
+        let cli = parse_args_from(args(
+            "cp2md --output-encoding latin-1 --encoding-fallback ? -o out x.json",
+        ))
+        .unwrap();
+        let bytes = encode_output("‘£5’ or “€5…” – ÿ", &cli);
+        assert_eq!(&bytes[..], b"?\xa35? or ??5?? ? \xff");
+        assert!(!bytes.iter().any(|byte| (0x80..0xa0).contains(byte)));
+    }
+
+    #[test]
+    fn skips_empty_chats_unless_kept() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let empty = temp.path().join("empty.json");
+        fs::write(&empty, r#"{"responderUsername":"Copilot","requests":[]}"#).unwrap();
+        let hidden = temp.path().join("hidden.json");
+        let json = r#"{"responderUsername":"Copilot","requests":[
+            {"message":{"text":"sync"},"response":[],"isHidden":true}]}"#;
+        fs::write(&hidden, json).unwrap();
+        let out_dir = temp.path().join("out");
+        let out_arg = out_dir.display().to_string();
+        let inputs = [
+            &a,
+            &empty.display().to_string(),
+            &hidden.display().to_string(),
+        ];
+
+        let mut args = vec!["cp2md", "-o", &out_arg];
+        args.extend(inputs.iter().map(|input| input.as_str()));
+        let (result, _, err) = run_args(&args);
+        let summary = result.unwrap();
+        assert_eq!((summary.written, summary.skipped_empty), (1, 2));
+        assert!(err.contains("empty.json (empty chat, use --keep-empty to convert it)"));
+        assert!(!out_dir.join("empty.md").exists() && !out_dir.join("hidden.md").exists());
+
+        args.insert(1, "--keep-empty");
+        let (result, _, _) = run_args(&args);
+        assert_eq!(result.unwrap().skipped_empty, 0);
+        assert!(out_dir.join("empty.md").exists() && out_dir.join("hidden.md").exists());
+    }
+
+    #[test]
+    fn concat_leaves_out_empty_chats() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let empty = temp.path().join("empty.json");
+        fs::write(&empty, r#"{"responderUsername":"Copilot","requests":[]}"#).unwrap();
+        let empty_arg = empty.display().to_string();
+
+        let (result, out, err) = run_args(&["cp2md", "--concat", "-o", "-", &a, &empty_arg, &b]);
+        assert_eq!(result.unwrap().skipped_empty, 1);
+        assert_eq!(out.matches("\n---\n").count(), 1);
+        assert!(!out.contains("# empty"));
+        assert!(err.contains("empty chat"));
+    }
+
+    #[test]
+    fn converts_each_line_of_a_jsonl_archive() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("archive.jsonl");
+        let chat = |text: &str| {
+            format!(
+                r#"{{"responderUsername":"Copilot","requests":[{{"message":{{"text":"{text}"}},"response":[{{"value":"ok"}}]}}]}}"#
+            )
+        };
+        fs::write(
+            &archive,
+            format!("{}\n\n{}\n", chat("first"), chat("second")),
+        )
+        .unwrap();
+        let input = archive.display().to_string();
+
+        let out_dir = temp.path().join("out");
+        let out_arg = out_dir.display().to_string();
+        let (result, _, _) = run_args(&["cp2md", "-o", &out_arg, &input]);
+        assert_eq!(result.unwrap().written, 2);
+        let first = fs::read_to_string(out_dir.join("archive-1.md")).unwrap();
+        assert!(first.contains("first") && !first.contains("second"));
+        let second = fs::read_to_string(out_dir.join("archive-3.md")).unwrap();
+        assert!(second.contains("second") && !second.contains("first"));
+
+        let (result, out, _) = run_args(&["cp2md", "--concat", "-o", "-", &input]);
+        result.unwrap();
+        assert_eq!(out.matches("\n---\n").count(), 1);
+        assert!(out.contains("# archive-1\n") && out.contains("# archive-3\n"));
+
+        let (result, _, _) = run_args(&["cp2md", "-o", "-", &input]);
+        assert!(matches!(result, Err(Error::MultipleChatsToStdout { .. })));
+    }
+
+    #[test]
+    fn replaces_invalid_utf8_unless_strict() {
+        let temp = TempDir::new().unwrap();
+        let input = temp.path().join("latin1.json");
+        fs::write(
+            &input,
+            b"{\"responderUsername\":\"Copilot\",\"requests\":[{\"message\":{\"text\":\"caf\xe9\"},\"response\":[]}]}",
+        )
+        .unwrap();
+        let input = input.display().to_string();
+
+        let (result, out, err) = run_args(&["cp2md", "-o", "-", &input]);
+        result.unwrap();
+        assert!(out.contains("caf\u{fffd}"));
+        assert!(err.contains(&format!(
+            "Warning: {input}: invalid UTF-8 at byte 66 was replaced with U+FFFD"
+        )));
+
+        let (result, out, _) = run_args(&["cp2md", "--strict", "-o", "-", &input]);
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().contains("invalid UTF-8 at byte 66"),
+            "{err}"
+        );
+        assert!(out.is_empty());
+    }
+
+    /// Writes a chat to `input` whose one request has the given selection
+    /// context `variables`, made with [`selection`].
+    fn write_chat_with_selections(input: &Path, variables: &[String]) -> String {
+        let json = format!(
+            r#"{{"responderUsername":"Copilot","requests":[{{"message":{{"text":"hi"}},
+                "response":[],"variableData":{{"variables":[{}]}}}}]}}"#,
+            variables.join(",")
+        );
+        fs::write(input, json).unwrap();
+        input.display().to_string()
+    }
+
+    /// Returns the JSON of a selection of lines `start` to `end` of `path`.
+    fn selection(path: &Path, start: u32, end: u32) -> String {
+        format!(
+            r#"{{"kind":"file","id":"vscode.implicit.selection","name":"file:{}",
+                "value":{{"uri":{{"path":{}}},
+                "range":{{"startLineNumber":{start},"endLineNumber":{end}}}}}}}"#,
+            path.file_name().unwrap_or_default().display(),
+            serde_json::json!(path.display().to_string())
+        )
+    }
+
+    #[test]
+    fn embeds_selections_read_from_disk() {
+        let temp = TempDir::new().unwrap();
+        let source = temp.path().join("lib.rs");
+        fs::write(&source, "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+        let latin1 = temp.path().join("old.rs");
+        fs::write(&latin1, b"// caf\xe9\nfn d() {}\n").unwrap();
+        let variables = [
+            selection(&source, 2, 3),
+            selection(&source, 3, 9),
+            selection(&latin1, 1, 2),
+            selection(&temp.path().join("gone.rs"), 1, 1),
+        ];
+        let input = write_chat_with_selections(&temp.path().join("chat.json"), &variables);
+
+        let (result, out, _) = run_args(&["cp2md", "--embed-selections", "-o", "-", &input]);
+        result.unwrap();
+        assert!(out.contains(":2-3 (selection)\n\n  ```rust\n  fn b() {}\n  fn c() {}\n  ```\n"));
+        assert_eq!(out.matches("*(source unavailable)*").count(), 3);
+
+        let (result, out, _) = run_args(&["cp2md", "-o", "-", &input]);
+        result.unwrap();
+        assert!(!out.contains("fn b()"));
+        assert!(!out.contains("source unavailable"));
+    }
+
+    #[test]
+    fn selections_outside_regular_files_are_unavailable() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("lib.rs"), "fn a() {}\n").unwrap();
+        let big = temp.path().join("big.rs");
+        fs::File::create(&big)
+            .unwrap()
+            .set_len(MAX_SELECTION_SOURCE_BYTES + 1)
+            .unwrap();
+        let mut variables = vec![
+            // Relative to the export's directory, not the working directory
+            selection(Path::new("lib.rs"), 1, 1),
+            selection(temp.path(), 1, 1),
+            selection(&big, 1, 1),
+        ];
+        if cfg!(unix) {
+            variables.push(selection(Path::new("/dev/zero"), 1, 1));
+        }
+        let input = write_chat_with_selections(&temp.path().join("chat.json"), &variables);
+
+        let (result, out, _) = run_args(&["cp2md", "--embed-selections", "-o", "-", &input]);
+        result.unwrap();
+        assert!(out.contains("  ```rust\n  fn a() {}\n  ```\n"), "{out}");
+        assert_eq!(
+            out.matches("*(source unavailable)*").count(),
+            variables.len() - 1
+        );
+    }
+
+    #[test]
+    fn concat_separator_can_be_changed_or_left_out() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+
+        let (result, out, _) = run_args(&[
+            "cp2md",
+            "--concat",
+            "--separator",
+            r"* * *\n",
+            "-o",
+            "-",
+            &a,
+            &b,
+        ]);
+        result.unwrap();
+        assert_eq!(out.matches("\n* * *\n\n# ").count(), 1);
+        assert!(!out.contains("---"));
+
+        let (result, out, _) =
+            run_args(&["cp2md", "--concat", "--no-separator", "-o", "-", &a, &b]);
+        result.unwrap();
+        assert_eq!(out.matches("\n\n# ").count(), 1);
+        assert!(!out.contains("---"));
+    }
+
+    #[test]
+    fn concat_per_dir_writes_one_file_per_input_directory() {
+        let temp = TempDir::new().unwrap();
+        let [work, personal] = ["work", "personal"].map(|name| {
+            let dir = temp.path().join(name);
+            fs::create_dir(&dir).unwrap();
+            write_chats(&dir);
+            dir.display().to_string()
+        });
+        let out_dir = temp.path().join("out");
+        let out_arg = out_dir.display().to_string();
+
+        let (result, _, err) = run_args(&[
+            "cp2md",
+            "--concat-per-dir",
+            "-o",
+            &out_arg,
+            &work,
+            &personal,
+        ]);
+        assert_eq!(result.unwrap().written, 2);
+        assert_eq!(
+            err,
+            format!(
+                "Wrote {} (2 files, 2 exchanges)\nWrote {} (2 files, 2 exchanges)\n",
+                out_dir.join("work.md").display(),
+                out_dir.join("personal.md").display()
+            )
+        );
+        for name in ["work.md", "personal.md"] {
+            let combined = fs::read_to_string(out_dir.join(name)).unwrap();
+            assert!(combined.contains("from a") && combined.contains("from b"));
+        }
+        assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 2);
+
+        // Two directories with the same name get distinct outputs
+        let other = temp.path().join("other").join("work");
+        fs::create_dir_all(&other).unwrap();
+        write_chats(&other);
+        let other_arg = other.display().to_string();
+        let args = [
+            "cp2md",
+            "--concat-per-dir",
+            "-f",
+            "-o",
+            &out_arg,
+            &work,
+            &other_arg,
+        ];
+        let (result, _, err) = run_args(&args);
+        result.unwrap();
+        assert!(err.contains(&format!(
+            "Writing {other_arg} to {} ({} is taken by {work})",
+            out_dir.join("work-other.md").display(),
+            out_dir.join("work.md").display()
+        )));
+        for name in ["work.md", "work-other.md"] {
+            let combined = fs::read_to_string(out_dir.join(name)).unwrap();
+            assert!(combined.contains("from a") && combined.contains("from b"));
+        }
+
+        let (result, _, _) =
+            run_args(&[&args[..2], &["--on-collision", "error"], &args[2..]].concat());
+        assert!(matches!(
+            result,
+            Err(Error::OutputCollision { path, .. }) if path == out_dir.join("work.md")
+        ));
+    }
+
+    #[test]
+    fn file_target_requires_concat() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let mut cli = parse_args_from(["cp2md", "--concat", "-o", "all.md", &a]).unwrap();
+        cli.concat = false;
+
+        let result = run(
+            &Command::Convert(Box::new(cli)),
+            &mut io::sink(),
+            &mut io::sink(),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::FileOutputRequiresConcat { path }) if path == Path::new("all.md")
+        ));
+    }
+
+    /// Writes a chat named `chat.json` into each of `dirs` under `root`,
+    /// returning their paths.
+    fn write_same_named_chats(root: &Path, dirs: &[&str]) -> Vec<String> {
+        dirs.iter()
+            .map(|dir| {
+                let dir = root.join(dir);
+                fs::create_dir_all(&dir).unwrap();
+                let path = dir.join("chat.json");
+                let json = format!(
+                    r#"{{"responderUsername":"Copilot","requests":[
+                        {{"message":{{"text":"from {}"}},"response":[]}}]}}"#,
+                    dir.display()
+                );
+                fs::write(&path, json).unwrap();
+                path.display().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renames_outputs_that_share_a_name() {
+        let temp = TempDir::new().unwrap();
+        let inputs = write_same_named_chats(temp.path(), &["projA", "projB", "projC"]);
+        let out_dir = temp.path().join("out");
+        let out_arg = out_dir.display().to_string();
+
+        let mut argv = vec!["cp2md", "-o", &out_arg];
+        argv.extend(inputs.iter().map(String::as_str));
+        let (result, _, err) = run_args(&argv);
+        assert_eq!(result.unwrap().written, 3);
+        for (name, dir) in [
+            ("chat.md", "projA"),
+            ("chat-projB.md", "projB"),
+            ("chat-projC.md", "projC"),
+        ] {
+            let markdown = fs::read_to_string(out_dir.join(name)).unwrap();
+            assert!(markdown.contains(dir), "{name} is not from {dir}");
+        }
+        assert!(err.contains(&format!(
+            "Writing {} to {out_arg}/chat-projB.md ({out_arg}/chat.md is taken by {})",
+            inputs[1], inputs[0]
+        )));
+
+        // Directories with the same name fall back to numbers
+        let temp = TempDir::new().unwrap();
+        let inputs = write_same_named_chats(temp.path(), &["a/x", "b/x", "c/x"]);
+        let out_arg = temp.path().join("out").display().to_string();
+        let mut argv = vec!["cp2md", "-n", "-o", &out_arg];
+        argv.extend(inputs.iter().map(String::as_str));
+        let (result, _, err) = run_args(&argv);
+        result.unwrap();
+        let planned: Vec<_> = err
+            .lines()
+            .filter_map(|line| line.strip_prefix("Would write "))
+            .collect();
+        assert_eq!(
+            planned,
+            [
+                format!("{out_arg}/chat.md"),
+                format!("{out_arg}/chat-x.md"),
+                format!("{out_arg}/chat-2.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn on_collision_chooses_error_or_overwrite() {
+        let temp = TempDir::new().unwrap();
+        let inputs = write_same_named_chats(temp.path(), &["projA", "projB"]);
+        let out_dir = temp.path().join("out");
+        let out_arg = out_dir.display().to_string();
+
+        let (result, _, _) = run_args(&[
+            "cp2md",
+            "--on-collision",
+            "error",
+            "-o",
+            &out_arg,
+            &inputs[0],
+            &inputs[1],
+        ]);
+        let err = result.unwrap_err();
+        assert!(matches!(err, Error::OutputCollision { .. }));
+        assert_eq!(err.exit_code(), 1);
+        assert!(out_dir.join("chat.md").exists());
+
+        let (result, _, err) = run_args(&[
+            "cp2md",
+            "-f",
+            "--on-collision",
+            "overwrite",
+            "--log-format",
+            "json",
+            "-o",
+            &out_arg,
+            &inputs[0],
+            &inputs[1],
+        ]);
+        assert_eq!(result.unwrap().written, 2);
+        let markdown = fs::read_to_string(out_dir.join("chat.md")).unwrap();
+        assert!(markdown.contains("projB"));
+        assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 1);
+        let collision: serde_json::Value = err
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .find(|value: &serde_json::Value| value["event"] == "collision")
+            .unwrap();
+        assert_eq!(collision["input"], inputs[1].as_str());
+        assert_eq!(collision["first"], inputs[0].as_str());
+        assert!(collision.get("renamed").is_none());
+    }
+
+    #[test]
+    fn dry_run_reports_without_writing() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let out_dir = temp.path().join("out");
+        let out_arg = out_dir.display().to_string();
+
+        let (result, out, err) = run_args(&["cp2md", "-n", "-o", &out_arg, &a, &b]);
+        assert_eq!(result.unwrap(), RunSummary::default());
+        assert!(out.is_empty());
+        assert_eq!(
+            err,
+            format!("Would write {out_arg}/a.md\nWould write {out_arg}/b.md\n")
+        );
+        assert!(!out_dir.exists());
+    }
+
+    #[test]
+    fn errors_map_to_exit_codes() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let bad = temp.path().join("bad.json");
+        fs::write(&bad, "{").unwrap();
+        let bad = bad.display().to_string();
+        let missing = temp.path().join("missing.json").display().to_string();
+        // A regular file where the output directory should be
+        let blocked = temp.path().join("blocked");
+        fs::write(&blocked, "").unwrap();
+        let blocked = blocked.display().to_string();
+
+        let code = |args: &[&str]| run_args(args).0.unwrap_err().exit_code();
+        assert_eq!(code(&["cp2md", "-o", "-", &a, &b]), 1);
+        assert_eq!(code(&["cp2md", "-o", "out", &missing]), 2);
+        assert_eq!(code(&["cp2md", "-o", "-", &bad]), 3);
+        assert_eq!(code(&["cp2md", "--validate", &a, &bad]), 3);
+        assert_eq!(code(&["cp2md", "-o", &blocked, &a]), 4);
+        assert_eq!(
+            parse_args(args("cp2md --heading-offset 9 -o - x.json"))
+                .unwrap_err()
+                .exit_code(),
+            1
+        );
+    }
+
+    #[test]
+    fn detect_prints_a_hint_per_file() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+
+        let (result, out, err) = run_args(&["cp2md", "--detect", &a, &b]);
+        assert_eq!(result.unwrap(), RunSummary::default());
+        assert_eq!(
+            out,
+            format!(
+                "{a}: unknown: 1 request, untagged text\n{b}: unknown: 1 request, untagged text\n"
+            )
+        );
+        assert!(err.is_empty());
+        assert!(!temp.path().join("a.md").exists());
+    }
+
+    #[test]
+    fn pretty_errors_point_at_the_bad_json() {
+        let temp = TempDir::new().unwrap();
+        let bad = temp.path().join("bad.json");
+        fs::write(
+            &bad,
+            "{\n  \"responderUsername\": \"Copilot\",\n  \"requests\": [}\n}",
+        )
+        .unwrap();
+        let bad = bad.display().to_string();
+        let frame = "  |\n3 |   \"requests\": [}\n  |                ^\n";
+
+        let (result, _, err) = run_args(&["cp2md", "-o", "-", &bad]);
+        assert!(matches!(result, Err(Error::ParseFile { .. })));
+        assert!(err.is_empty());
+
+        let (result, _, err) = run_args(&["cp2md", "--pretty-errors", "-o", "-", &bad]);
+        assert!(matches!(result, Err(Error::ParseFile { .. })));
+        assert_eq!(
+            err,
+            format!("{bad}: expected value at line 3 column 16\n{frame}")
+        );
+
+        let (_, out, _) = run_args(&["cp2md", "--validate", "--pretty-errors", &bad]);
+        let reason = r#"expected value at line 3 column 16 near "derUsername": "Copilot",\n  "requests": [}\n}""#;
+        let indented = "    |\n  3 |   \"requests\": [}\n    |                ^\n";
+        assert_eq!(
+            out,
+            format!("FAIL {bad}: failed to parse JSON: {reason}\n{indented}")
+        );
+    }
+
+    #[test]
+    fn strict_exit_fails_on_skipped_outputs() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let out_arg = temp.path().join("out").display().to_string();
+
+        let (result, _, _) = run_args(&["cp2md", "--strict-exit", "-q", "-o", &out_arg, &a]);
+        assert_eq!(result.unwrap().written, 1);
+
+        let (result, _, _) = run_args(&["cp2md", "-q", "-o", &out_arg, &a, &b]);
+        result.unwrap();
+
+        let args = ["cp2md", "--strict-exit", "-q", "-o", &out_arg, &a, &b];
+        let (result, _, err) = run_args(&args);
+        let error = result.unwrap_err();
+        assert!(matches!(error, Error::OutputsSkipped { count: 2 }));
+        assert_eq!(error.exit_code(), 4);
+        assert!(err.starts_with("Skipping"));
+
+        let args = ["cp2md", "--strict-exit", "-f", "-q", "-o", &out_arg, &a, &b];
+        run_args(&args).0.unwrap();
+    }
+
+    #[test]
+    fn color_choice_decides_ansi_codes_in_progress() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let out_arg = temp.path().join("out").display().to_string();
+
+        let (result, _, err) = run_args(&["cp2md", "--color", "never", "-o", &out_arg, &a]);
+        result.unwrap();
+        assert!(err.starts_with("Wrote ") && !err.contains('\x1b'));
+
+        let (result, _, err) = run_args(&["cp2md", "--color", "always", "-o", &out_arg, &a]);
+        result.unwrap();
+        assert!(err.starts_with("\x1b[33mSkipping ") && err.ends_with("\x1b[0m\n"));
+
+        let args = ["cp2md", "--color", "always", "-f", "-o", &out_arg, &a];
+        let (result, _, err) = run_args(&args);
+        result.unwrap();
+        assert!(err.starts_with("\x1b[32mWrote "));
+    }
+
+    #[test]
+    fn json_log_format_writes_one_event_per_line() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let out_dir = temp.path().join("out");
+        let out_arg = out_dir.display().to_string();
+        fs::create_dir(&out_dir).unwrap();
+        fs::write(out_dir.join("b.md"), "kept").unwrap();
+
+        let args = ["cp2md", "--log-format", "json", "-o", &out_arg, &a, &b];
+        let (result, _, err) = run_args(&args);
+        result.unwrap();
+        let events: Vec<serde_json::Value> = err
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+
+        assert_eq!(events[0]["event"], "wrote");
+        assert_eq!(events[0]["input"], a.as_str());
+        assert_eq!(events[0]["output"], out_dir.join("a.md").to_str().unwrap());
+        let written = fs::metadata(out_dir.join("a.md")).unwrap().len();
+        assert_eq!(events[0]["bytes"], written);
+
+        assert_eq!(events[1]["event"], "skipped");
+        assert_eq!(events[1]["reason"], "exists");
+        assert_eq!(events[1]["output"], out_dir.join("b.md").to_str().unwrap());
+
+        assert_eq!(events[2]["event"], "summary");
+        assert_eq!(
+            (&events[2]["written"], &events[2]["skipped_existing"]),
+            (&1.into(), &1.into())
+        );
+    }
+
+    #[test]
+    fn json_log_format_reports_dry_runs_and_concatenation() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let dir_arg = temp.path().join("out").display().to_string();
+        let out_arg = temp.path().join("all.md").display().to_string();
+
+        let args = ["cp2md", "--log-format", "json", "-n", "-o", &dir_arg, &a];
+        let (result, _, err) = run_args(&args);
+        result.unwrap();
+        let event: serde_json::Value = serde_json::from_str(err.lines().next().unwrap()).unwrap();
+        assert_eq!(event["event"], "dry-run");
+        assert_eq!(event["input"], a.as_str());
+
+        let args = [
+            "cp2md",
+            "--log-format",
+            "json",
+            "--concat",
+            "-o",
+            &out_arg,
+            &a,
+            &b,
+        ];
+        let (result, _, err) = run_args(&args);
+        result.unwrap();
+        let event: serde_json::Value = serde_json::from_str(err.lines().next().unwrap()).unwrap();
+        assert_eq!(event["event"], "wrote");
+        assert_eq!(
+            (&event["files"], &event["exchanges"]),
+            (&2.into(), &2.into())
+        );
+        assert!(event.get("input").is_none());
+    }
+
+    #[test]
+    fn summary_counts_written_and_skipped_outputs() {
+        let temp = TempDir::new().unwrap();
+        let [a, b] = write_chats(temp.path());
+        let out_arg = temp.path().join("out").display().to_string();
+
+        let (result, _, _) = run_args(&["cp2md", "-q", "-o", &out_arg, &a, &b]);
+        assert_eq!(
+            result.unwrap(),
+            RunSummary {
+                written: 2,
+                ..RunSummary::default()
+            }
+        );
+
+        let (result, _, err) = run_args(&["cp2md", "-q", "-o", &out_arg, &a, &b]);
+        assert_eq!(result.unwrap().skipped_existing, 2);
+        assert_eq!(err.lines().count(), 2);
+
+        let args = [
+            "cp2md",
+            "-q",
+            "--overwrite-if-changed",
+            "-o",
+            &out_arg,
+            &a,
+            &b,
+        ];
+        let (result, _, err) = run_args(&args);
+        assert_eq!(result.unwrap().unchanged, 2);
+        assert!(err.is_empty());
+    }
+
+    // =========================================================================
+    // Pure rendering tests (no I/O)
+    // =========================================================================
+
+    #[test]
+    fn only_agent_keeps_matching_requests() {
+        let mut chat = parser::parse_chat(
+            r#"{"responderUsername":"Copilot","requests":[
+                {"agent":{"name":"code-reviewer"},"message":{"text":"review"},"response":[]},
+                {"agent":{"name":"workspace"},"message":{"text":"search"},"response":[]},
+                {"message":{"text":"plain"},"response":[]},
+                {"agent":{"name":"code-reviewer"},"message":{"text":"again"},"response":[]}
+            ]}"#,
+        )
+        .unwrap();
+
+        retain_agent_requests(&mut chat, "code-reviewer");
+
+        let texts: Vec<_> = chat
+            .requests
+            .iter()
+            .map(|r| r.message.text.as_str())
+            .collect();
+        assert_eq!(texts, ["review", "again"]);
+    }
+
+    #[test]
+    fn only_agent_skips_chats_without_matching_requests() {
+        let temp = TempDir::new().unwrap();
+        let input = temp.path().join("chat.json");
+        fs::write(
+            &input,
+            r#"{"responderUsername":"Copilot","requests":[
+                {"agent":{"name":"workspace"},"message":{"text":"hi"},"response":[]}]}"#,
+        )
+        .unwrap();
+        let cli = parse_args_from(args("cp2md --only-agent reviewer -o out x.json")).unwrap();
+
+        let (mut out, mut err) = (io::sink(), Vec::new());
+        let (entries, skipped) = Runner::new(&cli, &mut out, &mut err)
+            .prepare_chats(&input)
+            .unwrap();
+        assert!(entries.is_empty());
+        assert_eq!(skipped, 1);
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            format!(
+                "Skipping {} (no requests handled by @reviewer)\n",
+                input.display()
+            )
+        );
+    }
+
+    #[test]
+    fn concat_counts_total_included_files() {
+        let temp = TempDir::new().unwrap();
+        let write_chat = |name: &str, agents: &[&str]| {
+            let requests: Vec<_> = agents
+                .iter()
+                .map(|agent| {
+                    format!(r#"{{"agent":{{"name":"{agent}"}},"message":{{"text":"hi"}},"response":[]}}"#)
+                })
+                .collect();
+            let path = temp.path().join(name);
+            let json = format!(
+                r#"{{"responderUsername":"Copilot","requests":[{}]}}"#,
+                requests.join(",")
+            );
+            fs::write(&path, json).unwrap();
+            path
+        };
+        let files = [
+            write_chat("a.json", &["reviewer", "reviewer", "workspace"]),
+            write_chat("b.json", &["workspace"]),
+            write_chat("c.json", &["reviewer"]),
+        ];
+        let (mut out, mut err) = (io::sink(), io::sink());
+
+        let cli = parse_args_from(args("cp2md --concat -o out.md x.json")).unwrap();
+        let (chats, _, _) = Runner::new(&cli, &mut out, &mut err)
+            .load_chats(&files)
+            .unwrap();
+        assert_eq!(
+            concat_counts(&chats, 0, None).to_string(),
+            "3 files, 5 exchanges"
+        );
+        assert_eq!(
+            concat_counts(&chats, 0, Some(1)).to_string(),
+            "3 files, 3 exchanges"
+        );
+
+        let cli =
+            parse_args_from(args("cp2md --concat --only-agent reviewer -o out.md x")).unwrap();
+        let (chats, _, skipped) = Runner::new(&cli, &mut out, &mut err)
+            .load_chats(&files)
+            .unwrap();
+        assert_eq!(
+            concat_counts(&chats, skipped, None).to_string(),
+            "2 files, 3 exchanges, 1 skipped"
+        );
+        assert_eq!(
+            concat_counts(&chats[1..], 2, None).to_string(),
+            "1 file, 1 exchange, 2 skipped"
+        );
+    }
+
+    #[test]
+    fn chat_title_falls_back_to_file_stem() {
+        let chat = parser::parse_chat(r#"{"responderUsername":"Copilot","requests":[]}"#).unwrap();
+        assert_eq!(
+            chat_title(Path::new("dir/session.json"), None, &chat),
+            "session"
+        );
+        assert_eq!(
+            chat_title(Path::new("dir/chats.jsonl"), Some(3), &chat),
+            "chats-3"
+        );
+    }
+
+    // =========================================================================
+    // Filesystem tests (require tempfiles)
+    // =========================================================================
+
+    #[test]
+    fn validate_fails_when_any_input_fails() {
+        let temp = TempDir::new().unwrap();
+        let good = temp.path().join("good.json");
+        fs::write(&good, r#"{"responderUsername":"Copilot","requests":[]}"#).unwrap();
+        let bad = temp.path().join("bad.json");
+        fs::write(&bad, r#"{"responderUsername":"Copilot"}"#).unwrap();
+        let cli = parse_args_from(args("cp2md --validate -q x.json")).unwrap();
+
+        let (mut out, mut err) = (Vec::new(), io::sink());
+        let mut runner = Runner::new(&cli, &mut out, &mut err);
+
+        runner.validate_files(std::slice::from_ref(&good)).unwrap();
+        let error = runner
+            .validate_files(&[good, bad, temp.path().join("missing.json")])
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ValidationFailed {
+                failed: 2,
+                total: 3
+            }
+        ));
+        let report = String::from_utf8(out).unwrap();
+        assert_eq!(report.lines().count(), 2);
+        assert!(report.lines().all(|line| line.starts_with("FAIL ")));
+    }
+
+    #[test]
+    fn collects_unique_json_files_in_order() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+
+        let direct = root.join("b.json");
+        fs::write(&direct, "{}\n").unwrap();
+        fs::write(root.join("a.json"), "{}\n").unwrap();
+
+        let nested = root.join("nested");
+        fs::create_dir(&nested).unwrap();
+        fs::write(nested.join("c.json"), "{}\n").unwrap();
+
+        fs::write(root.join("notes.txt"), "irrelevant").unwrap();
+
+        let json = ["json".to_owned()];
+        let groups = collect_input_files(&[direct.clone(), root.to_path_buf()], &json).unwrap();
+
+        // The directly given file joins its directory's group
+        assert_eq!(
+            groups,
+            vec![InputGroup {
+                dir: root.to_path_buf(),
+                files: vec![direct, root.join("a.json"), nested.join("c.json")],
+            }]
+        );
+
+        let elsewhere = TempDir::new().unwrap();
+        let lone = elsewhere.path().join("d.json");
+        fs::write(&lone, "{}\n").unwrap();
+        let groups = collect_input_files(&[nested.clone(), lone.clone()], &json).unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                InputGroup {
+                    dir: nested.clone(),
+                    files: vec![nested.join("c.json")],
+                },
+                InputGroup {
+                    dir: elsewhere.path().to_path_buf(),
+                    files: vec![lone],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_multi_dot_input_suffixes() {
+        let suffixes = ["copilot.json", "chat"];
+        for name in ["a.copilot.json", "b.chat", "x.y.copilot.json"] {
+            assert!(has_input_suffix(Path::new(name), &suffixes), "{name}");
+        }
+        for name in ["a.json", "acopilot.json", ".chat", "b.chat.bak", "chat"] {
+            assert!(!has_input_suffix(Path::new(name), &suffixes), "{name}");
+        }
+    }
+
+    #[test]
+    fn collects_files_with_chosen_suffixes() {
+        let temp = TempDir::new().unwrap();
+        let root = temp.path();
+        for name in ["a.copilot.json", "b.chat", "c.json"] {
+            fs::write(root.join(name), "{}\n").unwrap();
+        }
+        let direct = root.join("d.txt");
+        fs::write(&direct, "{}\n").unwrap();
+
+        let suffixes = ["copilot.json".to_owned(), "chat".to_owned()];
+        let groups = collect_input_files(&[root.to_path_buf(), direct.clone()], &suffixes).unwrap();
+        assert_eq!(
+            groups[0].files,
+            [root.join("a.copilot.json"), root.join("b.chat"), direct]
+        );
+    }
+
+    #[test]
+    fn writes_anonymize_map_keyed_by_input() {
+        let temp = TempDir::new().unwrap();
+        let input = temp.path().join("chat.json");
+        let map_path = temp.path().join("map.json");
+
+        let mut maps = AnonymizeMaps::new();
+        maps.insert(
+            input.clone(),
+            anonymize::AnonymizeMap {
+                requester: Some("alice".into()),
+                ..Default::default()
+            },
+        );
+        write_anonymize_map(&map_path, &maps).unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&map_path).unwrap()).unwrap();
+        assert_eq!(
+            written[input.to_string_lossy().as_ref()]["requester"],
+            "alice"
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn browser_loads_chats_as_a_conversion_does() {
+        let temp = TempDir::new().unwrap();
+        let chat = r#"{"requesterUsername":"alice","requests":[{"message":{"text":"hi"},"response":[{"value":"ok"}]}]}"#;
+        let single = temp.path().join("single.json");
+        let archive = temp.path().join("chats.jsonl");
+        let broken = temp.path().join("broken.json");
+        fs::write(&single, chat).unwrap();
+        fs::write(&archive, format!("{chat}\n{chat}\n")).unwrap();
+        fs::write(&broken, "{").unwrap();
+        let out = temp.path().join("out");
+
+        let files = [&single, &archive, &broken].map(|path| path.display().to_string());
+        let mut argv = vec![
+            "cp2md",
+            "--browse",
+            "--anonymize",
+            "-o",
+            out.to_str().unwrap(),
+        ];
+        argv.extend(files.iter().map(String::as_str));
+        let cli = parse_args_from(argv).unwrap();
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let mut runner = Runner::new(&cli, &mut stdout, &mut stderr);
+        let conversations = runner
+            .browse_conversations(&[single, archive, broken])
+            .unwrap();
+
+        let out_paths: Vec<_> = conversations.iter().map(|c| &c.out_path).collect();
+        assert_eq!(
+            out_paths,
+            ["single.md", "chats-1.md", "chats-2.md", "broken.md"]
+                .map(|name| out.join(name))
+                .iter()
+                .collect::<Vec<_>>()
+        );
+        for conversation in &conversations[..3] {
+            let chat = conversation.chat.as_ref().unwrap();
+            assert_ne!(chat.requester_username.as_deref(), Some("alice"));
+        }
+        assert!(matches!(
+            conversations[3].chat,
+            Err(Error::ParseFile { .. })
+        ));
+        assert_eq!(runner.maps.len(), 3);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn browser_rejects_file_output() {
+        let temp = TempDir::new().unwrap();
+        let [a, _] = write_chats(temp.path());
+        let (result, _, _) = run_args(&["cp2md", "--browse", "--concat", "-o", "all.md", &a]);
+        assert!(matches!(
+            result,
+            Err(Error::FileOutputRequiresConcat { path }) if path == Path::new("all.md")
+        ));
+    }
+
+    #[test]
+    fn nav_links_outputs_and_writes_index() {
+        let temp = TempDir::new().unwrap();
+        let out = temp.path().join("out");
+        fs::create_dir(&out).unwrap();
+        let files: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                let path = temp.path().join(format!("{name}.json"));
+                let json = r#"{"responderUsername":"Copilot","requests":[
+                    {"message":{"text":"hi"},"response":[]}]}"#;
+                fs::write(&path, json).unwrap();
+                path
+            })
+            .collect();
+        let cli = parse_args_from(args("cp2md --nav -q -o out x.json")).unwrap();
+
+        Runner::new(&cli, &mut io::sink(), &mut io::sink())
+            .process_with_nav(&files, &out)
+            .unwrap();
+
+        let first = fs::read_to_string(out.join("a.md")).unwrap();
+        assert!(first.ends_with("\n---\n\n[Index](index.md) · [Next →](b.md#copilot-chat)\n"));
+        let middle = fs::read_to_string(out.join("b.md")).unwrap();
+        assert!(middle.contains(
+            "[← Previous](a.md#copilot-chat) · [Index](index.md) · [Next →](c.md#copilot-chat)"
+        ));
+        let index = fs::read_to_string(out.join("index.md")).unwrap();
+        assert_eq!(
+            index,
+            "# Index\n\n- [a](a.md#copilot-chat)\n- [b](b.md#copilot-chat)\n- [c](c.md#copilot-chat)\n"
+        );
+    }
+
+    #[test]
+    fn nav_rejects_input_named_index() {
+        let temp = TempDir::new().unwrap();
+        let cli = parse_args_from(args("cp2md --nav -o out x.json")).unwrap();
+        let files = vec![temp.path().join("index.json")];
+
+        let err = Runner::new(&cli, &mut io::sink(), &mut io::sink())
+            .process_with_nav(&files, temp.path())
+            .unwrap_err();
+        assert!(matches!(err, Error::NavIndexConflict { .. }));
+    }
+
+    #[test]
+    fn scan_vscode_finds_chat_sessions() {
+        let temp = TempDir::new().unwrap();
+        let user = temp.path().join("Code").join("User");
+        let chat = r#"{"responderUsername":"Copilot","requests":[
+            {"message":{"text":"hi"},"response":[]}]}"#;
+        let write = |relative: &str, contents: &str| {
+            let path = user.join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        let second = write("workspaceStorage/f00d/chatSessions/2222.json", chat);
+        let first = write("workspaceStorage/beef/chatSessions/1111.json", chat);
+        let not_chat = write(
+            "workspaceStorage/beef/chatSessions/3333.json",
+            r#"{"version":3}"#,
+        );
+        let empty_window = write("globalStorage/emptyWindowChatSessions/4444.json", chat);
+        write(
+            "workspaceStorage/beef/workspace.json",
+            r#"{"folder":"/src"}"#,
+        );
+        write(
+            "workspaceStorage/beef/chatEditingSessions/x/state.json",
+            "{}",
+        );
+
+        // Either the user data directory or its User directory works
+        for input in [temp.path().join("Code"), user.clone()] {
+            let groups = collect_vscode_sessions(&[input]).unwrap();
+            assert_eq!(groups.len(), 1);
+            assert_eq!(
+                groups[0].files,
+                [first.as_path(), &not_chat, &second, &empty_window]
+            );
+        }
+
+        let cli = parse_args_from(args("cp2md --scan-vscode -o out x")).unwrap();
+        let (mut out, mut err) = (io::sink(), io::sink());
+        let mut runner = Runner::new(&cli, &mut out, &mut err);
+        assert_eq!(runner.prepare_chats(&first).unwrap().0.len(), 1);
+        let (entries, skipped) = runner.prepare_chats(&not_chat).unwrap();
+        assert!(entries.is_empty());
+        assert_eq!(skipped, 1);
+        assert_eq!(
+            output_path_for(&first, None, Path::new("out")).unwrap(),
+            Path::new("out/1111.md")
+        );
+    }
+
+    #[test]
+    fn portable_file_name_replaces_invalid_characters() {
+        assert_eq!(portable_file_name("a:b?c*d", "md"), "a_b_c_d.md");
+        assert_eq!(portable_file_name("<x>|\"y\"\\z", "md"), "_x___y__z.md");
+        assert_eq!(portable_file_name("tab\there", "md"), "tab_here.md");
+        assert_eq!(portable_file_name("chat-abc", "md"), "chat-abc.md");
+    }
+
+    #[test]
+    fn portable_file_name_avoids_reserved_names() {
+        assert_eq!(portable_file_name("CON", "md"), "CON_.md");
+        assert_eq!(portable_file_name("nul", "md"), "nul_.md");
+        assert_eq!(portable_file_name("Com1.backup", "md"), "Com1_.backup.md");
+        assert_eq!(portable_file_name("lpt9 ", "md"), "lpt9_.md");
+        assert_eq!(portable_file_name("console", "md"), "console.md");
+        assert_eq!(portable_file_name("COM10", "md"), "COM10.md");
+    }
+
+    #[test]
+    fn portable_file_name_trims_trailing_dots_and_spaces() {
+        assert_eq!(portable_file_name("notes. . ", "md"), "notes.md");
+        assert_eq!(portable_file_name("...", "md"), "_.md");
+        assert_eq!(portable_file_name("", "md"), "_.md");
+    }
+
+    #[test]
+    fn portable_file_name_truncates_on_character_boundary() {
+        let long = "é".repeat(200);
+        let name = portable_file_name(&long, "md");
+        assert!(name.len() <= MAX_FILE_NAME_BYTES);
+        assert_eq!(name, format!("{}.md", "é".repeat(126)));
+
+        let dotted = format!("{}. x", "a".repeat(251));
+        assert_eq!(
+            portable_file_name(&dotted, "md"),
+            format!("{}.md", "a".repeat(251))
+        );
+    }
+
+    #[test]
+    fn renamed_outputs_shorten_the_stem_to_fit() {
+        let input = Path::new("projB/chat.json");
+        let names: Vec<_> = renamed_outputs(input, Path::new("out/chat.md"))
+            .take(3)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Path::new("out/chat-projB.md"),
+                Path::new("out/chat-2.md"),
+                Path::new("out/chat-3.md"),
+            ]
+        );
+
+        let long = Path::new("out").join(format!("{}.md", "é".repeat(126)));
+        for name in renamed_outputs(input, &long).take(3) {
+            assert!(name.file_name().unwrap().len() <= MAX_FILE_NAME_BYTES);
+            assert!(name.extension().is_some_and(|ext| ext == "md"));
+        }
+        assert_ne!(
+            renamed_outputs(input, &long).nth(1),
+            renamed_outputs(input, &long).nth(2)
+        );
+        assert!(
+            renamed_outputs(Path::new("chat.json"), Path::new("chat.md"))
+                .next()
+                .is_some_and(|name| name == Path::new("chat-2.md"))
+        );
+    }
+
+    #[test]
+    fn output_path_is_portable() {
+        assert_eq!(
+            output_path_for(Path::new("chats/aux.json"), None, Path::new("out")).unwrap(),
+            Path::new("out/aux_.md")
+        );
+    }
+
+    #[test]
+    fn discover_lists_chat_sessions() {
+        let temp = TempDir::new().unwrap();
+        let sessions = temp.path().join("User/workspaceStorage/beef/chatSessions");
+        fs::create_dir_all(&sessions).unwrap();
+        fs::write(
+            sessions.join("1111.json"),
+            r#"{"customTitle":"Fix CI","requests":[
+                {"message":{"text":"hi"},"response":[]},
+                {"message":{"text":"again"},"response":[]}]}"#,
+        )
+        .unwrap();
+        fs::write(sessions.join("2222.json"), r#"{"requests":[]}"#).unwrap();
+
+        let (result, out, _) = run_args(&["cp2md", "discover", temp.path().to_str().unwrap()]);
+        result.unwrap();
+        assert_eq!(
+            out,
+            format!(
+                "{}: Fix CI (2 exchanges)\n{}: 2222 (0 exchanges)\n",
+                sessions.join("1111.json").display(),
+                sessions.join("2222.json").display()
+            )
+        );
+    }
+
+    #[test]
+    fn scan_vscode_rejects_other_directories() {
+        let temp = TempDir::new().unwrap();
+        let err = collect_vscode_sessions(&[temp.path().to_path_buf()]).unwrap_err();
+        assert!(matches!(err, Error::NoVscodeStorage { .. }));
+    }
+
+    #[test]
+    fn overwrite_if_changed_skips_identical_output() {
+        let temp = TempDir::new().unwrap();
+        let input = temp.path().join("chat.json");
+        fs::write(
+            &input,
+            r#"{"responderUsername":"Copilot","requests":[
+                {"message":{"text":"hi"},"response":[{"value":"hello"}]}]}"#,
+        )
+        .unwrap();
+        let out = temp.path().join("out");
+        fs::create_dir(&out).unwrap();
+        let output = out.join("chat.md");
+        let cli = parse_args_from(args("cp2md --overwrite-if-changed -q -o out x.json")).unwrap();
+        let (mut stdout, mut stderr) = (io::sink(), io::sink());
+        let mut runner = Runner::new(&cli, &mut stdout, &mut stderr);
+
+        runner.process_file(&input, &out).unwrap();
+        let rendered = fs::read_to_string(&output).unwrap();
+        assert!(rendered.contains("hello"));
+
+        // An unchanged rewrite would bump the modification time
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&output)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        runner.process_file(&input, &out).unwrap();
+        assert_eq!(fs::metadata(&output).unwrap().modified().unwrap(), old);
+
+        fs::write(&output, "stale").unwrap();
+        runner.process_file(&input, &out).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), rendered);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn errors_on_inaccessible_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new().unwrap();
+        let bad_dir = temp.path().join("restricted");
+        fs::create_dir(&bad_dir).unwrap();
+
+        fs::set_permissions(&bad_dir, fs::Permissions::from_mode(0o000)).unwrap();
+        let result = collect_input_files(std::slice::from_ref(&bad_dir), &["json".to_owned()]);
+        assert!(result.is_err());
+
+        // Restore permissions so TempDir cleanup succeeds
+        fs::set_permissions(&bad_dir, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -16,6 +16,8 @@
 //! # Example
 //!
 //! ```no_run
+//! # #[cfg(feature = "render")]
+//! # {
 //! use cp2md::{parser, renderer};
 //!
 //! let json = std::fs::read_to_string("chat.json").unwrap();
@@ -29,14 +31,32 @@
 //!
 //! let markdown = renderer::render_chat(&chat, &opts);
 //! println!("{markdown}");
+//! # }
 //! ```
 //!
 //! # Modules
 //!
 //! - [`parser`]: JSON parsing and type definitions for Copilot chat exports
-//! - [`renderer`]: Markdown generation with configurable output options
+//! - `renderer`: Markdown generation with configurable output options
+//!   (requires the `render` feature, on by default)
+//! - [`anonymize`]: Replacement of usernames and paths for sharing
+//!
+//! # Features
+//!
+//! - `render` (default): the `renderer` module, which pulls in `chrono`,
+//!   `regex`, and `toml`
+//! - `cli` (default): the `cp2md` binary and its argument parsing and
+//!   directory traversal dependencies
+//! - `tui`: the interactive browser behind `cp2md --browse`
+//! - `serde-serialize`: `Serialize` for the parser's types, writing chats
+//!   back out in the export format
+//!
+//! With `default-features = false`, only the parser and anonymizer are
+//! built, depending on nothing beyond `serde`, `serde_json`, and `snafu`.
 
 #![deny(missing_docs)]
 
+pub mod anonymize;
 pub mod parser;
+#[cfg(feature = "render")]
 pub mod renderer;
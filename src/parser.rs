@@ -33,7 +33,11 @@
 //! ```
 
 use serde::Deserialize;
+#[cfg(feature = "serde-serialize")]
+use serde::Serialize;
 use snafu::prelude::*;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 
 /// Error type for JSON parsing failures.
 #[derive(Debug, Snafu)]
@@ -44,6 +48,89 @@ pub enum ParseError {
         /// The underlying JSON parsing error.
         source: serde_json::Error,
     },
+
+    /// The input is not well-formed JSON.
+    ///
+    /// Exports are usually a single line, so the line and column in the
+    /// message say little; the excerpt shows the input around the error.
+    #[snafu(display(
+        "failed to parse JSON: {}{source} near \"{excerpt}\"",
+        if *truncated { "file appears truncated (ends mid-value): " } else { "" }
+    ))]
+    Syntax {
+        /// The underlying JSON parsing error.
+        source: serde_json::Error,
+        /// Up to 40 characters of input on either side of the error, with
+        /// control characters escaped.
+        excerpt: String,
+        /// Whether the input ended in the middle of a value.
+        truncated: bool,
+    },
+
+    /// The input is not UTF-8, and [`ParseOptions::strict_utf8`] refused
+    /// to replace what is not.
+    #[snafu(display("invalid UTF-8 at byte {offset}"))]
+    InvalidUtf8 {
+        /// The underlying decoding error.
+        source: std::str::Utf8Error,
+        /// Byte offset of the first invalid sequence.
+        offset: usize,
+    },
+}
+
+impl ParseError {
+    /// Returns the underlying JSON error, or `None` when the input was not
+    /// read as far as JSON.
+    #[must_use]
+    pub const fn json_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Json { source } | Self::Syntax { source, .. } => Some(source),
+            Self::InvalidUtf8 { .. } => None,
+        }
+    }
+
+    /// Wraps an error from parsing `src`, describing syntax errors with an
+    /// excerpt of the input around them.
+    fn from_json(src: &str, source: serde_json::Error) -> Self {
+        if !(source.is_syntax() || source.is_eof()) {
+            return Self::Json { source };
+        }
+        let offset = byte_offset(src, source.line(), source.column().saturating_sub(1));
+        let before: Vec<char> = src[..offset].chars().collect();
+        let skipped = before.len().saturating_sub(ERROR_CONTEXT_CHARS);
+        let excerpt: String = before[skipped..]
+            .iter()
+            .copied()
+            .chain(src[offset..].chars().take(ERROR_CONTEXT_CHARS))
+            .map(|c| {
+                if c.is_control() {
+                    c.escape_debug().to_string()
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect();
+        Self::Syntax {
+            truncated: source.is_eof(),
+            source,
+            excerpt,
+        }
+    }
+}
+
+/// Converts a 1-based line and a 0-based byte column, as `serde_json` reports
+/// them, to a byte offset into `src` on a character boundary.
+fn byte_offset(src: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = src
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let mut offset = (line_start + column).min(src.len());
+    while !src.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
 }
 
 /// The root structure of a GitHub Copilot chat export.
@@ -51,15 +138,85 @@ pub enum ParseError {
 /// This represents the entire conversation history exported from
 /// a Copilot chat session.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
 #[serde(rename_all = "camelCase")]
 pub struct ChatExport {
     /// The display name of the assistant (typically "GitHub Copilot").
+    ///
+    /// [`DEFAULT_RESPONDER`] when the export does not record one.
+    #[serde(default = "default_responder")]
     pub responder_username: String,
 
+    /// The username of the person who started the chat, when recorded.
+    #[serde(default)]
+    pub requester_username: Option<String>,
+
+    /// The title the user gave the chat in VS Code, if any.
+    #[serde(default)]
+    pub custom_title: Option<String>,
+
+    /// The model chosen for the whole session, when recorded.
+    ///
+    /// Read from a top-level `selectedModel`, given either as a model ID or
+    /// as an object with an `identifier`. When that is missing, the first
+    /// request that names its model supplies it.
+    #[serde(
+        default,
+        rename = "selectedModel",
+        deserialize_with = "deserialize_model"
+    )]
+    pub default_model: Option<String>,
+
     /// The sequence of request/response exchanges in the conversation.
+    ///
+    /// Some variants of the format store these under `exchanges` or `turns`.
+    #[serde(alias = "exchanges", alias = "turns")]
     pub requests: Vec<Request>,
 }
 
+/// The assistant's name for an export that does not record one.
+pub const DEFAULT_RESPONDER: &str = "Assistant";
+
+fn default_responder() -> String {
+    DEFAULT_RESPONDER.to_owned()
+}
+
+impl ChatExport {
+    /// Returns an iterator over the conversation's exchanges, in order.
+    pub fn exchanges(&self) -> std::slice::Iter<'_, Request> {
+        self.requests.iter()
+    }
+
+    /// Returns every file the conversation's responses edited, as
+    /// [`Request::edited_paths`] gives them, once each and sorted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cp2md::parser::parse_chat;
+    ///
+    /// let chat = parse_chat(r#"{
+    ///     "responderUsername": "GitHub Copilot",
+    ///     "requests": [
+    ///         { "message": { "text": "Fix" }, "response": [
+    ///             { "kind": "textEditGroup", "uri": { "path": "/src/b.rs" }, "edits": [[{ "text": "b" }]] }
+    ///         ] },
+    ///         { "message": { "text": "Again" }, "response": [
+    ///             { "kind": "textEditGroup", "uri": { "path": "/src/a.rs" }, "edits": [[{ "text": "a" }]] },
+    ///             { "kind": "textEditGroup", "uri": { "path": "/src/b.rs" }, "edits": [[{ "text": "b" }]] }
+    ///         ] }
+    ///     ]
+    /// }"#).unwrap();
+    ///
+    /// let paths: Vec<_> = chat.all_edited_paths().into_iter().collect();
+    /// assert_eq!(paths, ["/src/a.rs", "/src/b.rs"]);
+    /// ```
+    #[must_use]
+    pub fn all_edited_paths(&self) -> BTreeSet<&str> {
+        self.exchanges().flat_map(Request::edited_paths).collect()
+    }
+}
+
 /// A single request/response exchange in the conversation.
 ///
 /// Each request represents one user message and the corresponding
@@ -87,6 +244,114 @@ pub struct Request {
 
     /// The assistant's response, which may contain multiple elements.
     pub response: Vec<ResponseElement>,
+
+    /// Whether the export marks this request as internal (`isHidden` or
+    /// `shouldBeBlocked`), such as an agent's bookkeeping turn.
+    pub hidden: bool,
+
+    /// Tokens the request used, from its `result.metadata.usage`, when the
+    /// export records them.
+    pub usage: Option<Usage>,
+}
+
+/// Tokens a request used, as [`Request::usage`] gives them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Tokens sent to the model, counting the prompt and its context.
+    pub prompt: u32,
+    /// Tokens the model wrote in its response.
+    pub completion: u32,
+}
+
+impl Request {
+    /// Returns what each of the response's tool invocations did, in order,
+    /// leaving out invocations with no past-tense message or a blank one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cp2md::parser::parse_chat;
+    ///
+    /// let chat = parse_chat(r#"{
+    ///     "responderUsername": "GitHub Copilot",
+    ///     "requests": [{ "message": { "text": "Look" }, "response": [
+    ///         { "kind": "toolInvocationSerialized", "pastTenseMessage": { "value": "Read lib.rs" } },
+    ///         { "kind": "toolInvocationSerialized" },
+    ///         { "value": "Done." }
+    ///     ] }]
+    /// }"#).unwrap();
+    ///
+    /// let tools: Vec<_> = chat.requests[0].tool_invocations().collect();
+    /// assert_eq!(tools, ["Read lib.rs"]);
+    /// ```
+    pub fn tool_invocations(&self) -> impl Iterator<Item = &str> {
+        self.response.iter().filter_map(|elem| match elem {
+            ResponseElement::ToolInvocation {
+                past_tense: Some(message),
+            } if !message.trim().is_empty() => Some(message.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Returns the path of each file the response edited, in order.
+    ///
+    /// An edit group counts when it changes its file's text or deletes the
+    /// file; a file edited by several groups is named once per group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cp2md::parser::parse_chat;
+    ///
+    /// let chat = parse_chat(r#"{
+    ///     "responderUsername": "GitHub Copilot",
+    ///     "requests": [{ "message": { "text": "Fix" }, "response": [
+    ///         { "kind": "textEditGroup", "uri": { "path": "/src/main.rs" }, "edits": [[{ "text": "fn main() {}" }]] },
+    ///         { "kind": "textEditGroup", "uri": { "path": "/src/lib.rs" }, "edits": [] }
+    ///     ] }]
+    /// }"#).unwrap();
+    ///
+    /// let paths: Vec<_> = chat.requests[0].edited_paths().collect();
+    /// assert_eq!(paths, ["/src/main.rs"]);
+    /// ```
+    pub fn edited_paths(&self) -> impl Iterator<Item = &str> {
+        self.response
+            .iter()
+            .filter_map(ResponseElement::edited_path)
+    }
+
+    /// Returns the response's text, joining its text elements.
+    ///
+    /// Exports split one stream of Markdown across elements, often mid-line,
+    /// so the elements are joined as they are, and only blank ones are left
+    /// out. Other elements, such as inline references, are not included.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cp2md::parser::parse_chat;
+    ///
+    /// let chat = parse_chat(r#"{
+    ///     "responderUsername": "GitHub Copilot",
+    ///     "requests": [{ "message": { "text": "Hi" }, "response": [
+    ///         { "value": "Hello " },
+    ///         { "kind": "toolInvocationSerialized" },
+    ///         { "value": "there!" }
+    ///     ] }]
+    /// }"#).unwrap();
+    ///
+    /// assert_eq!(chat.requests[0].response_text(), "Hello there!");
+    /// ```
+    #[must_use]
+    pub fn response_text(&self) -> String {
+        self.response
+            .iter()
+            .filter_map(|elem| match elem {
+                ResponseElement::Text(text) if !text.trim().is_empty() => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 /// A context item attached to a request.
@@ -112,6 +377,8 @@ pub enum ContextItem {
         start_line: u32,
         /// Ending line number (1-indexed).
         end_line: u32,
+        /// The selected text, when the export includes it.
+        text: Option<String>,
     },
     /// A folder reference.
     Folder {
@@ -125,10 +392,21 @@ pub enum ContextItem {
         /// Display name (e.g., "copilot-instructions.md").
         name: String,
     },
+    /// Text pasted into the chat as an attachment.
+    PastedText {
+        /// The pasted text.
+        text: String,
+    },
+    /// Terminal output attached to the chat.
+    TerminalOutput {
+        /// The attached output.
+        text: String,
+    },
 }
 
 /// A user message in the conversation.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde-serialize", derive(Serialize))]
 pub struct Message {
     /// The text content of the user's message.
     pub text: String,
@@ -137,9 +415,11 @@ pub struct Message {
 /// An element within an assistant's response.
 ///
 /// Responses are composed of multiple elements that can include plain text,
-/// file references, code edits, and tool invocations. This enum represents
+/// file references, code edits, tool invocations, and annotations. This enum represents
 /// all the different element types that can appear in a response.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Equality ignores the raw JSON kept for [`ResponseElement::Other`].
+#[derive(Debug, Clone)]
 pub enum ResponseElement {
     /// Plain text content from the assistant.
     Text(String),
@@ -164,6 +444,8 @@ pub enum ResponseElement {
         path: String,
         /// The individual edit operations (replacement text).
         edits: Vec<String>,
+        /// Whether the file was created, modified, or deleted.
+        kind: EditKind,
     },
 
     /// A tool invocation performed by the assistant.
@@ -172,111 +454,952 @@ pub enum ResponseElement {
         past_tense: Option<String>,
     },
 
+    /// A warning attached to the response, such as a vulnerability flagged
+    /// in suggested code or a note that content was filtered.
+    ///
+    /// Vulnerabilities follow the element they were attached to, and a
+    /// filter marker ends the response it was reported for.
+    Annotation {
+        /// What kind of warning this is.
+        severity: Severity,
+        /// The warning's text, as given by the export.
+        message: String,
+    },
+
     /// An unrecognized or unsupported response element.
     ///
     /// This variant handles forward compatibility with new element types
     /// that may be added to the export format in the future.
-    Other,
+    Other {
+        /// The element's original JSON, kept only when
+        /// [`ParseOptions::keep_raw`] is set.
+        raw: Option<serde_json::Value>,
+    },
 }
 
-impl<'de> Deserialize<'de> for ResponseElement {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let value = serde_json::Value::deserialize(deserializer)?;
+impl PartialEq for ResponseElement {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Text(a), Self::Text(b)) => a == b,
+            (
+                Self::InlineReference { name, path },
+                Self::InlineReference {
+                    name: other_name,
+                    path: other_path,
+                },
+            ) => name == other_name && path == other_path,
+            (Self::CodeBlockUri { path }, Self::CodeBlockUri { path: other_path }) => {
+                path == other_path
+            }
+            (
+                Self::TextEditGroup { path, edits, kind },
+                Self::TextEditGroup {
+                    path: other_path,
+                    edits: other_edits,
+                    kind: other_kind,
+                },
+            ) => path == other_path && edits == other_edits && kind == other_kind,
+            (
+                Self::ToolInvocation { past_tense },
+                Self::ToolInvocation {
+                    past_tense: other_past_tense,
+                },
+            ) => past_tense == other_past_tense,
+            (
+                Self::Annotation { severity, message },
+                Self::Annotation {
+                    severity: other_severity,
+                    message: other_message,
+                },
+            ) => severity == other_severity && message == other_message,
+            // Raw JSON is a debugging aid, not part of an element's identity
+            (Self::Other { .. }, Self::Other { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ResponseElement {}
+
+impl ResponseElement {
+    /// Returns the path of an edit group that changes its file's text or
+    /// deletes the file, or `None` for any other element.
+    #[must_use]
+    pub fn edited_path(&self) -> Option<&str> {
+        match self {
+            Self::TextEditGroup { path, edits, kind }
+                if !edits.is_empty() || *kind == EditKind::Delete =>
+            {
+                Some(path)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the `kind` of an unrecognized element, if its raw JSON was kept.
+    #[must_use]
+    pub fn unknown_kind(&self) -> Option<&str> {
+        match self {
+            Self::Other { raw: Some(raw) } => get_str(raw, &["kind"]),
+            _ => None,
+        }
+    }
+}
+
+/// Something unusual about an export that parsing worked around.
+#[derive(Debug, Clone, PartialEq, Eq, Snafu)]
+pub enum ParseWarning {
+    /// A request's response was a single object or string, as in older
+    /// exports, instead of an array.
+    #[snafu(display(
+        "request {} stores its response as a bare {shape} instead of an array",
+        index + 1
+    ))]
+    NonArrayResponse {
+        /// Zero-based index of the request.
+        index: usize,
+        /// `"object"` or `"string"`.
+        shape: &'static str,
+    },
+    /// A response element was neither an object nor a string, so it was
+    /// kept as [`ResponseElement::Other`].
+    #[snafu(display(
+        "request {} has a malformed response element at position {}",
+        request + 1,
+        element + 1
+    ))]
+    MalformedElement {
+        /// Zero-based index of the request.
+        request: usize,
+        /// Zero-based index of the element within the response.
+        element: usize,
+    },
+    /// A request's timestamp is from before 2015 or more than a day in the
+    /// future, as when it is in an unexpected unit.
+    #[snafu(display(
+        "request {} has an implausible timestamp {timestamp} (see --timestamp-unit)",
+        index + 1
+    ))]
+    ImplausibleTimestamp {
+        /// Zero-based index of the request.
+        index: usize,
+        /// The timestamp, in milliseconds after [`ParseOptions::timestamp_unit`].
+        timestamp: i64,
+    },
+    /// The input is not UTF-8, so what is not was replaced with U+FFFD.
+    #[snafu(display("invalid UTF-8 at byte {offset} was replaced with U+FFFD (see --strict)"))]
+    ReplacedInvalidUtf8 {
+        /// Byte offset of the first invalid sequence.
+        offset: usize,
+    },
+}
+
+/// Which generation of the export format a file looks like, as judged by
+/// [`detect_format_version`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatVariant {
+    /// Context under `variableData` or in `attachments`, with every
+    /// response an array.
+    Current,
+    /// Context in a top-level `variables` array, or responses stored as a
+    /// single object or string.
+    Legacy,
+    /// Features of both generations.
+    Mixed,
+    /// Nothing that tells the generations apart, as in a chat whose
+    /// requests attach no context.
+    Unknown,
+}
+
+impl std::fmt::Display for FormatVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Current => "current",
+            Self::Legacy => "legacy",
+            Self::Mixed => "mixed",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+/// The export format features found in a file, from
+/// [`detect_format_version`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct FormatHint {
+    /// Number of requests.
+    pub requests: usize,
+    /// Whether the chat names its model in `selectedModel`.
+    pub selected_model: bool,
+    /// Whether any request keeps context in `variableData.variables`.
+    pub variable_data: bool,
+    /// Whether any request keeps context in a top-level `variables` array.
+    pub top_level_variables: bool,
+    /// Whether any request has `attachments`.
+    pub attachments: bool,
+    /// Whether any request names its `agent`.
+    pub agent: bool,
+    /// Whether any response is a single object or string, not an array.
+    pub single_responses: bool,
+    /// Whether any response element is text without a `kind`.
+    pub untagged_elements: bool,
+    /// The `kind` of each tagged response element, sorted and without
+    /// duplicates.
+    pub element_kinds: Vec<String>,
+}
+
+impl FormatHint {
+    /// Judges which generation of the format the features belong to.
+    #[must_use]
+    pub const fn variant(&self) -> FormatVariant {
+        let current = self.variable_data || self.attachments;
+        let legacy = self.top_level_variables || self.single_responses;
+        match (current, legacy) {
+            (true, true) => FormatVariant::Mixed,
+            (true, false) => FormatVariant::Current,
+            (false, true) => FormatVariant::Legacy,
+            (false, false) => FormatVariant::Unknown,
+        }
+    }
+}
+
+impl std::fmt::Display for FormatHint {
+    /// Writes the variant and then each feature found, such as
+    /// `current: 2 requests, variableData, agent, kinds: inlineReference`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let plural = if self.requests == 1 { "" } else { "s" };
+        write!(f, "{}: {} request{plural}", self.variant(), self.requests)?;
+        let features = [
+            (self.selected_model, "selectedModel"),
+            (self.variable_data, "variableData"),
+            (self.top_level_variables, "top-level variables"),
+            (self.attachments, "attachments"),
+            (self.agent, "agent"),
+            (self.single_responses, "single-element responses"),
+            (self.untagged_elements, "untagged text"),
+        ];
+        for (_, name) in features.iter().filter(|(found, _)| *found) {
+            write!(f, ", {name}")?;
+        }
+        if !self.element_kinds.is_empty() {
+            write!(f, ", kinds: {}", self.element_kinds.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A JSON Schema describing the parts of a Copilot chat export that cp2md
+/// reads.
+///
+/// Properties not listed are allowed and ignored. The parser is more
+/// forgiving than the schema: it also accepts, for example, a response
+/// element it cannot read, and treats it as unknown.
+pub const EXPORT_SCHEMA: &str = r##"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "GitHub Copilot chat export",
+  "description": "The parts of a VS Code Copilot chat export that cp2md reads. Other properties are ignored.",
+  "type": "object",
+  "required": ["responderUsername", "requests"],
+  "properties": {
+    "responderUsername": { "type": "string" },
+    "requesterUsername": { "type": ["string", "null"] },
+    "customTitle": { "type": ["string", "null"] },
+    "selectedModel": {
+      "description": "The session's model, as an ID or an object naming one.",
+      "anyOf": [
+        { "type": "string" },
+        {
+          "type": "object",
+          "properties": {
+            "identifier": { "type": "string" },
+            "id": { "type": "string" }
+          }
+        }
+      ]
+    },
+    "requests": {
+      "type": "array",
+      "items": { "$ref": "#/$defs/request" }
+    }
+  },
+  "$defs": {
+    "request": {
+      "type": "object",
+      "properties": {
+        "timestamp": { "description": "Milliseconds since the Unix epoch.", "type": "integer" },
+        "modelId": { "type": "string" },
+        "agent": {
+          "type": "object",
+          "properties": { "name": { "type": "string" } }
+        },
+        "message": {
+          "type": "object",
+          "properties": { "text": { "type": "string" } }
+        },
+        "response": {
+          "description": "The response's elements. Older exports store a single element on its own.",
+          "anyOf": [
+            { "type": "array", "items": { "$ref": "#/$defs/responseElement" } },
+            { "$ref": "#/$defs/responseElement" }
+          ]
+        },
+        "variableData": {
+          "type": "object",
+          "properties": {
+            "variables": { "type": "array", "items": { "$ref": "#/$defs/variable" } }
+          }
+        },
+        "variables": {
+          "description": "Where older exports keep variableData.variables. Ignored when variableData.variables is present.",
+          "type": "array",
+          "items": { "$ref": "#/$defs/variable" }
+        },
+        "attachments": {
+          "type": "array",
+          "items": {
+            "type": "object",
+            "properties": { "type": { "type": "string" } }
+          }
+        },
+        "result": {
+          "type": "object",
+          "properties": {
+            "errorDetails": {
+              "type": "object",
+              "properties": {
+                "message": { "type": "string" },
+                "responseIsFiltered": {
+                  "description": "Marks a response a content filter withheld part of.",
+                  "type": "boolean"
+                }
+              }
+            },
+            "metadata": {
+              "type": "object",
+              "properties": {
+                "usage": {
+                  "description": "Tokens the request used. Keys may also be prompt_tokens and completion_tokens.",
+                  "type": "object",
+                  "properties": {
+                    "promptTokens": { "type": "integer", "minimum": 0 },
+                    "completionTokens": { "type": "integer", "minimum": 0 }
+                  }
+                }
+              }
+            }
+          }
+        },
+        "isHidden": {
+          "description": "Marks an internal request, such as an agent's bookkeeping turn.",
+          "type": "boolean"
+        },
+        "shouldBeBlocked": {
+          "description": "Treated the same as isHidden.",
+          "type": "boolean"
+        }
+      }
+    },
+    "responseElement": {
+      "description": "Markdown text, or an object whose kind says what it holds. Objects without a kind are text, held in value or value.value.",
+      "anyOf": [
+        { "type": "string" },
+        {
+          "type": "object",
+          "properties": {
+            "kind": { "type": "string" },
+            "vulnerabilities": {
+              "description": "Warnings about the element's code, each with a title and a description.",
+              "type": "array",
+              "items": { "$ref": "#/$defs/vulnerability" }
+            },
+            "codeVulnAnnotations": {
+              "description": "Where some exports keep vulnerabilities.",
+              "type": "array",
+              "items": { "$ref": "#/$defs/vulnerability" }
+            }
+          }
+        }
+      ]
+    },
+    "vulnerability": {
+      "type": "object",
+      "properties": {
+        "title": { "type": "string" },
+        "description": { "type": "string" },
+        "message": { "type": "string" }
+      }
+    },
+    "variable": {
+      "type": "object",
+      "properties": {
+        "id": { "type": "string" },
+        "name": { "type": "string" },
+        "kind": { "type": "string" }
+      }
+    }
+  }
+}
+"##;
+
+/// Options controlling how exports are parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Whether to keep the original JSON of unrecognized response elements.
+    ///
+    /// Off by default, since large exports would otherwise carry a copy of
+    /// every element cp2md does not understand.
+    pub keep_raw: bool,
+
+    /// Whether to accept a comma after the last item of an array or object,
+    /// as exports edited by hand can have.
+    ///
+    /// Off by default, since such input is not JSON.
+    pub lenient: bool,
+
+    /// The unit request timestamps are written in.
+    pub timestamp_unit: TimestampUnit,
+
+    /// Whether [`decode_export`] refuses input that is not UTF-8, rather
+    /// than replacing each invalid sequence with U+FFFD.
+    ///
+    /// Off by default, since exports can hold pasted terminal output in
+    /// another encoding.
+    pub strict_utf8: bool,
+}
+
+/// The unit of the request timestamps in an export.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampUnit {
+    /// Milliseconds, except that positive values below 10^11, which as
+    /// milliseconds would fall in 1973, are read as seconds.
+    #[default]
+    Auto,
+    /// Milliseconds since the Unix epoch, as VS Code writes them.
+    Millis,
+    /// Seconds since the Unix epoch.
+    Seconds,
+}
+
+impl TimestampUnit {
+    /// Returns `timestamp`, written in this unit, in milliseconds.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cp2md::parser::TimestampUnit;
+    ///
+    /// assert_eq!(TimestampUnit::Auto.to_millis(1_733_356_800), 1_733_356_800_000);
+    /// assert_eq!(TimestampUnit::Auto.to_millis(1_733_356_800_000), 1_733_356_800_000);
+    /// assert_eq!(TimestampUnit::Millis.to_millis(1_733_356_800), 1_733_356_800);
+    /// ```
+    #[must_use]
+    pub const fn to_millis(self, timestamp: i64) -> i64 {
+        match self {
+            Self::Auto if timestamp > 0 && timestamp < SECONDS_BELOW => {
+                timestamp.saturating_mul(1000)
+            }
+            Self::Auto | Self::Millis => timestamp,
+            Self::Seconds => timestamp.saturating_mul(1000),
+        }
+    }
+}
+
+/// Timestamps below this are read as seconds under [`TimestampUnit::Auto`].
+const SECONDS_BELOW: i64 = 100_000_000_000;
+
+/// The start of 2015 in milliseconds, before which no chat was exported.
+const EARLIEST_TIMESTAMP: i64 = 1_420_070_400_000;
+
+/// How far in the future a timestamp may be, in milliseconds, to allow for
+/// clocks that are off.
+const FUTURE_SLACK: i64 = 24 * 60 * 60 * 1000;
+
+/// Returns whether `timestamp` could be when a request was made, given the
+/// current time `now`, both in milliseconds.
+const fn is_plausible_timestamp(timestamp: i64, now: i64) -> bool {
+    timestamp >= EARLIEST_TIMESTAMP && timestamp <= now.saturating_add(FUTURE_SLACK)
+}
+
+/// What a [`ResponseElement::Annotation`] warns about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub enum Severity {
+    /// Suggested code was flagged, for example as a likely vulnerability.
+    #[default]
+    Warning,
+    /// Part of the response was withheld by a content filter.
+    Filtered,
+}
+
+/// The kind of change a [`ResponseElement::TextEditGroup`] made to its file.
+///
+/// Exports only sometimes record whether a file was created or deleted, so
+/// [`EditKind::Modify`] is used whenever no such signal is present.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde-serialize",
+    derive(Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub enum EditKind {
+    /// The file was newly created.
+    Create,
+    /// An existing file was modified.
+    #[default]
+    Modify,
+    /// The file was deleted.
+    Delete,
+}
 
-        if let Some(kind) = get_str(&value, &["kind"]) {
-            return Ok(match kind {
+impl ResponseElement {
+    /// Reads an element from its JSON, keeping the JSON of an unrecognized
+    /// element only when `keep_raw` is set.
+    fn from_value(value: &serde_json::Value, keep_raw: bool) -> Self {
+        // Some minimal exports store response text as bare strings
+        if let Some(text) = value.as_str() {
+            return Self::Text(normalize_newlines(text));
+        }
+
+        if let Some(kind) = get_str(value, &["kind"]) {
+            return match kind {
                 "inlineReference" => Self::InlineReference {
-                    name: get_string(&value, &["name"])
-                        .or_else(|| get_string(&value, &["inlineReference", "name"])),
-                    path: get_str(&value, &["inlineReference", "path"])
+                    name: get_string(value, &["name"])
+                        .or_else(|| get_string(value, &["inlineReference", "name"])),
+                    path: get_str(value, &["inlineReference", "path"])
                         .unwrap_or_default()
                         .to_owned(),
                 },
                 "codeblockUri" => Self::CodeBlockUri {
-                    path: get_str(&value, &["uri", "path"])
+                    path: get_str(value, &["uri", "path"])
                         .unwrap_or_default()
                         .to_owned(),
                 },
                 "textEditGroup" => Self::TextEditGroup {
-                    path: get_str(&value, &["uri", "path"])
+                    path: get_str(value, &["uri", "path"])
                         .unwrap_or_default()
                         .to_owned(),
-                    edits: extract_edits(&value),
+                    edits: extract_edits(value),
+                    kind: extract_edit_kind(value),
                 },
                 "toolInvocationSerialized" => Self::ToolInvocation {
-                    past_tense: get_string(&value, &["pastTenseMessage", "value"]),
+                    past_tense: get_str(value, &["pastTenseMessage", "value"])
+                        .map(normalize_newlines),
                 },
-                _ => Self::Other,
-            });
+                // The vulnerabilities themselves are read by extract_response
+                "markdownVuln" => Self::Text(normalize_newlines(
+                    get_str(value, &["content", "value"]).unwrap_or_default(),
+                )),
+                _ => Self::Other {
+                    raw: keep_raw.then(|| value.clone()),
+                },
+            };
         }
 
-        // No "kind" field: check if it's a text response
-        if let Some(text) = get_str(&value, &["value"]) {
-            return Ok(Self::Text(text.to_owned()));
+        // No "kind" field: check if it's a text response, whose value some
+        // exports wrap in an object of its own
+        if let Some(text) =
+            get_str(value, &["value"]).or_else(|| get_str(value, &["value", "value"]))
+        {
+            return Self::Text(normalize_newlines(text));
         }
 
-        Ok(Self::Other)
+        Self::Other {
+            raw: keep_raw.then(|| value.clone()),
+        }
     }
 }
 
-impl<'de> Deserialize<'de> for Request {
+impl<'de> Deserialize<'de> for ResponseElement {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         let value = serde_json::Value::deserialize(deserializer)?;
+        Ok(Self::from_value(&value, false))
+    }
+}
 
+impl Request {
+    /// Reads a request from its JSON, keeping the JSON of unrecognized
+    /// response elements only when `keep_raw` is set.
+    fn from_value(value: &serde_json::Value, keep_raw: bool) -> Self {
         let timestamp = value
             .get("timestamp")
             .and_then(serde_json::Value::as_i64)
             .unwrap_or(0);
 
-        let model_id = get_string(&value, &["modelId"]);
-        let agent_name = get_string(&value, &["agent", "name"]);
+        let model_id = get_string(value, &["modelId"]).filter(|m| !m.is_empty());
+        let agent_name = get_string(value, &["agent", "name"]);
 
         let message = value
             .get("message")
-            .and_then(|m| serde_json::from_value(m.clone()).ok())
-            .unwrap_or(Message {
-                text: String::new(),
-            });
+            .and_then(|m| serde_json::from_value::<Message>(m.clone()).ok())
+            .map_or_else(
+                || Message {
+                    text: String::new(),
+                },
+                |m| Message {
+                    text: normalize_newlines(&m.text),
+                },
+            );
 
-        let response = value
+        let mut response = value
             .get("response")
-            .and_then(|r| serde_json::from_value(r.clone()).ok())
-            .unwrap_or_default();
+            .map_or_else(Vec::new, |response| extract_response(response, keep_raw));
+        response.extend(filter_annotation(value));
+
+        let context = extract_context(value);
+
+        let hidden = ["isHidden", "shouldBeBlocked"]
+            .iter()
+            .any(|key| value.get(key).and_then(serde_json::Value::as_bool) == Some(true));
 
-        let context = extract_context(&value);
+        let usage = extract_usage(value);
 
-        Ok(Self {
+        Self {
             timestamp,
             model_id,
             agent_name,
             context,
             message,
             response,
-        })
+            hidden,
+            usage,
+        }
     }
 }
 
-/// Extracts context items from the variableData.variables array.
-fn extract_context(value: &serde_json::Value) -> Vec<ContextItem> {
-    let variables = match value.get("variableData").and_then(|v| v.get("variables")) {
-        Some(v) => v.as_array(),
-        None => return Vec::new(),
+impl<'de> Deserialize<'de> for Request {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Ok(Self::from_value(&value, false))
+    }
+}
+
+/// Writes an element in the export format, so it reads back as the same
+/// element.
+#[cfg(feature = "serde-serialize")]
+impl Serialize for ResponseElement {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde_json::json;
+
+        let value = match self {
+            Self::Text(text) => json!({ "value": text }),
+            Self::InlineReference { name, path } => {
+                let mut value = json!({
+                    "kind": "inlineReference",
+                    "inlineReference": { "path": path },
+                });
+                if let Some(name) = name {
+                    value["name"] = json!(name);
+                }
+                value
+            }
+            Self::CodeBlockUri { path } => {
+                json!({ "kind": "codeblockUri", "uri": { "path": path } })
+            }
+            Self::TextEditGroup { path, edits, kind } => {
+                let mut value = json!({
+                    "kind": "textEditGroup",
+                    "uri": { "path": path },
+                    "edits": edits.iter().map(|text| [json!({ "text": text })]).collect::<Vec<_>>(),
+                });
+                match kind {
+                    EditKind::Create => value["state"] = json!("created"),
+                    EditKind::Delete => value["state"] = json!("deleted"),
+                    EditKind::Modify => {}
+                }
+                value
+            }
+            Self::ToolInvocation { past_tense } => {
+                let mut value = json!({ "kind": "toolInvocationSerialized" });
+                if let Some(past_tense) = past_tense {
+                    value["pastTenseMessage"] = json!({ "value": past_tense });
+                }
+                value
+            }
+            // A filter marker belongs to its request, which writes it as
+            // such; on its own it can only be written as a vulnerability
+            Self::Annotation { message, .. } => json!({
+                "kind": "markdownVuln",
+                "content": { "value": "" },
+                "vulnerabilities": [{ "title": message }],
+            }),
+            // Without its raw JSON an unknown element can only be written
+            // as something that reads back as unknown
+            Self::Other { raw } => raw.clone().unwrap_or_else(|| json!({})),
+        };
+        value.serialize(serializer)
+    }
+}
+
+/// Writes a context item as the variable or attachment it was read from.
+#[cfg(feature = "serde-serialize")]
+impl Serialize for ContextItem {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde_json::json;
+
+        let value = match self {
+            Self::File { name, path } => json!({
+                "kind": "file",
+                "name": name,
+                "value": { "path": path },
+            }),
+            Self::Selection {
+                name,
+                path,
+                start_line,
+                end_line,
+                text,
+            } => {
+                let mut value = json!({
+                    "kind": "file",
+                    "name": name,
+                    "isSelection": true,
+                    "value": {
+                        "path": path,
+                        "range": { "startLineNumber": start_line, "endLineNumber": end_line },
+                    },
+                });
+                if let Some(text) = text {
+                    value["value"]["text"] = json!(text);
+                }
+                value
+            }
+            Self::Folder { name, path } => json!({
+                "kind": "folder",
+                "name": name,
+                "value": { "path": path },
+            }),
+            Self::Instructions { name } => json!({ "kind": "promptFile", "name": name }),
+            Self::PastedText { text } => json!({ "type": "pastedText", "value": text }),
+            Self::TerminalOutput { text } => json!({ "type": "terminalOutput", "value": text }),
+        };
+        value.serialize(serializer)
+    }
+}
+
+/// Writes a request in the export format, with its context split back into
+/// variables and attachments.
+#[cfg(feature = "serde-serialize")]
+impl Serialize for Request {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+
+        let (attachments, variables): (Vec<_>, Vec<_>) = self.context.iter().partition(|item| {
+            matches!(
+                item,
+                ContextItem::PastedText { .. } | ContextItem::TerminalOutput { .. }
+            )
+        });
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("timestamp", &self.timestamp)?;
+        if let Some(model_id) = &self.model_id {
+            map.serialize_entry("modelId", model_id)?;
+        }
+        if let Some(name) = &self.agent_name {
+            map.serialize_entry("agent", &serde_json::json!({ "name": name }))?;
+        }
+        map.serialize_entry("message", &self.message)?;
+        let (filtered, response): (Vec<_>, Vec<_>) = self.response.iter().partition(|elem| {
+            matches!(
+                elem,
+                ResponseElement::Annotation {
+                    severity: Severity::Filtered,
+                    ..
+                }
+            )
+        });
+        map.serialize_entry("response", &response)?;
+        let mut result = serde_json::Map::new();
+        if let Some(ResponseElement::Annotation { message, .. }) = filtered.first() {
+            result.insert(
+                "errorDetails".to_owned(),
+                serde_json::json!({ "message": message, "responseIsFiltered": true }),
+            );
+        }
+        if let Some(usage) = self.usage {
+            result.insert(
+                "metadata".to_owned(),
+                serde_json::json!({ "usage": {
+                    "promptTokens": usage.prompt,
+                    "completionTokens": usage.completion,
+                } }),
+            );
+        }
+        if !result.is_empty() {
+            map.serialize_entry("result", &result)?;
+        }
+        if !variables.is_empty() {
+            map.serialize_entry(
+                "variableData",
+                &serde_json::json!({ "variables": variables }),
+            )?;
+        }
+        if !attachments.is_empty() {
+            map.serialize_entry("attachments", &attachments)?;
+        }
+        if self.hidden {
+            map.serialize_entry("isHidden", &true)?;
+        }
+        map.end()
+    }
+}
+
+/// Reads a session-level model, given as an ID or an object naming one.
+fn deserialize_model<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    let model = value
+        .as_str()
+        .or_else(|| get_str(&value, &["identifier"]))
+        .or_else(|| get_str(&value, &["id"]));
+    Ok(model.filter(|m| !m.is_empty()).map(str::to_owned))
+}
+
+/// Extracts response elements from an array, a single object, or a string.
+///
+/// Older exports store a one-element response as the bare element. Each
+/// element is read on its own, so one that cannot be read becomes
+/// [`ResponseElement::Other`] rather than costing the whole response; its
+/// JSON is kept only when `keep_raw` is set.
+fn extract_response(response: &serde_json::Value, keep_raw: bool) -> Vec<ResponseElement> {
+    let elements = match response {
+        serde_json::Value::Array(elements) => elements.as_slice(),
+        serde_json::Value::Object(_) | serde_json::Value::String(_) => {
+            std::slice::from_ref(response)
+        }
+        _ => &[],
     };
+    let mut out = Vec::with_capacity(elements.len());
+    for value in elements {
+        let elem = ResponseElement::from_value(value, keep_raw);
+        // A markdownVuln written only to carry its vulnerabilities has no text
+        let carrier_only = get_str(value, &["kind"]) == Some("markdownVuln")
+            && matches!(&elem, ResponseElement::Text(text) if text.is_empty());
+        if !carrier_only {
+            out.push(elem);
+        }
+        out.extend(vulnerability_annotations(value));
+    }
+    out
+}
 
-    let Some(variables) = variables else {
-        return Vec::new();
+/// Reads the vulnerabilities a response element was flagged with, as
+/// annotations.
+///
+/// Chat exports list them in `vulnerabilities`, each with a `title` and a
+/// `description`; some exports use `codeVulnAnnotations` instead, whose
+/// entries may give a `message` in their place.
+fn vulnerability_annotations(value: &serde_json::Value) -> Vec<ResponseElement> {
+    ["vulnerabilities", "codeVulnAnnotations"]
+        .iter()
+        .filter_map(|key| value.get(key).and_then(serde_json::Value::as_array))
+        .flatten()
+        .filter_map(|vuln| {
+            let title = get_str(vuln, &["title"])
+                .or_else(|| get_str(vuln, &["message"]))
+                .map(str::trim)
+                .filter(|t| !t.is_empty());
+            let description = get_str(vuln, &["description"])
+                .map(str::trim)
+                .filter(|d| !d.is_empty() && Some(*d) != title);
+            let message = match (title, description) {
+                (Some(title), Some(description)) => format!("{title}: {description}"),
+                (Some(text), None) | (None, Some(text)) => text.to_owned(),
+                (None, None) => return None,
+            };
+            Some(ResponseElement::Annotation {
+                severity: Severity::Warning,
+                message: normalize_newlines(&message),
+            })
+        })
+        .collect()
+}
+
+/// Reads the token counts a request's result carries, named in camel case
+/// or snake case.
+///
+/// Either count may be missing, and is then read as 0, but not both.
+fn extract_usage(value: &serde_json::Value) -> Option<Usage> {
+    let usage = value.get("result")?.get("metadata")?.get("usage")?;
+    let count = |keys: [&str; 2]| {
+        keys.iter()
+            .find_map(|key| usage.get(key).and_then(serde_json::Value::as_u64))
+            .map(|n| u32::try_from(n).unwrap_or(u32::MAX))
     };
+    let prompt = count(["promptTokens", "prompt_tokens"]);
+    let completion = count(["completionTokens", "completion_tokens"]);
+    if prompt.is_none() && completion.is_none() {
+        return None;
+    }
+    Some(Usage {
+        prompt: prompt.unwrap_or_default(),
+        completion: completion.unwrap_or_default(),
+    })
+}
+
+/// Reads the marker a request's result carries when a content filter
+/// withheld part of its response.
+fn filter_annotation(value: &serde_json::Value) -> Option<ResponseElement> {
+    let details = value.get("result")?.get("errorDetails")?;
+    if details
+        .get("responseIsFiltered")
+        .and_then(serde_json::Value::as_bool)
+        != Some(true)
+    {
+        return None;
+    }
+    let message = get_str(details, &["message"])
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .unwrap_or("The response was filtered.");
+    Some(ResponseElement::Annotation {
+        severity: Severity::Filtered,
+        message: normalize_newlines(message),
+    })
+}
+
+/// Extracts context items from the variableData.variables array and the
+/// attachments array.
+///
+/// Older exports keep the variables in a top-level `variables` array
+/// instead, which is read when `variableData.variables` is missing.
+fn extract_context(value: &serde_json::Value) -> Vec<ContextItem> {
+    let variables = value
+        .get("variableData")
+        .and_then(|v| v.get("variables"))
+        .or_else(|| value.get("variables"))
+        .and_then(serde_json::Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
 
     let mut items = Vec::new();
 
     for var in variables {
-        let kind = get_str(var, &["kind"]).unwrap_or("");
+        // Older variables may have no kind, only a file URI as their value
+        let uri_path = get_str(var, &["value"]).and_then(path_from_file_uri);
+        let kind = get_str(var, &["kind"])
+            .or_else(|| uri_path.as_ref().map(|_| "file"))
+            .unwrap_or("");
         let name = get_string(var, &["name"]).unwrap_or_default();
         let id = get_string(var, &["id"]).unwrap_or_default();
 
@@ -285,9 +1408,9 @@ fn extract_context(value: &serde_json::Value) -> Vec<ContextItem> {
                 // Get path from value.uri.path or value.path
                 let path = get_string(var, &["value", "uri", "path"])
                     .or_else(|| get_string(var, &["value", "path"]))
+                    .or(uri_path)
                     .unwrap_or_default();
 
-                // Check if this is a selection (has range with line numbers)
                 if let Some(range) = var.get("value").and_then(|v| v.get("range")) {
                     #[allow(clippy::cast_possible_truncation)]
                     let start_line = range
@@ -301,13 +1424,13 @@ fn extract_context(value: &serde_json::Value) -> Vec<ContextItem> {
                         .unwrap_or_else(|| u64::from(start_line))
                         as u32;
 
-                    // Only treat as selection if it's actually a selection (not whole file)
-                    if id.contains("selection") || start_line != end_line || start_line > 1 {
+                    if is_selection(var, &id, start_line, end_line) {
                         items.push(ContextItem::Selection {
                             name: clean_context_name(&name),
                             path,
                             start_line,
                             end_line,
+                            text: get_str(var, &["value", "text"]).map(normalize_newlines),
                         });
                         continue;
                     }
@@ -324,7 +1447,9 @@ fn extract_context(value: &serde_json::Value) -> Vec<ContextItem> {
                 });
             }
             "folder" => {
-                let path = get_string(var, &["value", "path"]).unwrap_or_default();
+                let path = get_string(var, &["value", "path"])
+                    .or(uri_path)
+                    .unwrap_or_default();
                 items.push(ContextItem::Folder {
                     name: clean_context_name(&name),
                     path,
@@ -335,12 +1460,97 @@ fn extract_context(value: &serde_json::Value) -> Vec<ContextItem> {
         }
     }
 
+    items.extend(extract_attachments(value));
     items
 }
 
-/// Cleans up context item names by removing prefixes like "file:" or "prompt:".
-fn clean_context_name(name: &str) -> String {
-    name.strip_prefix("file:")
+/// Extracts pasted text and terminal output from the attachments array.
+///
+/// Each attachment is `{type, value}`, where the value is the text itself
+/// or an object holding it. Images and unknown types are skipped.
+fn extract_attachments(value: &serde_json::Value) -> Vec<ContextItem> {
+    let Some(attachments) = value.get("attachments").and_then(|a| a.as_array()) else {
+        return Vec::new();
+    };
+
+    attachments
+        .iter()
+        .filter_map(|attachment| {
+            let content = attachment.get("value")?;
+            let text = content
+                .as_str()
+                .or_else(|| get_str(content, &["text"]))
+                .or_else(|| get_str(content, &["output"]))?;
+            let text = normalize_newlines(text);
+            match get_str(attachment, &["type"])? {
+                "pastedText" | "paste" | "text" => Some(ContextItem::PastedText { text }),
+                "terminalOutput" | "terminal" => Some(ContextItem::TerminalOutput { text }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Decides whether a file variable with a line range is a selection.
+///
+/// VS Code also records a range for whole-file attachments (often the
+/// cursor line), so a range alone is not enough. In order of precedence:
+///
+/// 1. An explicit `isSelection` flag, on the variable or its value, decides.
+/// 2. An id containing "selection" (e.g. `vscode.implicit.selection`) is a
+///    selection.
+/// 3. When the file's `lineCount` is known, a range short of the whole file
+///    is a selection.
+/// 4. Otherwise the variable is treated as a whole file.
+fn is_selection(var: &serde_json::Value, id: &str, start_line: u32, end_line: u32) -> bool {
+    let flag = var
+        .get("isSelection")
+        .or_else(|| var.get("value")?.get("isSelection"))
+        .and_then(serde_json::Value::as_bool);
+    if let Some(flag) = flag {
+        return flag;
+    }
+    if id.contains("selection") {
+        return true;
+    }
+
+    var.get("value")
+        .and_then(|v| v.get("lineCount"))
+        .and_then(serde_json::Value::as_u64)
+        .is_some_and(|total| start_line > 1 || u64::from(end_line) < total)
+}
+
+/// Returns the percent-decoded path of a `file://` URI.
+///
+/// Any host, as in `file://server/share/a.txt`, is dropped. Returns `None`
+/// for other schemes.
+fn path_from_file_uri(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = &rest[rest.find('/')?..];
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+/// Cleans up context item names by removing prefixes like "file:" or "prompt:".
+fn clean_context_name(name: &str) -> String {
+    name.strip_prefix("file:")
         .or_else(|| name.strip_prefix("prompt:"))
         .unwrap_or(name)
         .to_owned()
@@ -377,10 +1587,48 @@ fn extract_edits(value: &serde_json::Value) -> Vec<String> {
         .filter_map(|group| group.as_array())
         .flatten()
         .filter_map(|edit| edit.get("text")?.as_str())
-        .map(str::to_owned)
+        .map(normalize_newlines)
         .collect()
 }
 
+/// Converts Windows (`\r\n`) and old Mac (`\r`) line endings to `\n`.
+///
+/// Exports created on Windows carry `\r\n` inside message text; normalizing
+/// here keeps the line-based rendering helpers consistent.
+fn normalize_newlines(s: &str) -> String {
+    if s.contains('\r') {
+        s.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Determines whether a text edit group created, modified, or deleted its file.
+///
+/// Recognizes explicit `isNew`/`isDeleted` flags, a `state` string, and an
+/// empty `originalUri` (no prior file). Falls back to [`EditKind::Modify`].
+fn extract_edit_kind(value: &serde_json::Value) -> EditKind {
+    let flag = |key: &str| value.get(key).and_then(serde_json::Value::as_bool) == Some(true);
+
+    match get_str(value, &["state"]) {
+        Some("deleted" | "delete") => return EditKind::Delete,
+        Some("created" | "create" | "new") => return EditKind::Create,
+        _ => {}
+    }
+
+    let original_missing = value
+        .get("originalUri")
+        .is_some_and(|uri| uri.is_null() || get_str(uri, &["path"]).is_some_and(str::is_empty));
+
+    if flag("isDeleted") || flag("isDelete") {
+        EditKind::Delete
+    } else if flag("isNew") || flag("isCreate") || original_missing {
+        EditKind::Create
+    } else {
+        EditKind::Modify
+    }
+}
+
 /// Parses a JSON string into a [`ChatExport`] structure.
 ///
 /// This is the main entry point for parsing Copilot chat exports.
@@ -408,7 +1656,422 @@ fn extract_edits(value: &serde_json::Value) -> Vec<String> {
 /// assert_eq!(chat.responder_username, "GitHub Copilot");
 /// ```
 pub fn parse_chat(json_str: &str) -> Result<ChatExport, ParseError> {
-    serde_json::from_str(json_str).context(JsonSnafu)
+    parse_chat_with_options(json_str, &ParseOptions::default())
+}
+
+/// Parses a JSON string into a [`ChatExport`] using the given options.
+///
+/// # Errors
+///
+/// Returns an error if the JSON is malformed or doesn't match the expected
+/// Copilot chat export schema.
+///
+/// # Example
+///
+/// ```
+/// use cp2md::parser::{parse_chat_with_options, ParseOptions, ResponseElement};
+///
+/// let json = r#"{
+///     "responderUsername": "GitHub Copilot",
+///     "requests": [{ "response": [{ "kind": "mcpServersStarting" }] }]
+/// }"#;
+///
+/// let opts = ParseOptions { keep_raw: true, ..ParseOptions::default() };
+/// let chat = parse_chat_with_options(json, &opts).unwrap();
+/// assert_eq!(chat.requests[0].response[0].unknown_kind(), Some("mcpServersStarting"));
+/// ```
+pub fn parse_chat_with_options(
+    json_str: &str,
+    opts: &ParseOptions,
+) -> Result<ChatExport, ParseError> {
+    if opts.keep_raw {
+        // Only the request-by-request path can keep unrecognized elements
+        return parse_chat_with_warnings(json_str, opts).map(|(chat, _)| chat);
+    }
+    let json_str = source_for(json_str, opts.lenient);
+    let chat: ChatExport =
+        serde_json::from_str(&json_str).map_err(|e| ParseError::from_json(&json_str, e))?;
+    Ok(finish_chat(chat, *opts))
+}
+
+/// Parses a JSON string into a [`ChatExport`], also reporting anything
+/// unusual about its shape.
+///
+/// # Errors
+///
+/// Returns an error if the JSON is malformed or doesn't match the expected
+/// Copilot chat export schema.
+///
+/// # Example
+///
+/// ```
+/// use cp2md::parser::{parse_chat_with_warnings, ParseOptions, ParseWarning};
+///
+/// let json = r#"{
+///     "responderUsername": "GitHub Copilot",
+///     "requests": [{ "response": { "value": "Hi" } }]
+/// }"#;
+///
+/// let (chat, warnings) = parse_chat_with_warnings(json, &ParseOptions::default()).unwrap();
+/// assert_eq!(chat.requests[0].response.len(), 1);
+/// assert_eq!(warnings, [ParseWarning::NonArrayResponse { index: 0, shape: "object" }]);
+/// ```
+pub fn parse_chat_with_warnings(
+    json_str: &str,
+    opts: &ParseOptions,
+) -> Result<(ChatExport, Vec<ParseWarning>), ParseError> {
+    /// A chat whose requests are kept as JSON until they are checked.
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RawChat {
+        #[serde(default = "default_responder")]
+        responder_username: String,
+        #[serde(default)]
+        requester_username: Option<String>,
+        #[serde(default)]
+        custom_title: Option<String>,
+        #[serde(
+            default,
+            rename = "selectedModel",
+            deserialize_with = "deserialize_model"
+        )]
+        default_model: Option<String>,
+        #[serde(alias = "exchanges", alias = "turns")]
+        requests: Vec<serde_json::Value>,
+    }
+
+    let json_str = source_for(json_str, opts.lenient);
+    let raw: RawChat =
+        serde_json::from_str(&json_str).map_err(|e| ParseError::from_json(&json_str, e))?;
+    let mut warnings = Vec::new();
+    let mut requests = Vec::with_capacity(raw.requests.len());
+    for (index, value) in raw.requests.into_iter().enumerate() {
+        let shape = match value.get("response") {
+            Some(serde_json::Value::Object(_)) => Some("object"),
+            Some(serde_json::Value::String(_)) => Some("string"),
+            _ => None,
+        };
+        if let Some(shape) = shape {
+            warnings.push(ParseWarning::NonArrayResponse { index, shape });
+        }
+        if let Some(elements) = value.get("response").and_then(|r| r.as_array()) {
+            // Elements are objects or, in minimal exports, strings
+            let malformed = elements
+                .iter()
+                .enumerate()
+                .filter(|(_, elem)| !elem.is_object() && !elem.is_string())
+                .map(|(element, _)| ParseWarning::MalformedElement {
+                    request: index,
+                    element,
+                });
+            warnings.extend(malformed);
+        }
+        requests.push(Request::from_value(&value, opts.keep_raw));
+    }
+
+    let chat = ChatExport {
+        responder_username: raw.responder_username,
+        requester_username: raw.requester_username,
+        custom_title: raw.custom_title,
+        default_model: raw.default_model,
+        requests,
+    };
+    let chat = finish_chat(chat, *opts);
+
+    // Requests without a timestamp have 0, which is not worth a warning
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since| {
+            i64::try_from(since.as_millis()).unwrap_or(i64::MAX)
+        });
+    let implausible = chat
+        .requests
+        .iter()
+        .enumerate()
+        .filter(|(_, req)| req.timestamp != 0 && !is_plausible_timestamp(req.timestamp, now))
+        .map(|(index, req)| ParseWarning::ImplausibleTimestamp {
+            index,
+            timestamp: req.timestamp,
+        });
+    warnings.extend(implausible);
+    Ok((chat, warnings))
+}
+
+/// Decodes an export read as bytes, replacing each invalid UTF-8 sequence
+/// with U+FFFD unless [`ParseOptions::strict_utf8`] is set.
+///
+/// Returns the text, with a warning giving where the first invalid
+/// sequence was when any were replaced.
+///
+/// # Errors
+///
+/// Returns [`ParseError::InvalidUtf8`] for input that is not UTF-8 when
+/// `strict_utf8` is set.
+///
+/// # Example
+///
+/// ```
+/// use cp2md::parser::{decode_export, ParseOptions, ParseWarning};
+///
+/// let (text, warning) = decode_export(b"caf\xe9", &ParseOptions::default()).unwrap();
+/// assert_eq!(text, "caf\u{fffd}");
+/// assert_eq!(warning, Some(ParseWarning::ReplacedInvalidUtf8 { offset: 3 }));
+/// ```
+pub fn decode_export<'a>(
+    bytes: &'a [u8],
+    opts: &ParseOptions,
+) -> Result<(Cow<'a, str>, Option<ParseWarning>), ParseError> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok((Cow::Borrowed(text), None)),
+        Err(source) if opts.strict_utf8 => Err(ParseError::InvalidUtf8 {
+            offset: source.valid_up_to(),
+            source,
+        }),
+        Err(source) => Ok((
+            String::from_utf8_lossy(bytes),
+            Some(ParseWarning::ReplacedInvalidUtf8 {
+                offset: source.valid_up_to(),
+            }),
+        )),
+    }
+}
+
+/// Parses an export read as bytes into a [`ChatExport`], also reporting
+/// anything unusual about its shape.
+///
+/// The bytes are decoded with [`decode_export`], so input that is not
+/// UTF-8 is read with replacement characters unless
+/// [`ParseOptions::strict_utf8`] is set; a warning says where.
+///
+/// # Errors
+///
+/// Returns an error if the input is not UTF-8 under `strict_utf8`, or as
+/// for [`parse_chat_with_warnings`].
+///
+/// # Example
+///
+/// ```
+/// use cp2md::parser::{parse_chat_bytes, ParseOptions, ParseWarning};
+///
+/// let json = b"{\"responderUsername\":\"Copilot\",\"customTitle\":\"caf\xe9\",\"requests\":[]}";
+/// let (chat, warnings) = parse_chat_bytes(json, &ParseOptions::default()).unwrap();
+/// assert_eq!(chat.custom_title.as_deref(), Some("caf\u{fffd}"));
+/// assert_eq!(warnings, [ParseWarning::ReplacedInvalidUtf8 { offset: 49 }]);
+///
+/// let strict = ParseOptions { strict_utf8: true, ..ParseOptions::default() };
+/// assert!(parse_chat_bytes(json, &strict).is_err());
+/// ```
+pub fn parse_chat_bytes(
+    bytes: &[u8],
+    opts: &ParseOptions,
+) -> Result<(ChatExport, Vec<ParseWarning>), ParseError> {
+    let (json, decoding) = decode_export(bytes, opts)?;
+    let (chat, warnings) = parse_chat_with_warnings(&json, opts)?;
+    Ok((chat, decoding.into_iter().chain(warnings).collect()))
+}
+
+/// Characters of a long line shown on either side of an error's column.
+const ERROR_CONTEXT_CHARS: usize = 40;
+
+/// Formats a JSON error with the line of `src` it points at and a caret
+/// under its column, in the style of compiler diagnostics.
+///
+/// Long lines, as in single-line exports, are cut to the characters around
+/// the column. Errors without a position are formatted as their message.
+///
+/// # Example
+///
+/// ```
+/// use cp2md::parser::format_json_error;
+///
+/// let src = "{\n  \"requests\": [1,,]\n}";
+/// let err = serde_json::from_str::<serde_json::Value>(src).unwrap_err();
+/// assert_eq!(
+///     format_json_error(src, &err),
+///     "expected value at line 2 column 18\n  |\n2 |   \"requests\": [1,,]\n  |                  ^"
+/// );
+/// ```
+#[must_use]
+pub fn format_json_error(src: &str, err: &serde_json::Error) -> String {
+    if err.line() == 0 {
+        return err.to_string();
+    }
+    let line = src.split('\n').nth(err.line() - 1).unwrap_or("");
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    // serde_json counts columns in bytes from 1; count characters instead
+    let mut byte = err.column().saturating_sub(1).min(line.len());
+    while !line.is_char_boundary(byte) {
+        byte -= 1;
+    }
+    let before: Vec<char> = line[..byte].chars().collect();
+    let after: Vec<char> = line[byte..].chars().collect();
+
+    let skipped = before.len().saturating_sub(ERROR_CONTEXT_CHARS);
+    let mut excerpt = String::new();
+    if skipped > 0 {
+        excerpt.push('…');
+    }
+    excerpt.extend(&before[skipped..]);
+    let caret = excerpt.chars().count();
+    excerpt.extend(after.iter().take(ERROR_CONTEXT_CHARS));
+    if after.len() > ERROR_CONTEXT_CHARS {
+        excerpt.push('…');
+    }
+    let excerpt: String = excerpt
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+
+    let number = err.line().to_string();
+    let gutter = " ".repeat(number.len());
+    format!(
+        "{err}\n{gutter} |\n{number} | {excerpt}\n{gutter} | {}^",
+        " ".repeat(caret)
+    )
+}
+
+/// Reports which features of the export format a file uses.
+///
+/// The file must parse as a chat, as with [`parse_chat`]; the features are
+/// then read from its JSON. This is diagnostic: the parser reads every
+/// variant either way.
+///
+/// # Errors
+///
+/// Returns an error if the JSON is malformed or is not a chat export.
+///
+/// # Example
+///
+/// ```
+/// use cp2md::parser::{detect_format_version, FormatVariant};
+///
+/// let hint = detect_format_version(r#"{
+///     "responderUsername": "GitHub Copilot",
+///     "requests": [{
+///         "message": { "text": "Hi" },
+///         "response": { "value": "Hello" },
+///         "variables": [{ "kind": "file", "value": "file:///a.rs" }]
+///     }]
+/// }"#).unwrap();
+///
+/// assert_eq!(hint.variant(), FormatVariant::Legacy);
+/// assert!(hint.top_level_variables && hint.single_responses);
+/// ```
+pub fn detect_format_version(json_str: &str) -> Result<FormatHint, ParseError> {
+    parse_chat(json_str)?;
+    let value: serde_json::Value =
+        serde_json::from_str(json_str).map_err(|e| ParseError::from_json(json_str, e))?;
+
+    let requests = ["requests", "exchanges", "turns"]
+        .iter()
+        .find_map(|key| value.get(key))
+        .and_then(serde_json::Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    let mut hint = FormatHint {
+        requests: requests.len(),
+        selected_model: value.get("selectedModel").is_some_and(|m| !m.is_null()),
+        ..FormatHint::default()
+    };
+    let mut kinds = std::collections::BTreeSet::new();
+    for request in requests {
+        hint.variable_data |= request
+            .get("variableData")
+            .and_then(|v| v.get("variables"))
+            .is_some();
+        hint.top_level_variables |= request.get("variables").is_some();
+        hint.attachments |= request.get("attachments").is_some();
+        hint.agent |= request.get("agent").is_some_and(|a| !a.is_null());
+
+        let elements = match request.get("response") {
+            Some(serde_json::Value::Array(elements)) => elements.iter().collect(),
+            Some(element @ (serde_json::Value::Object(_) | serde_json::Value::String(_))) => {
+                hint.single_responses = true;
+                vec![element]
+            }
+            _ => Vec::new(),
+        };
+        for element in elements {
+            match get_str(element, &["kind"]) {
+                Some(kind) => {
+                    kinds.insert(kind.to_owned());
+                }
+                None => hint.untagged_elements |= element.is_object() || element.is_string(),
+            }
+        }
+    }
+    hint.element_kinds = kinds.into_iter().collect();
+    Ok(hint)
+}
+
+/// Returns the text to deserialize, which is `src` unless it is to be read
+/// leniently, as [`ParseOptions::lenient`] describes.
+fn source_for(src: &str, lenient: bool) -> Cow<'_, str> {
+    if lenient {
+        blank_trailing_commas(src)
+    } else {
+        Cow::Borrowed(src)
+    }
+}
+
+/// Replaces each comma that ends an array or object with a space, leaving
+/// commas inside strings alone.
+///
+/// Blanking rather than removing the commas keeps the line and column of
+/// any later error pointing into `src`.
+fn blank_trailing_commas(src: &str) -> Cow<'_, str> {
+    let bytes = src.as_bytes();
+    let mut trailing = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b',' => {
+                let next = bytes[i + 1..]
+                    .iter()
+                    .find(|b| !matches!(b, b' ' | b'\t' | b'\n' | b'\r'));
+                if matches!(next, Some(b']' | b'}')) {
+                    trailing.push(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    if trailing.is_empty() {
+        return Cow::Borrowed(src);
+    }
+
+    let mut out = String::with_capacity(src.len());
+    let mut start = 0;
+    for i in trailing {
+        out.push_str(&src[start..i]);
+        out.push(' ');
+        start = i + 1;
+    }
+    out.push_str(&src[start..]);
+    Cow::Owned(out)
+}
+
+/// Reads the timestamps of a freshly deserialized chat as milliseconds and
+/// fills in its default model.
+fn finish_chat(mut chat: ChatExport, opts: ParseOptions) -> ChatExport {
+    for request in &mut chat.requests {
+        request.timestamp = opts.timestamp_unit.to_millis(request.timestamp);
+    }
+    if chat.default_model.is_none() {
+        chat.default_model = chat.requests.iter().find_map(|r| r.model_id.clone());
+    }
+    chat
 }
 
 #[cfg(test)]
@@ -465,11 +2128,37 @@ mod tests {
         let chat = parse_chat(&json).unwrap();
 
         assert_eq!(chat.responder_username, "GitHub Copilot");
+        assert!(chat.requester_username.is_none());
+        assert!(chat.custom_title.is_none());
         assert_eq!(chat.requests.len(), 1);
         assert_eq!(chat.requests[0].message.text, "Hello");
         assert_eq!(chat.requests[0].model_id, Some("claude-sonnet-4".into()));
     }
 
+    #[test]
+    fn parses_requester_username() {
+        let json = r#"{
+            "requesterUsername": "octocat",
+            "responderUsername": "GitHub Copilot",
+            "requests": []
+        }"#;
+        let chat = parse_chat(json).unwrap();
+
+        assert_eq!(chat.requester_username.as_deref(), Some("octocat"));
+    }
+
+    #[test]
+    fn parses_custom_title() {
+        let json = r#"{
+            "customTitle": "Fix flaky test",
+            "responderUsername": "GitHub Copilot",
+            "requests": []
+        }"#;
+        let chat = parse_chat(json).unwrap();
+
+        assert_eq!(chat.custom_title.as_deref(), Some("Fix flaky test"));
+    }
+
     #[test]
     fn parses_text_response() {
         let json = minimal_chat_json(&request_json("Hi", r#"{"value": "Hello there!"}"#));
@@ -481,6 +2170,97 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_text_response_with_nested_value() {
+        let json = minimal_chat_json(&request_json(
+            "Hi",
+            r#"{"value": {"value": "Hello there!"}}, {"value": {"value": 3}}"#,
+        ));
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].response[0],
+            ResponseElement::Text("Hello there!".into())
+        );
+        assert!(matches!(
+            chat.requests[0].response[1],
+            ResponseElement::Other { .. }
+        ));
+    }
+
+    #[test]
+    fn lenient_parsing_accepts_trailing_commas() {
+        let json = r#"{
+            "responderUsername": "GitHub Copilot",
+            "requests": [
+                { "message": { "text": "a, ]" }, "response": [{ "value": "Hi" },], },
+            ],
+        }"#;
+        assert!(parse_chat(json).is_err());
+
+        let opts = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let chat = parse_chat_with_options(json, &opts).unwrap();
+        assert_eq!(chat.requests[0].message.text, "a, ]");
+        assert_eq!(
+            chat.requests[0].response,
+            [ResponseElement::Text("Hi".into())]
+        );
+        let (_, warnings) = parse_chat_with_warnings(json, &opts).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn blanking_trailing_commas_keeps_positions() {
+        assert!(matches!(
+            blank_trailing_commas(r#"[1, "\",]"]"#),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(blank_trailing_commas("[1,\n]"), "[1 \n]");
+        assert_eq!(blank_trailing_commas(r#"{"a":[1,],}"#), r#"{"a":[1 ] }"#);
+    }
+
+    #[test]
+    fn normalizes_crlf_line_endings() {
+        let json = minimal_chat_json(
+            r#"{
+                "message": { "text": "line one\r\nline two\rline three" },
+                "response": [
+                    { "value": "reply\r\n\r\nmore" },
+                    {
+                        "kind": "toolInvocationSerialized",
+                        "pastTenseMessage": { "value": "Ran\r\ncommand" }
+                    },
+                    {
+                        "kind": "textEditGroup",
+                        "uri": { "path": "/src/main.rs" },
+                        "edits": [[{"text": "a\r\nb"}]]
+                    }
+                ]
+            }"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+        let request = &chat.requests[0];
+
+        assert_eq!(request.message.text, "line one\nline two\nline three");
+        assert_eq!(
+            request.response[0],
+            ResponseElement::Text("reply\n\nmore".into())
+        );
+        assert_eq!(
+            request.response[1],
+            ResponseElement::ToolInvocation {
+                past_tense: Some("Ran\ncommand".into())
+            }
+        );
+        match &request.response[2] {
+            ResponseElement::TextEditGroup { edits, .. } => assert_eq!(edits, &["a\nb"]),
+            other => panic!("Expected TextEditGroup, got {other:?}"),
+        }
+    }
+
     #[test]
     fn parses_inline_reference() {
         let json = minimal_chat_json(&request_json(
@@ -582,62 +2362,253 @@ mod tests {
         let chat = parse_chat(&json).unwrap();
 
         match &chat.requests[0].response[0] {
-            ResponseElement::TextEditGroup { path, edits } => {
+            ResponseElement::TextEditGroup { path, edits, kind } => {
                 assert_eq!(path, "/src/main.rs");
                 assert_eq!(edits.len(), 2);
                 assert_eq!(edits[0], "fn main() {}");
                 assert_eq!(edits[1], "// comment");
+                assert_eq!(*kind, EditKind::Modify);
             }
             other => panic!("Expected TextEditGroup, got {other:?}"),
         }
     }
 
-    #[test]
-    fn parses_tool_invocation() {
-        let json = minimal_chat_json(&request_json(
-            "Search",
-            r#"{
-                "kind": "toolInvocationSerialized",
-                "pastTenseMessage": { "value": "Searched for text" }
-            }"#,
-        ));
+    fn parse_edit_kind(edit_group_json: &str) -> EditKind {
+        let json = minimal_chat_json(&request_json("Edit file", edit_group_json));
         let chat = parse_chat(&json).unwrap();
 
         match &chat.requests[0].response[0] {
-            ResponseElement::ToolInvocation { past_tense } => {
-                assert_eq!(past_tense.as_deref(), Some("Searched for text"));
-            }
-            other => panic!("Expected ToolInvocation, got {other:?}"),
+            ResponseElement::TextEditGroup { kind, .. } => *kind,
+            other => panic!("Expected TextEditGroup, got {other:?}"),
         }
     }
 
     #[test]
-    fn parses_tool_invocation_without_message() {
-        let json = minimal_chat_json(&request_json(
-            "Search",
-            r#"{"kind": "toolInvocationSerialized"}"#,
-        ));
-        let chat = parse_chat(&json).unwrap();
+    fn parses_created_edit_group_from_flag() {
+        let kind = parse_edit_kind(
+            r#"{
+                "kind": "textEditGroup",
+                "uri": { "path": "/src/new_module.rs" },
+                "isNew": true,
+                "edits": [[{"text": "pub fn f() {}"}]]
+            }"#,
+        );
+        assert_eq!(kind, EditKind::Create);
+    }
 
-        match &chat.requests[0].response[0] {
-            ResponseElement::ToolInvocation { past_tense } => {
-                assert!(past_tense.is_none());
-            }
-            other => panic!("Expected ToolInvocation, got {other:?}"),
-        }
+    #[test]
+    fn parses_created_edit_group_from_empty_original_uri() {
+        let kind = parse_edit_kind(
+            r#"{
+                "kind": "textEditGroup",
+                "uri": { "path": "/src/new_module.rs" },
+                "originalUri": { "path": "" },
+                "edits": [[{"text": "pub fn f() {}"}]]
+            }"#,
+        );
+        assert_eq!(kind, EditKind::Create);
     }
 
     #[test]
-    fn parses_unknown_kind_as_other() {
-        let json = minimal_chat_json(&request_json(
-            "Something",
-            r#"{"kind": "unknownKind", "data": "whatever"}"#,
+    fn parses_deleted_edit_group_from_state() {
+        let kind = parse_edit_kind(
+            r#"{
+                "kind": "textEditGroup",
+                "uri": { "path": "/src/old.rs" },
+                "state": "deleted",
+                "edits": []
+            }"#,
+        );
+        assert_eq!(kind, EditKind::Delete);
+    }
+
+    #[test]
+    fn parses_deleted_edit_group_from_flag() {
+        let kind = parse_edit_kind(
+            r#"{
+                "kind": "textEditGroup",
+                "uri": { "path": "/src/old.rs" },
+                "isDeleted": true
+            }"#,
+        );
+        assert_eq!(kind, EditKind::Delete);
+    }
+
+    #[test]
+    fn parses_modified_edit_group_with_original_uri() {
+        let kind = parse_edit_kind(
+            r#"{
+                "kind": "textEditGroup",
+                "uri": { "path": "/src/main.rs" },
+                "originalUri": { "path": "/src/main.rs" },
+                "edits": [[{"text": "fn main() {}"}]]
+            }"#,
+        );
+        assert_eq!(kind, EditKind::Modify);
+    }
+
+    #[test]
+    fn parses_tool_invocation() {
+        let json = minimal_chat_json(&request_json(
+            "Search",
+            r#"{
+                "kind": "toolInvocationSerialized",
+                "pastTenseMessage": { "value": "Searched for text" }
+            }"#,
+        ));
+        let chat = parse_chat(&json).unwrap();
+
+        match &chat.requests[0].response[0] {
+            ResponseElement::ToolInvocation { past_tense } => {
+                assert_eq!(past_tense.as_deref(), Some("Searched for text"));
+            }
+            other => panic!("Expected ToolInvocation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_vulnerability_annotations() {
+        let json = minimal_chat_json(&request_json(
+            "Run it",
+            r#"{
+                "kind": "markdownVuln",
+                "content": { "value": "eval(input)" },
+                "vulnerabilities": [
+                    { "title": "Code injection", "description": "Input reaches eval." }
+                ]
+            },
+            {
+                "kind": "codeblockUri",
+                "uri": { "path": "/src/run.js" },
+                "codeVulnAnnotations": [{ "message": "Hardcoded credentials" }]
+            },
+            {
+                "kind": "markdownVuln",
+                "content": { "value": "" },
+                "vulnerabilities": [{ "title": "Weak hash" }]
+            }"#,
+        ));
+        let chat = parse_chat(&json).unwrap();
+
+        let warning = |message: &str| ResponseElement::Annotation {
+            severity: Severity::Warning,
+            message: message.into(),
+        };
+        assert_eq!(
+            chat.requests[0].response,
+            [
+                ResponseElement::Text("eval(input)".into()),
+                warning("Code injection: Input reaches eval."),
+                ResponseElement::CodeBlockUri {
+                    path: "/src/run.js".into()
+                },
+                warning("Hardcoded credentials"),
+                warning("Weak hash"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_filtered_response_marker() {
+        let json = minimal_chat_json(
+            r#"{
+                "message": { "text": "Write a parser" },
+                "response": [{ "value": "Here is" }],
+                "result": {
+                    "errorDetails": {
+                        "message": "The response was filtered due to matching public code.",
+                        "responseIsFiltered": true
+                    }
+                }
+            },
+            {
+                "message": { "text": "Try again" },
+                "response": [],
+                "result": { "errorDetails": { "message": "Rate limited" } }
+            }"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].response[1],
+            ResponseElement::Annotation {
+                severity: Severity::Filtered,
+                message: "The response was filtered due to matching public code.".into(),
+            }
+        );
+        assert!(chat.requests[1].response.is_empty());
+    }
+
+    #[test]
+    fn parses_token_usage() {
+        let json = minimal_chat_json(
+            r#"{
+                "message": { "text": "Explain" },
+                "response": [],
+                "result": { "metadata": { "usage": { "promptTokens": 1234, "completionTokens": 567 } } }
+            },
+            {
+                "message": { "text": "Again" },
+                "response": [],
+                "result": { "metadata": { "usage": { "prompt_tokens": 10 } } }
+            },
+            {
+                "message": { "text": "Unknown" },
+                "response": [],
+                "result": { "metadata": { "usage": { "total": 99 } } }
+            },
+            {
+                "message": { "text": "None" },
+                "response": []
+            }"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        let usage: Vec<_> = chat.exchanges().map(|req| req.usage).collect();
+        assert_eq!(
+            usage,
+            [
+                Some(Usage {
+                    prompt: 1234,
+                    completion: 567
+                }),
+                Some(Usage {
+                    prompt: 10,
+                    completion: 0
+                }),
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_tool_invocation_without_message() {
+        let json = minimal_chat_json(&request_json(
+            "Search",
+            r#"{"kind": "toolInvocationSerialized"}"#,
+        ));
+        let chat = parse_chat(&json).unwrap();
+
+        match &chat.requests[0].response[0] {
+            ResponseElement::ToolInvocation { past_tense } => {
+                assert!(past_tense.is_none());
+            }
+            other => panic!("Expected ToolInvocation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parses_unknown_kind_as_other() {
+        let json = minimal_chat_json(&request_json(
+            "Something",
+            r#"{"kind": "unknownKind", "data": "whatever"}"#,
         ));
         let chat = parse_chat(&json).unwrap();
 
         assert!(matches!(
             chat.requests[0].response[0],
-            ResponseElement::Other
+            ResponseElement::Other { raw: None }
         ));
     }
 
@@ -648,8 +2619,86 @@ mod tests {
 
         assert!(matches!(
             chat.requests[0].response[0],
-            ResponseElement::Other
+            ResponseElement::Other { raw: None }
+        ));
+    }
+
+    #[test]
+    fn keeps_raw_unknown_element_when_requested() {
+        let json = minimal_chat_json(&request_json(
+            "New",
+            r#"{"kind": "futureFeature", "payload": [1, 2]}"#,
         ));
+        let opts = ParseOptions {
+            keep_raw: true,
+            ..ParseOptions::default()
+        };
+        let chat = parse_chat_with_options(&json, &opts).unwrap();
+
+        let elem = &chat.requests[0].response[0];
+        assert_eq!(elem.unknown_kind(), Some("futureFeature"));
+        match elem {
+            ResponseElement::Other { raw: Some(raw) } => assert_eq!(raw["payload"][1], 2),
+            other => panic!("Expected Other with raw JSON, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn deserializing_an_unknown_element_does_not_keep_its_json() {
+        let elem: ResponseElement =
+            serde_json::from_str(r#"{"kind": "futureFeature", "payload": [1, 2]}"#).unwrap();
+        assert!(matches!(elem, ResponseElement::Other { raw: None }));
+
+        let request: Request =
+            serde_json::from_str(r#"{"response": [{"kind": "futureFeature"}]}"#).unwrap();
+        assert!(matches!(
+            request.response[..],
+            [ResponseElement::Other { raw: None }]
+        ));
+    }
+
+    #[test]
+    fn other_equality_ignores_raw() {
+        let with_raw = ResponseElement::Other {
+            raw: Some(serde_json::json!({"kind": "x"})),
+        };
+        assert_eq!(with_raw, ResponseElement::Other { raw: None });
+        assert_ne!(with_raw, ResponseElement::Text(String::new()));
+    }
+
+    #[test]
+    fn parses_bare_string_response_elements() {
+        let json = minimal_chat_json(&request_json("Hi", r#""Hello", "world""#));
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].response,
+            vec![
+                ResponseElement::Text("Hello".into()),
+                ResponseElement::Text("world".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_mixed_string_and_object_response_elements() {
+        let json = minimal_chat_json(&request_json(
+            "Hi",
+            r#""See ", {"kind": "inlineReference", "inlineReference": {"path": "/a.rs"}}, {"value": " for details"}"#,
+        ));
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].response,
+            vec![
+                ResponseElement::Text("See ".into()),
+                ResponseElement::InlineReference {
+                    name: None,
+                    path: "/a.rs".into(),
+                },
+                ResponseElement::Text(" for details".into()),
+            ]
+        );
     }
 
     #[test]
@@ -685,6 +2734,41 @@ mod tests {
         assert!(chat.requests[0].model_id.is_none());
     }
 
+    #[test]
+    fn parses_session_model() {
+        let json = r#"{
+            "responderUsername": "Copilot",
+            "selectedModel": { "identifier": "copilot/gpt-4o" },
+            "requests": [{ "modelId": "", "message": { "text": "Hi" }, "response": [] }]
+        }"#;
+        let chat = parse_chat(json).unwrap();
+
+        assert_eq!(chat.default_model.as_deref(), Some("copilot/gpt-4o"));
+        assert!(chat.requests[0].model_id.is_none());
+
+        let json = json.replace(r#"{ "identifier": "copilot/gpt-4o" }"#, r#""o3""#);
+        let (chat, _) = parse_chat_with_warnings(&json, &ParseOptions::default()).unwrap();
+        assert_eq!(chat.default_model.as_deref(), Some("o3"));
+    }
+
+    #[test]
+    fn takes_default_model_from_first_request_naming_one() {
+        let json = minimal_chat_json(
+            r#"{ "message": { "text": "a" }, "response": [] },
+               { "modelId": "claude-sonnet-4", "message": { "text": "b" }, "response": [] },
+               { "modelId": "gpt-4o", "message": { "text": "c" }, "response": [] }"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(chat.default_model.as_deref(), Some("claude-sonnet-4"));
+        assert!(
+            parse_chat(&minimal_chat_json(""))
+                .unwrap()
+                .default_model
+                .is_none()
+        );
+    }
+
     #[test]
     fn parses_agent_name() {
         let json = minimal_chat_json(&request_json_with_agent("Hi", "documentation-reviewer"));
@@ -696,6 +2780,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parses_hidden_request_flags() {
+        for flag in ["isHidden", "shouldBeBlocked"] {
+            let json = minimal_chat_json(&format!(
+                r#"{{ "message": {{ "text": "Hi" }}, "response": [], "{flag}": true }}"#
+            ));
+            let chat = parse_chat(&json).unwrap();
+
+            assert!(chat.requests[0].hidden, "{flag} not read");
+        }
+    }
+
+    #[test]
+    fn requests_are_visible_unless_flagged() {
+        let json = minimal_chat_json(&format!(
+            r#"{}, {{ "message": {{ "text": "Hi" }}, "response": [], "isHidden": false }}"#,
+            request_json("Hi", "")
+        ));
+        let chat = parse_chat(&json).unwrap();
+
+        assert!(chat.requests.iter().all(|request| !request.hidden));
+    }
+
     #[test]
     fn parses_request_without_agent() {
         let json = minimal_chat_json(&request_json("Hi", ""));
@@ -749,11 +2856,154 @@ mod tests {
                 path,
                 start_line,
                 end_line,
+                text,
             } => {
                 assert_eq!(name, "todo.md");
                 assert_eq!(path, "/docs/todo.md");
                 assert_eq!(*start_line, 5);
                 assert_eq!(*end_line, 10);
+                assert!(text.is_none());
+            }
+            other => panic!("Expected Selection, got {other:?}"),
+        }
+    }
+
+    /// Parses a single file variable and returns the resulting context item.
+    fn parse_file_variable(variable: &str) -> ContextItem {
+        let json = minimal_chat_json(&request_json_with_context("Hi", variable));
+        let mut chat = parse_chat(&json).unwrap();
+        chat.requests.remove(0).context.remove(0)
+    }
+
+    #[test]
+    fn whole_file_with_cursor_range_is_file() {
+        let item = parse_file_variable(
+            r#"{
+                "kind": "file",
+                "id": "file:///src/lib.rs",
+                "name": "file:lib.rs",
+                "value": {
+                    "uri": { "path": "/src/lib.rs" },
+                    "range": { "startLineNumber": 57, "endLineNumber": 57 }
+                }
+            }"#,
+        );
+        assert_eq!(
+            item,
+            ContextItem::File {
+                name: "lib.rs".into(),
+                path: "/src/lib.rs".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn explicit_selection_flag_wins_over_id() {
+        let item = parse_file_variable(
+            r#"{
+                "kind": "file",
+                "id": "file:///src/lib.rs",
+                "name": "file:lib.rs",
+                "value": {
+                    "uri": { "path": "/src/lib.rs" },
+                    "range": { "startLineNumber": 10, "endLineNumber": 12 },
+                    "isSelection": true
+                }
+            }"#,
+        );
+        assert!(matches!(
+            item,
+            ContextItem::Selection {
+                start_line: 10,
+                end_line: 12,
+                ..
+            }
+        ));
+
+        let item = parse_file_variable(
+            r#"{
+                "kind": "file",
+                "id": "vscode.implicit.selection",
+                "name": "file:lib.rs",
+                "isSelection": false,
+                "value": {
+                    "uri": { "path": "/src/lib.rs" },
+                    "range": { "startLineNumber": 10, "endLineNumber": 12 }
+                }
+            }"#,
+        );
+        assert!(matches!(item, ContextItem::File { .. }));
+    }
+
+    #[test]
+    fn single_line_selection_at_first_line_is_selection() {
+        let item = parse_file_variable(
+            r#"{
+                "kind": "file",
+                "id": "vscode.implicit.selection",
+                "name": "file:lib.rs",
+                "value": {
+                    "uri": { "path": "/src/lib.rs" },
+                    "range": { "startLineNumber": 1, "endLineNumber": 1 }
+                }
+            }"#,
+        );
+        assert!(matches!(
+            item,
+            ContextItem::Selection {
+                start_line: 1,
+                end_line: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn range_short_of_known_line_count_is_selection() {
+        let variable = |end: u32| {
+            format!(
+                r#"{{
+                    "kind": "file",
+                    "id": "file:///src/lib.rs",
+                    "name": "file:lib.rs",
+                    "value": {{
+                        "uri": {{ "path": "/src/lib.rs" }},
+                        "range": {{ "startLineNumber": 1, "endLineNumber": {end} }},
+                        "lineCount": 80
+                    }}
+                }}"#
+            )
+        };
+        assert!(matches!(
+            parse_file_variable(&variable(20)),
+            ContextItem::Selection { .. }
+        ));
+        assert!(matches!(
+            parse_file_variable(&variable(80)),
+            ContextItem::File { .. }
+        ));
+    }
+
+    #[test]
+    fn parses_selection_text() {
+        let json = minimal_chat_json(&request_json_with_context(
+            "Hi",
+            r#"{
+                "kind": "file",
+                "id": "vscode.implicit.selection",
+                "name": "file:lib.rs",
+                "value": {
+                    "uri": { "path": "/src/lib.rs" },
+                    "range": { "startLineNumber": 2, "endLineNumber": 3 },
+                    "text": "let a = 1;\r\nlet b = 2;"
+                }
+            }"#,
+        ));
+        let chat = parse_chat(&json).unwrap();
+
+        match &chat.requests[0].context[0] {
+            ContextItem::Selection { text, .. } => {
+                assert_eq!(text.as_deref(), Some("let a = 1;\nlet b = 2;"));
             }
             other => panic!("Expected Selection, got {other:?}"),
         }
@@ -801,6 +3051,70 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parses_context_from_older_top_level_variables() {
+        let json = minimal_chat_json(
+            r#"{
+                "message": { "text": "Hi" },
+                "response": [],
+                "variables": [
+                    {
+                        "kind": "file",
+                        "name": "file:My Notes.md",
+                        "value": "file:///home/user/My%20Notes.md"
+                    },
+                    { "name": "lib.rs", "value": "file://host/share/lib.rs" },
+                    { "kind": "folder", "name": "src/", "value": "file:///project/src" },
+                    { "name": "web", "value": "https://example.com" }
+                ]
+            }"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].context,
+            vec![
+                ContextItem::File {
+                    name: "My Notes.md".into(),
+                    path: "/home/user/My Notes.md".into(),
+                },
+                ContextItem::File {
+                    name: "lib.rs".into(),
+                    path: "/share/lib.rs".into(),
+                },
+                ContextItem::Folder {
+                    name: "src/".into(),
+                    path: "/project/src".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn prefers_variable_data_over_older_variables() {
+        let json = minimal_chat_json(
+            r#"{
+                "message": { "text": "Hi" },
+                "response": [],
+                "variableData": { "variables": [
+                    { "kind": "file", "name": "new.rs", "value": { "uri": { "path": "/new.rs" } } }
+                ] },
+                "variables": [
+                    { "kind": "file", "name": "old.rs", "value": "file:///old.rs" }
+                ]
+            }"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].context,
+            vec![ContextItem::File {
+                name: "new.rs".into(),
+                path: "/new.rs".into(),
+            }]
+        );
+    }
+
     #[test]
     fn skips_tool_and_prompt_text_context() {
         let json = minimal_chat_json(&request_json_with_context(
@@ -823,6 +3137,77 @@ mod tests {
         assert!(chat.requests[0].context.is_empty());
     }
 
+    #[test]
+    fn detects_current_format_features() {
+        let json = r#"{
+            "responderUsername": "GitHub Copilot",
+            "selectedModel": { "identifier": "gpt-4o" },
+            "requests": [{
+                "agent": { "name": "workspace" },
+                "message": { "text": "Hi" },
+                "response": [
+                    { "value": "Hello" },
+                    { "kind": "toolInvocationSerialized" },
+                    { "kind": "inlineReference", "inlineReference": { "path": "/a.rs" } },
+                    { "kind": "inlineReference", "inlineReference": { "path": "/b.rs" } }
+                ],
+                "variableData": { "variables": [] },
+                "attachments": []
+            }]
+        }"#;
+        let hint = detect_format_version(json).unwrap();
+
+        assert_eq!(
+            hint,
+            FormatHint {
+                requests: 1,
+                selected_model: true,
+                variable_data: true,
+                attachments: true,
+                agent: true,
+                untagged_elements: true,
+                element_kinds: vec!["inlineReference".into(), "toolInvocationSerialized".into()],
+                ..FormatHint::default()
+            }
+        );
+        assert_eq!(hint.variant(), FormatVariant::Current);
+        assert_eq!(
+            hint.to_string(),
+            "current: 1 request, selectedModel, variableData, attachments, agent, untagged text, \
+             kinds: inlineReference toolInvocationSerialized"
+        );
+    }
+
+    #[test]
+    fn detects_legacy_and_mixed_formats() {
+        let legacy = minimal_chat_json(
+            r#"{ "message": { "text": "Hi" }, "response": "Hello",
+                 "variables": [{ "kind": "file", "value": "file:///a.rs" }] }"#,
+        );
+        let hint = detect_format_version(&legacy).unwrap();
+        assert!(hint.top_level_variables && hint.single_responses);
+        assert_eq!(hint.variant(), FormatVariant::Legacy);
+
+        let mixed = minimal_chat_json(&format!(
+            r#"{}, {{ "message": {{ "text": "Hi" }}, "response": {{ "value": "Hello" }} }}"#,
+            request_json_with_context("Hi", "")
+        ));
+        let hint = detect_format_version(&mixed).unwrap();
+        assert_eq!(hint.requests, 2);
+        assert_eq!(hint.variant(), FormatVariant::Mixed);
+
+        let plain = minimal_chat_json(&request_json("Hi", ""));
+        let hint = detect_format_version(&plain).unwrap();
+        assert_eq!(hint.variant(), FormatVariant::Unknown);
+        assert_eq!(hint.to_string(), "unknown: 1 request");
+    }
+
+    #[test]
+    fn detect_rejects_what_does_not_parse() {
+        assert!(detect_format_version("{").is_err());
+        assert!(detect_format_version(r#"{"responderUsername": "Copilot"}"#).is_err());
+    }
+
     #[test]
     fn returns_error_for_invalid_json() {
         let result = parse_chat("not valid json");
@@ -834,4 +3219,440 @@ mod tests {
         let result = parse_chat(r#"{"responderUsername": "Copilot"}"#);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn reads_requests_stored_under_exchanges() {
+        let json = r#"{
+            "responderUsername": "Copilot",
+            "exchanges": [{"message": {"text": "Hi"}, "response": []}]
+        }"#;
+        let chat = parse_chat(json).unwrap();
+        assert_eq!(chat.requests.len(), 1);
+        assert_eq!(chat.requests[0].message.text, "Hi");
+
+        let (chat, _) = parse_chat_with_warnings(json, &ParseOptions::default()).unwrap();
+        assert_eq!(chat.requests.len(), 1);
+        assert_eq!(detect_format_version(json).unwrap().requests, 1);
+    }
+
+    #[test]
+    fn defaults_a_missing_responder_username() {
+        let chat = parse_chat(r#"{"turns": []}"#).unwrap();
+        assert_eq!(chat.responder_username, DEFAULT_RESPONDER);
+        assert!(chat.requests.is_empty());
+    }
+
+    #[test]
+    fn exchanges_iterates_requests_in_order() {
+        let json = minimal_chat_json(
+            r#"{"message": {"text": "first"}, "response": []},
+               {"message": {"text": "second"}, "response": []}"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        let texts: Vec<_> = chat.exchanges().map(|r| r.message.text.as_str()).collect();
+        assert_eq!(texts, ["first", "second"]);
+    }
+
+    #[test]
+    fn parses_response_as_single_object() {
+        let json = minimal_chat_json(
+            r#"{"message": {"text": "Hi"}, "response": {"value": "Hello there"}}"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].response,
+            [ResponseElement::Text("Hello there".into())]
+        );
+    }
+
+    #[test]
+    fn parses_response_as_plain_string() {
+        let json =
+            minimal_chat_json(r#"{"message": {"text": "Hi"}, "response": "Hello\r\nthere"}"#);
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].response,
+            [ResponseElement::Text("Hello\nthere".into())]
+        );
+    }
+
+    #[test]
+    fn reads_timestamps_in_seconds_as_milliseconds() {
+        let json = minimal_chat_json(
+            r#"{"timestamp": 1733356800, "response": []},
+               {"timestamp": 1733356800000, "response": []},
+               {"response": []}"#,
+        );
+        let (chat, warnings) = parse_chat_with_warnings(&json, &ParseOptions::default()).unwrap();
+        let timestamps: Vec<_> = chat.requests.iter().map(|r| r.timestamp).collect();
+        assert_eq!(timestamps, [1_733_356_800_000, 1_733_356_800_000, 0]);
+        assert!(warnings.is_empty());
+        assert_eq!(chat, parse_chat(&json).unwrap());
+
+        let opts = ParseOptions {
+            timestamp_unit: TimestampUnit::Seconds,
+            ..ParseOptions::default()
+        };
+        let json = minimal_chat_json(r#"{"timestamp": 1733356800, "response": []}"#);
+        let chat = parse_chat_with_options(&json, &opts).unwrap();
+        assert_eq!(chat.requests[0].timestamp, 1_733_356_800_000);
+    }
+
+    #[test]
+    fn warns_about_implausible_timestamps() {
+        let json = minimal_chat_json(
+            r#"{"timestamp": 1733356800, "response": []},
+               {"timestamp": 1733356800000, "response": []},
+               {"timestamp": -5, "response": []},
+               {"timestamp": 900000000000, "response": []},
+               {"timestamp": 9000000000000000, "response": []}"#,
+        );
+        let (_, warnings) = parse_chat_with_warnings(&json, &ParseOptions::default()).unwrap();
+        let flagged: Vec<_> = warnings
+            .iter()
+            .map(|warning| match warning {
+                ParseWarning::ImplausibleTimestamp { index, timestamp } => (*index, *timestamp),
+                other => panic!("unexpected warning {other:?}"),
+            })
+            .collect();
+        assert_eq!(
+            flagged,
+            [(2, -5), (3, 900_000_000_000), (4, 9_000_000_000_000_000)]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "request 3 has an implausible timestamp -5 (see --timestamp-unit)"
+        );
+
+        // Read as milliseconds, seconds fall in 1970
+        let opts = ParseOptions {
+            timestamp_unit: TimestampUnit::Millis,
+            ..ParseOptions::default()
+        };
+        let (chat, warnings) = parse_chat_with_warnings(&json, &opts).unwrap();
+        assert_eq!(chat.requests[0].timestamp, 1_733_356_800);
+        assert_eq!(
+            warnings[0],
+            ParseWarning::ImplausibleTimestamp {
+                index: 0,
+                timestamp: 1_733_356_800
+            }
+        );
+    }
+
+    #[test]
+    fn plausible_timestamps_run_from_2015_to_a_day_ahead() {
+        let now = 1_733_356_800_000;
+        assert!(is_plausible_timestamp(EARLIEST_TIMESTAMP, now));
+        assert!(!is_plausible_timestamp(EARLIEST_TIMESTAMP - 1, now));
+        assert!(is_plausible_timestamp(now + FUTURE_SLACK, now));
+        assert!(!is_plausible_timestamp(now + FUTURE_SLACK + 1, now));
+        assert!(is_plausible_timestamp(now, i64::MAX));
+    }
+
+    #[test]
+    fn warns_only_about_non_array_responses() {
+        let json = minimal_chat_json(
+            r#"{"response": [{"value": "array"}]},
+               {"response": {"value": "object"}},
+               {"response": "string"},
+               {"response": null}"#,
+        );
+        let (chat, warnings) = parse_chat_with_warnings(&json, &ParseOptions::default()).unwrap();
+
+        let texts: Vec<_> = chat.requests.iter().map(|r| r.response.len()).collect();
+        assert_eq!(texts, [1, 1, 1, 0]);
+        assert_eq!(
+            warnings,
+            [
+                ParseWarning::NonArrayResponse {
+                    index: 1,
+                    shape: "object"
+                },
+                ParseWarning::NonArrayResponse {
+                    index: 2,
+                    shape: "string"
+                },
+            ]
+        );
+        assert_eq!(chat, parse_chat(&json).unwrap());
+        assert_eq!(
+            warnings[0].to_string(),
+            "request 2 stores its response as a bare object instead of an array"
+        );
+    }
+
+    #[test]
+    fn parses_pasted_text_and_terminal_attachments() {
+        let json = minimal_chat_json(
+            r#"{
+                "message": {"text": "Why does this fail?"},
+                "variableData": {"variables": [
+                    {"kind": "file", "name": "main.rs", "value": {"path": "/src/main.rs"}}
+                ]},
+                "attachments": [
+                    {"type": "pastedText", "value": "let x = 1;\r\nlet y = 2;"},
+                    {"type": "terminalOutput", "value": {"output": "error[E0308]: mismatched types"}},
+                    {"type": "image", "value": {"mimeType": "image/png"}},
+                    {"type": "pastedText"}
+                ],
+                "response": []
+            }"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].context,
+            [
+                ContextItem::File {
+                    name: "main.rs".into(),
+                    path: "/src/main.rs".into(),
+                },
+                ContextItem::PastedText {
+                    text: "let x = 1;\nlet y = 2;".into(),
+                },
+                ContextItem::TerminalOutput {
+                    text: "error[E0308]: mismatched types".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_attachments_without_variable_data() {
+        let json = minimal_chat_json(
+            r#"{"attachments": [{"type": "terminal", "value": {"text": "$ cargo test"}}],
+                "response": []}"#,
+        );
+        let chat = parse_chat(&json).unwrap();
+
+        assert_eq!(
+            chat.requests[0].context,
+            [ContextItem::TerminalOutput {
+                text: "$ cargo test".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_response_when_one_element_is_malformed() {
+        let json = minimal_chat_json(r#"{"response": [{"value": "keep me"}, null, 42]}"#);
+        let opts = ParseOptions {
+            keep_raw: true,
+            ..ParseOptions::default()
+        };
+        let (chat, warnings) = parse_chat_with_warnings(&json, &opts).unwrap();
+
+        let response = &chat.requests[0].response;
+        assert_eq!(response.len(), 3);
+        assert_eq!(response[0], ResponseElement::Text("keep me".into()));
+        assert!(matches!(
+            &response[1],
+            ResponseElement::Other {
+                raw: Some(serde_json::Value::Null)
+            }
+        ));
+        assert!(matches!(
+            &response[2],
+            ResponseElement::Other { raw: Some(raw) } if raw == 42
+        ));
+        assert_eq!(
+            warnings,
+            [
+                ParseWarning::MalformedElement {
+                    request: 0,
+                    element: 1
+                },
+                ParseWarning::MalformedElement {
+                    request: 0,
+                    element: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_json_error_with_caret() {
+        let src = "{\n  \"responderUsername\": \"Copilot\"\n  \"requests\": []\n}";
+        let err = parse_chat(src).unwrap_err();
+        assert_eq!(
+            format_json_error(src, err.json_error().unwrap()),
+            "expected `,` or `}` at line 3 column 3\n  |\n3 |   \"requests\": []\n  |   ^"
+        );
+    }
+
+    #[test]
+    fn truncated_export_is_reported_as_truncated() {
+        let json = minimal_chat_json(&request_json("Hello", r#"{ "value": "Hi there" }"#));
+        let cut = &json[..json.find("Hi there").unwrap() + 2];
+
+        let err = parse_chat(cut).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::Syntax {
+                truncated: true,
+                ..
+            }
+        ));
+        let message = err.to_string();
+        assert!(
+            message.starts_with("failed to parse JSON: file appears truncated (ends mid-value): "),
+            "{message}"
+        );
+        // The excerpt runs to the end of the input
+        let tail = format!(r#"near "{}"response": [{{ "value": "Hi""#, " ".repeat(14));
+        assert!(message.ends_with(&tail), "{message}");
+    }
+
+    #[test]
+    fn syntax_error_shows_input_around_offset() {
+        let before = format!(
+            r#"{{"responderUsername":"Copilot","customTitle":"{}","#,
+            "t".repeat(50)
+        );
+        let json = format!("{before}\t\"requests\":[}}, {}]}}", r#"{"x":1}"#.repeat(10));
+
+        let err = parse_chat_with_warnings(&json, &ParseOptions::default()).unwrap_err();
+        let ParseError::Syntax {
+            excerpt, truncated, ..
+        } = &err
+        else {
+            panic!("expected a syntax error, got {err:?}");
+        };
+        assert!(!truncated);
+        // 40 characters either side of the `}`, with the tab escaped
+        assert_eq!(
+            excerpt,
+            &format!(
+                r#"{}",\t"requests":[}}, {}{{""#,
+                "t".repeat(25),
+                r#"{"x":1}"#.repeat(5)
+            )
+        );
+        assert!(err.to_string().contains("expected value at line 1 column"));
+    }
+
+    #[test]
+    fn data_errors_have_no_excerpt() {
+        let err = parse_chat(r#"{"responderUsername": "Copilot"}"#).unwrap_err();
+        assert!(matches!(err, ParseError::Json { .. }));
+        assert_eq!(
+            err.to_string(),
+            "failed to parse JSON: missing field `requests` at line 1 column 32"
+        );
+    }
+
+    #[test]
+    fn formats_json_error_on_long_line_as_excerpt() {
+        let src = format!(
+            r#"{{"responderUsername":"{}","requests":[}}"#,
+            "x".repeat(100)
+        );
+        let err = serde_json::from_str::<ChatExport>(&src).unwrap_err();
+
+        let formatted = format_json_error(&src, &err);
+        let lines: Vec<_> = formatted.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[2],
+            format!("1 | …{}\",\"requests\":[}}", "x".repeat(26))
+        );
+        // Under the character after the ellipsis and 40 characters of context
+        assert_eq!(lines[3].find('^'), Some("  | …".chars().count() + 40));
+    }
+
+    #[test]
+    fn formats_json_error_at_end_of_input() {
+        let src = "{\"requests\": [\n";
+        let err = serde_json::from_str::<serde_json::Value>(src).unwrap_err();
+
+        assert_eq!(
+            format_json_error(src, &err),
+            "EOF while parsing a list at line 2 column 0\n  |\n2 | \n  | ^"
+        );
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn serialized_chat_parses_back_the_same() {
+        let json = r#"{
+            "requesterUsername": "octocat",
+            "responderUsername": "GitHub Copilot",
+            "customTitle": "Round trip",
+            "requests": [{
+                "timestamp": 1733356800000,
+                "modelId": "claude-sonnet-4",
+                "agent": { "name": "workspace" },
+                "message": { "text": "Hello" },
+                "variableData": { "variables": [
+                    { "kind": "file", "name": "file:lib.rs", "value": { "path": "/src/lib.rs" } },
+                    { "kind": "file", "id": "vscode.implicit.selection", "name": "main.rs",
+                      "value": { "path": "/src/main.rs", "text": "fn main() {}",
+                                 "range": { "startLineNumber": 3, "endLineNumber": 5 } } },
+                    { "kind": "folder", "name": "src/", "value": { "path": "/src" } },
+                    { "kind": "promptFile", "name": "prompt:copilot-instructions.md" }
+                ] },
+                "attachments": [
+                    { "type": "pastedText", "value": "pasted" },
+                    { "type": "terminal", "value": { "output": "$ ls" } }
+                ],
+                "response": [
+                    { "value": "Hi" },
+                    { "kind": "inlineReference", "name": "lib", "inlineReference": { "path": "/src/lib.rs" } },
+                    { "kind": "codeblockUri", "uri": { "path": "/src/lib.rs" } },
+                    { "kind": "textEditGroup", "uri": { "path": "/new.rs" }, "isNew": true,
+                      "edits": [[{ "text": "a" }, { "text": "b" }]] },
+                    { "kind": "textEditGroup", "uri": { "path": "/old.rs" }, "state": "deleted", "edits": [] },
+                    { "kind": "toolInvocationSerialized", "pastTenseMessage": { "value": "Read lib.rs" } },
+                    { "kind": "toolInvocationSerialized" },
+                    { "kind": "somethingNew" }
+                ]
+            }, {
+                "message": { "text": "No metadata" },
+                "response": []
+            }, {
+                "message": { "text": "Bookkeeping" },
+                "response": [],
+                "isHidden": true
+            }, {
+                "message": { "text": "Counted" },
+                "response": [{ "value": "Done" }],
+                "result": { "metadata": { "usage": { "promptTokens": 12, "completionTokens": 3 } } }
+            }]
+        }"#;
+        let chat = parse_chat(json).unwrap();
+
+        let serialized = serde_json::to_string(&chat).unwrap();
+        let (reparsed, warnings) =
+            parse_chat_with_warnings(&serialized, &ParseOptions::default()).unwrap();
+
+        assert_eq!(reparsed, chat);
+        assert!(warnings.is_empty());
+        assert_eq!(reparsed.requests[0].context.len(), 6);
+        assert_eq!(reparsed.requests[0].response.len(), 8);
+    }
+
+    #[cfg(feature = "serde-serialize")]
+    #[test]
+    fn serializes_unknown_elements_lossily() {
+        let json = minimal_chat_json(&request_json("Hi", r#"{ "kind": "somethingNew", "n": 1 }"#));
+
+        let kept = parse_chat_with_options(
+            &json,
+            &ParseOptions {
+                keep_raw: true,
+                ..ParseOptions::default()
+            },
+        )
+        .unwrap();
+        let value = serde_json::to_value(&kept.requests[0].response[0]).unwrap();
+        assert_eq!(value, serde_json::json!({ "kind": "somethingNew", "n": 1 }));
+
+        let dropped = parse_chat(&json).unwrap();
+        let value = serde_json::to_value(&dropped.requests[0].response[0]).unwrap();
+        assert_eq!(value, serde_json::json!({}));
+    }
 }
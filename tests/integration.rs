@@ -2,11 +2,152 @@
 // Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
 
 //! Integration tests for cp2md parsing and rendering.
+//!
+//! Besides the tests below, every export in `tests/fixtures/` is rendered
+//! with each of [`option_sets`] and compared with the golden output in
+//! `tests/snapshots/render/`. After a change to the output, run the tests
+//! with `UPDATE_SNAPSHOTS=1` to rewrite the golden files, and review the
+//! difference along with the change.
+
+#![cfg(feature = "render")]
 
 use cp2md::{parser, renderer};
 use std::fs;
 use std::path::Path;
 
+/// Exports in `tests/fixtures/`, by file stem.
+const FIXTURES: &[&str] = &[
+    "agent-session",
+    "context-heavy",
+    "errors",
+    "latin1-terminal",
+    "simple-qa",
+    "split-fences",
+];
+
+/// Named option sets each fixture is rendered with.
+///
+/// A change to the renderer that is not covered should add to a set, or
+/// add a set, rather than test its output with substrings alone.
+fn option_sets() -> Vec<(&'static str, renderer::RenderOptions)> {
+    use renderer::{ContextPosition, FrontMatterFormat, PathStyle, RenderOptions};
+
+    vec![
+        ("default", RenderOptions::default()),
+        (
+            "all",
+            RenderOptions {
+                edit_summary: true,
+                show_activity_badges: true,
+                show_selection_text: true,
+                show_usage: true,
+                ..RenderOptions::all_metadata()
+            },
+        ),
+        ("minimal", RenderOptions::minimal()),
+        (
+            "layout",
+            RenderOptions {
+                front_matter: Some(FrontMatterFormat::Yaml),
+                heading_offset: 1,
+                quote_user: true,
+                metadata_as_alert: true,
+                context_position: ContextPosition::After,
+                context_index: true,
+                edits_as_section: true,
+                hoist_instructions: true,
+                path_style: PathStyle::Footnote,
+                reflow_paragraphs: true,
+                ..RenderOptions::default()
+            },
+        ),
+        (
+            "summary",
+            RenderOptions {
+                summary_only: true,
+                ..RenderOptions::default()
+            },
+        ),
+        (
+            "embedded",
+            RenderOptions {
+                document_heading: None,
+                edit_summary: true,
+                edits_as_section: true,
+                max_message_chars: Some(120),
+                ..RenderOptions::default()
+            },
+        ),
+    ]
+}
+
+/// Compares `actual` with the golden file at `path`, or with
+/// `UPDATE_SNAPSHOTS=1` set, writes `actual` there.
+///
+/// Returns a description of the first difference on a mismatch.
+fn check_snapshot(path: &Path, actual: &str) -> Result<(), String> {
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|value| value == "1") {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, actual)
+            .unwrap_or_else(|e| panic!("Failed to write {}: {e}", path.display()));
+        return Ok(());
+    }
+
+    let Ok(expected) = fs::read_to_string(path) else {
+        return Err(format!("{}: missing", path.display()));
+    };
+    if expected == actual {
+        return Ok(());
+    }
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for number in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(want), Some(got)) if want == got => {}
+            (want, got) => {
+                return Err(format!(
+                    "{}:{number}: expected {want:?}, got {got:?}",
+                    path.display()
+                ));
+            }
+        }
+    }
+    unreachable!()
+}
+
+/// Renders every fixture with every option set and checks the output
+/// against its golden file.
+#[test]
+fn renders_fixtures_as_their_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut failures = Vec::new();
+
+    for fixture in FIXTURES {
+        let json = fs::read(root.join("fixtures").join(format!("{fixture}.json")))
+            .unwrap_or_else(|e| panic!("Failed to read fixture {fixture}: {e}"));
+        let (chat, _) = parser::parse_chat_bytes(&json, &parser::ParseOptions::default())
+            .unwrap_or_else(|e| panic!("Failed to parse fixture {fixture}: {e}"));
+
+        for (name, opts) in option_sets() {
+            let markdown = renderer::render_chat(&chat, &opts);
+            let path = root
+                .join("snapshots")
+                .join("render")
+                .join(format!("{fixture}.{name}.md"));
+            if let Err(failure) = check_snapshot(&path, &markdown) {
+                failures.push(failure);
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} snapshots differ; rerun with UPDATE_SNAPSHOTS=1 to accept the new output:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
 /// Parses all JSON files in the chats directory and verifies they produce valid output.
 #[test]
 fn parses_all_sample_chats() {
@@ -44,6 +185,11 @@ fn parses_all_sample_chats() {
                 "Invalid markdown header in {}",
                 path.display()
             );
+            assert!(
+                !markdown.contains("\n\n\n"),
+                "More than one blank line between blocks in {}",
+                path.display()
+            );
         }
     }
 }
@@ -154,3 +300,180 @@ fn file_edit_summary_rendered() {
     );
     assert!(output.contains("3 lines"), "Should show line count");
 }
+
+/// Tests that an export with bytes that are not UTF-8 converts, with each
+/// invalid sequence replaced, unless decoding is strict.
+#[test]
+fn latin1_bytes_convert_with_replacement_characters() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/latin1-terminal.json");
+    let json = fs::read(path).unwrap();
+    assert!(std::str::from_utf8(&json).is_err());
+
+    let (chat, warnings) = parser::parse_chat_bytes(&json, &parser::ParseOptions::default())
+        .expect("Lossy decoding should succeed");
+    let offset = json.iter().position(|&byte| byte == 0xe9).unwrap();
+    assert_eq!(
+        warnings,
+        [parser::ParseWarning::ReplacedInvalidUtf8 { offset }]
+    );
+    let output = renderer::render_chat(&chat, &renderer::RenderOptions::default());
+    assert!(output.contains("could not read `r\u{fffd}sum\u{fffd}.toml`"));
+
+    let strict = parser::ParseOptions {
+        strict_utf8: true,
+        ..parser::ParseOptions::default()
+    };
+    let err = parser::parse_chat_bytes(&json, &strict).unwrap_err();
+    assert!(matches!(err, parser::ParseError::InvalidUtf8 { offset: at, .. } if at == offset));
+}
+
+/// Tests that Windows line endings in an export produce clean LF output.
+#[test]
+fn crlf_input_produces_normalized_output() {
+    let json = r###"{
+        "responderUsername": "GitHub Copilot",
+        "requests": [{
+            "timestamp": 1733356800000,
+            "message": { "text": "## Question\r\n\r\nWhy?   \r\n" },
+            "response": [
+                { "value": "Because.  \r\n\r\n```\r\ncode  \r\n```\r\n" }
+            ]
+        }]
+    }"###;
+
+    let chat = parser::parse_chat(json).unwrap();
+    let output = renderer::render_chat(&chat, &renderer::RenderOptions::default());
+
+    assert!(!output.contains('\r'), "Output should not contain CR");
+    assert!(output.contains("#### Question\n\nWhy?\n"));
+    assert!(
+        output.contains("```\ncode  \n```"),
+        "Code keeps trailing spaces"
+    );
+    assert!(output.ends_with("```\n"), "Document ends with one newline");
+
+    let crlf = renderer::convert_line_endings(&output, renderer::LineEnding::Crlf);
+    assert_eq!(crlf.matches("\r\n").count(), output.matches('\n').count());
+}
+
+/// Tests that attached context is rendered from both the current and the
+/// older request layout.
+#[test]
+fn renders_context_from_each_layout() {
+    let fixtures = [
+        (
+            "variableData",
+            r#""variableData": { "variables": [
+                { "kind": "file", "name": "file:main.rs", "value": { "uri": { "path": "/src/main.rs" } } }
+            ] }"#,
+        ),
+        (
+            "top-level variables",
+            r#""variables": [
+                { "kind": "file", "name": "file:main.rs", "value": "file:///src/main.rs" }
+            ]"#,
+        ),
+    ];
+
+    for (layout, variables) in fixtures {
+        let json = format!(
+            r#"{{
+                "responderUsername": "GitHub Copilot",
+                "requests": [{{
+                    "message": {{ "text": "What does this do?" }},
+                    "response": [{{ "value": "It starts the app." }}],
+                    {variables}
+                }}]
+            }}"#
+        );
+
+        let chat = parser::parse_chat(&json).unwrap();
+        let output = renderer::render_chat(&chat, &renderer::RenderOptions::default());
+
+        assert!(
+            output.contains("- `main.rs` (file)"),
+            "{layout} context not rendered: {output}"
+        );
+    }
+}
+
+/// Tests that every shape of response older exports use is rendered.
+#[test]
+fn renders_each_response_shape() {
+    let fixtures = [
+        ("array", r#"[{ "value": "Use a loop." }]"#),
+        ("object", r#"{ "value": "Use a loop." }"#),
+        ("string", r#""Use a loop.""#),
+    ];
+
+    for (shape, response) in fixtures {
+        let json = format!(
+            r#"{{
+                "responderUsername": "GitHub Copilot",
+                "requests": [{{
+                    "timestamp": 1733356800000,
+                    "message": {{ "text": "How do I repeat this?" }},
+                    "response": {response}
+                }}]
+            }}"#
+        );
+
+        let chat = parser::parse_chat(&json).unwrap();
+        let output = renderer::render_chat(&chat, &renderer::RenderOptions::default());
+
+        assert!(
+            output.ends_with("## Assistant\n\nUse a loop.\n"),
+            "{shape} response not rendered: {output}"
+        );
+    }
+}
+
+/// Tests that mixed responses never leave more than one blank line between blocks.
+#[test]
+fn no_runs_of_blank_lines_between_blocks() {
+    let json = r#"{
+        "responderUsername": "GitHub Copilot",
+        "requests": [
+            {
+                "timestamp": 1733356800000,
+                "message": { "text": "Look around\n\n\n\nplease" },
+                "response": [
+                    { "kind": "toolInvocationSerialized" },
+                    { "kind": "unknownThing" }
+                ]
+            },
+            {
+                "timestamp": 1733356860000,
+                "message": { "text": "Now edit it" },
+                "response": [
+                    { "value": "Sure.\n\n\n" },
+                    {
+                        "kind": "toolInvocationSerialized",
+                        "pastTenseMessage": { "value": "Read main.rs" }
+                    },
+                    {
+                        "kind": "textEditGroup",
+                        "uri": { "path": "/src/main.rs" },
+                        "edits": [[{ "text": "fn main() {}" }]]
+                    },
+                    { "value": "\n\n\nDone." }
+                ]
+            }
+        ]
+    }"#;
+
+    let chat = parser::parse_chat(json).unwrap();
+
+    for show_tools in [false, true] {
+        let opts = renderer::RenderOptions {
+            show_tools,
+            ..Default::default()
+        };
+        let output = renderer::render_chat(&chat, &opts);
+
+        assert!(
+            !output.contains("\n\n\n"),
+            "blank line run with show_tools={show_tools}: {output}"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Parser tests that build without any optional features.
+//!
+//! Run with `cargo test --no-default-features` to check that the
+//! parser-only configuration compiles and parses real exports.
+
+use cp2md::parser;
+use std::fs;
+use std::path::Path;
+
+/// A small export exercising every response element kind.
+const FIXTURE: &str = r#"{
+    "responderUsername": "GitHub Copilot",
+    "requests": [{
+        "timestamp": 1733356800000,
+        "modelId": "claude-sonnet-4",
+        "message": { "text": "Rename foo" },
+        "response": [
+            { "value": "Renaming " },
+            { "kind": "inlineReference", "inlineReference": { "path": "/src/lib.rs" } },
+            { "kind": "codeblockUri", "uri": { "path": "/src/lib.rs" } },
+            { "kind": "textEditGroup", "uri": { "path": "/src/lib.rs" }, "edits": [[{ "text": "fn bar() {}" }]] },
+            { "kind": "toolInvocationSerialized", "pastTenseMessage": { "value": "Read lib.rs" } },
+            { "kind": "markdownVuln", "content": { "value": "eval(name)" }, "vulnerabilities": [{ "title": "Code injection", "description": "Input reaches eval." }] },
+            { "kind": "somethingNew" }
+        ],
+        "result": {
+            "errorDetails": { "message": "The response was filtered.", "responseIsFiltered": true }
+        }
+    }]
+}"#;
+
+#[test]
+fn parses_fixture_without_renderer() {
+    let chat = parser::parse_chat(FIXTURE).unwrap();
+
+    assert_eq!(chat.requests.len(), 1);
+    assert_eq!(chat.requests[0].response.len(), 9);
+}
+
+/// Parses every sample chat, if the samples directory is present.
+#[test]
+fn parses_all_sample_chats_without_renderer() {
+    let chats_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("chats");
+    let Ok(entries) = fs::read_dir(&chats_dir) else {
+        return;
+    };
+
+    for entry in entries {
+        let path = entry.expect("Failed to read directory entry").path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let json = fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+            parser::parse_chat(&json)
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+        }
+    }
+}
+
+/// Returns the in-tree fixtures and any sample chats, as parsed JSON.
+fn fixture_chats() -> Vec<(String, serde_json::Value)> {
+    let mut chats = vec![("FIXTURE".to_owned(), serde_json::from_str(FIXTURE).unwrap())];
+    let chats_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("chats");
+    for entry in fs::read_dir(&chats_dir).into_iter().flatten() {
+        let path = entry.expect("Failed to read directory entry").path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let json = fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+            let value = serde_json::from_str(&json)
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+            chats.push((path.display().to_string(), value));
+        }
+    }
+    chats
+}
+
+/// Checks the fixtures against the published export schema.
+#[test]
+fn fixtures_match_export_schema() {
+    let schema: serde_json::Value = serde_json::from_str(parser::EXPORT_SCHEMA).unwrap();
+    let validator = jsonschema::validator_for(&schema).expect("EXPORT_SCHEMA is a valid schema");
+
+    for (name, chat) in fixture_chats() {
+        let errors: Vec<_> = validator
+            .iter_errors(&chat)
+            .map(|e| e.to_string())
+            .collect();
+        assert!(
+            errors.is_empty(),
+            "{name} does not match the schema: {errors:?}"
+        );
+    }
+
+    let object_response = serde_json::json!({
+        "responderUsername": "GitHub Copilot",
+        "requests": [{ "response": { "value": "Hi" } }, { "response": "Hi" }]
+    });
+    assert!(validator.is_valid(&object_response));
+
+    let missing_responder = serde_json::json!({ "requests": [] });
+    assert!(!validator.is_valid(&missing_responder));
+    let numeric_message = serde_json::json!({
+        "responderUsername": "GitHub Copilot",
+        "requests": [{ "message": { "text": 1 } }]
+    });
+    assert!(!validator.is_valid(&numeric_message));
+}
+
+/// Checks that the fixtures read back the same after being written out.
+#[cfg(feature = "serde-serialize")]
+#[test]
+fn fixtures_round_trip_through_serialize() {
+    for (name, json) in fixture_chats() {
+        let chat = parser::parse_chat(&json.to_string()).unwrap();
+        let serialized = serde_json::to_string(&chat).unwrap();
+        let reparsed = parser::parse_chat(&serialized)
+            .unwrap_or_else(|e| panic!("{name} does not read back: {e}"));
+        assert_eq!(reparsed, chat, "{name} changed in the round trip");
+    }
+}
@@ -0,0 +1,294 @@
+// SPDX-License-Identifier: GPL-3.0-only
+// Copyright (C) 2025 Brian Hetro <whee@smaertness.net>
+
+//! Property tests rendering arbitrary chats.
+//!
+//! Message and response text is drawn from Markdown-heavy alphabets, with
+//! headings, fences, tags, and multi-byte characters, as well as from any
+//! string at all. Rendering must not panic, and output must stay within a
+//! constant factor of the input.
+
+#![cfg(feature = "render")]
+
+use cp2md::parser::{
+    ChatExport, ContextItem, EditKind, Message, Request, ResponseElement, Severity, Usage,
+};
+use cp2md::renderer::{
+    CodeIndent, ContextPosition, DeepHeadingStyle, RenderOptions, RepoInfo, SelectionExcerpts,
+    render_chat, render_exchange, render_summary,
+};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+
+/// Text that is mostly Markdown syntax, or any string at all.
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        3 => "([#`~<>/!=\"' \n\t*_|\\-]|\u{a0}|é|🔧|[a-z0-9]){0,120}",
+        1 => any::<String>(),
+    ]
+}
+
+fn element() -> impl Strategy<Value = ResponseElement> {
+    prop_oneof![
+        4 => text().prop_map(ResponseElement::Text),
+        1 => (proptest::option::of(text()), text())
+            .prop_map(|(name, path)| ResponseElement::InlineReference { name, path }),
+        1 => text().prop_map(|path| ResponseElement::CodeBlockUri { path }),
+        1 => (text(), vec(text(), 0..3), 0..3_u8).prop_map(|(path, edits, kind)| {
+            let kind = match kind {
+                0 => EditKind::Create,
+                1 => EditKind::Modify,
+                _ => EditKind::Delete,
+            };
+            ResponseElement::TextEditGroup { path, edits, kind }
+        }),
+        1 => proptest::option::of(text())
+            .prop_map(|past_tense| ResponseElement::ToolInvocation { past_tense }),
+        1 => (any::<bool>(), text()).prop_map(|(filtered, message)| {
+            let severity = if filtered {
+                Severity::Filtered
+            } else {
+                Severity::Warning
+            };
+            ResponseElement::Annotation { severity, message }
+        }),
+        1 => Just(ResponseElement::Other { raw: None }),
+    ]
+}
+
+fn context_item() -> impl Strategy<Value = ContextItem> {
+    prop_oneof![
+        (text(), text()).prop_map(|(name, path)| ContextItem::File { name, path }),
+        (
+            text(),
+            text(),
+            any::<u32>(),
+            any::<u32>(),
+            proptest::option::of(text())
+        )
+            .prop_map(
+                |(name, path, start_line, end_line, text)| ContextItem::Selection {
+                    name,
+                    path,
+                    start_line,
+                    end_line,
+                    text,
+                }
+            ),
+        (text(), text()).prop_map(|(name, path)| ContextItem::Folder { name, path }),
+        text().prop_map(|name| ContextItem::Instructions { name }),
+        text().prop_map(|text| ContextItem::PastedText { text }),
+        text().prop_map(|text| ContextItem::TerminalOutput { text }),
+    ]
+}
+
+fn request() -> impl Strategy<Value = Request> {
+    (
+        any::<i64>(),
+        proptest::option::of(text()),
+        proptest::option::of(text()),
+        vec(context_item(), 0..3),
+        text(),
+        vec(element(), 0..6),
+        any::<bool>(),
+        proptest::option::of(any::<(u32, u32)>()),
+    )
+        .prop_map(
+            |(timestamp, model_id, agent_name, context, message, response, hidden, usage)| {
+                Request {
+                    timestamp,
+                    model_id,
+                    agent_name,
+                    context,
+                    message: Message { text: message },
+                    response,
+                    hidden,
+                    usage: usage.map(|(prompt, completion)| Usage { prompt, completion }),
+                }
+            },
+        )
+}
+
+fn chat() -> impl Strategy<Value = ChatExport> {
+    (
+        text(),
+        proptest::option::of(text()),
+        proptest::option::of(text()),
+        vec(request(), 0..4),
+    )
+        .prop_map(
+            |(responder_username, custom_title, default_model, requests)| ChatExport {
+                responder_username,
+                requester_username: None,
+                custom_title,
+                default_model,
+                requests,
+            },
+        )
+}
+
+#[allow(clippy::too_many_lines)]
+fn options() -> impl Strategy<Value = RenderOptions> {
+    (
+        any::<bool>(),
+        0..=5_u8,
+        any::<bool>(),
+        proptest::option::of(1..=120_usize),
+        proptest::option::of(0..=40_usize),
+        proptest::option::of(1..=200_usize),
+        proptest::option::of(1..=6_u8),
+        any::<[bool; 19]>(),
+        prop_oneof![
+            Just(CodeIndent::Keep),
+            (1..=8_usize).prop_map(CodeIndent::Tabs),
+            (1..=8_usize).prop_map(CodeIndent::Spaces),
+        ],
+    )
+        .prop_map(
+            |(
+                all,
+                heading_offset,
+                bold,
+                wrap,
+                max_len,
+                max_chars,
+                document_heading,
+                flags,
+                code_indent,
+            )| {
+                let base = if all {
+                    RenderOptions::all_metadata()
+                } else {
+                    RenderOptions::default()
+                };
+                let [
+                    quote,
+                    edits,
+                    badges,
+                    dedupe,
+                    hoist,
+                    hidden,
+                    title,
+                    normalize,
+                    summary,
+                    friendly,
+                    annotations,
+                    images,
+                    permalinks,
+                    excerpts,
+                    alert,
+                    context_after,
+                    context_index,
+                    usage,
+                    reflow,
+                ] = flags;
+                RenderOptions {
+                    heading_offset,
+                    document_heading,
+                    deep_heading_style: if bold {
+                        DeepHeadingStyle::Bold
+                    } else {
+                        DeepHeadingStyle::Clamp
+                    },
+                    wrap,
+                    code_indent,
+                    max_tool_message_len: max_len,
+                    max_message_chars: max_chars,
+                    quote_user: quote,
+                    edit_summary: edits,
+                    edits_as_section: edits,
+                    show_activity_badges: badges,
+                    dedupe_context: dedupe,
+                    dedupe_metadata: dedupe,
+                    hoist_instructions: hoist,
+                    show_hidden: hidden,
+                    title_from_first_message: title,
+                    normalize_blank_lines: normalize,
+                    summary_only: summary,
+                    friendly_model_names: friendly,
+                    show_annotations: annotations,
+                    embed_images: images,
+                    repo_permalink: permalinks.then(|| RepoInfo {
+                        url: "https://github.com/owner/repo".to_owned(),
+                        git_ref: "main".to_owned(),
+                    }),
+                    // Every selection is unavailable, as when files are gone
+                    selection_excerpts: excerpts.then(|| SelectionExcerpts {
+                        max_lines: 3,
+                        excerpts: BTreeMap::new(),
+                    }),
+                    metadata_as_alert: alert,
+                    context_position: if context_after {
+                        ContextPosition::After
+                    } else {
+                        ContextPosition::Before
+                    },
+                    context_index,
+                    show_usage: usage,
+                    reflow_paragraphs: reflow,
+                    show_selection_text: true,
+                    ..base
+                }
+            },
+        )
+}
+
+/// Total length of the text in a chat, counting each piece once.
+fn input_len(chat: &ChatExport) -> usize {
+    let context_len = |item: &ContextItem| match item {
+        ContextItem::File { name, path } | ContextItem::Folder { name, path } => {
+            name.len() + path.len()
+        }
+        ContextItem::Selection {
+            name, path, text, ..
+        } => name.len() + path.len() + text.as_ref().map_or(0, String::len),
+        ContextItem::Instructions { name } => name.len(),
+        ContextItem::PastedText { text } | ContextItem::TerminalOutput { text } => text.len(),
+    };
+    let element_len = |elem: &ResponseElement| match elem {
+        ResponseElement::Text(text) => text.len(),
+        ResponseElement::InlineReference { name, path } => {
+            name.as_ref().map_or(0, String::len) + path.len()
+        }
+        ResponseElement::CodeBlockUri { path } => path.len(),
+        ResponseElement::TextEditGroup { path, edits, .. } => {
+            path.len() + edits.iter().map(String::len).sum::<usize>()
+        }
+        ResponseElement::ToolInvocation { past_tense } => {
+            past_tense.as_ref().map_or(0, String::len)
+        }
+        ResponseElement::Annotation { message, .. } => message.len(),
+        ResponseElement::Other { .. } => 0,
+    };
+    let request_len = |req: &Request| {
+        req.message.text.len()
+            + req.model_id.as_ref().map_or(0, String::len)
+            + req.agent_name.as_ref().map_or(0, String::len)
+            + req.context.iter().map(context_len).sum::<usize>()
+            + req.response.iter().map(element_len).sum::<usize>()
+    };
+    chat.responder_username.len()
+        + chat.custom_title.as_ref().map_or(0, String::len)
+        + chat.default_model.as_ref().map_or(0, String::len)
+        + chat.requests.iter().map(request_len).sum::<usize>()
+}
+
+proptest! {
+    #[test]
+    fn renders_any_chat_in_bounded_output(chat in chat(), opts in options()) {
+        let markdown = render_chat(&chat, &opts);
+        let bound = 64 * input_len(&chat) + 2048 * (chat.requests.len() + 1);
+        prop_assert!(
+            markdown.len() <= bound,
+            "{} bytes of output for a bound of {bound}",
+            markdown.len()
+        );
+        prop_assert!(markdown.ends_with('\n'));
+
+        prop_assert!(render_summary(&chat, &opts).ends_with('\n'));
+        for request in &chat.requests {
+            prop_assert!(render_exchange(request, &opts).len() <= bound);
+        }
+    }
+}